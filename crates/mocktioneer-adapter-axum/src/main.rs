@@ -1,6 +1,21 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use mocktioneer_core::latency::{self, Timer};
 use mocktioneer_core::MocktioneerApp;
 
+/// Sleeps on the tokio runtime the axum adapter serves from.
+struct TokioTimer;
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
 fn main() {
+    latency::install_timer(Box::new(TokioTimer));
     if let Err(err) =
         edgezero_adapter_axum::run_app::<MocktioneerApp>(include_str!("../../../edgezero.toml"))
     {
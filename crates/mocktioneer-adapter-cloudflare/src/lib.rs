@@ -1,12 +1,29 @@
 #![cfg_attr(target_arch = "wasm32", no_main)]
 
+#[cfg(target_arch = "wasm32")]
+use std::{future::Future, pin::Pin, time::Duration};
+
+#[cfg(target_arch = "wasm32")]
+use mocktioneer_core::latency::{self, Timer};
 #[cfg(target_arch = "wasm32")]
 use mocktioneer_core::MocktioneerApp;
 #[cfg(target_arch = "wasm32")]
 use worker::*;
 
+/// Sleeps on the Workers runtime's `setTimeout`.
+#[cfg(target_arch = "wasm32")]
+struct WorkerTimer;
+
+#[cfg(target_arch = "wasm32")]
+impl Timer for WorkerTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(Delay::from(duration))
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 #[event(fetch)]
 pub async fn main(req: Request, env: Env, ctx: Context) -> Result<Response> {
+    latency::install_timer(Box::new(WorkerTimer));
     edgezero_adapter_cloudflare::run_app::<MocktioneerApp>(req, env, ctx).await
 }
@@ -3,10 +3,15 @@
 #[cfg(target_arch = "wasm32")]
 use fastly::{Error, Request, Response};
 #[cfg(target_arch = "wasm32")]
+use mocktioneer_core::latency::{self, BlockingTimer};
+#[cfg(target_arch = "wasm32")]
 use mocktioneer_core::MocktioneerApp;
 #[cfg(target_arch = "wasm32")]
 #[fastly::main]
 pub fn main(req: Request) -> Result<Response, Error> {
+    // Compute has no async timer, and each instance serves one request, so
+    // simulated latency simply blocks.
+    latency::install_timer(Box::new(BlockingTimer));
     edgezero_adapter_fastly::run_app::<MocktioneerApp>(include_str!("../../../edgezero.toml"), req)
 }
 
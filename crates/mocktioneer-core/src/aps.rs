@@ -49,6 +49,85 @@ pub struct ApsSlot {
     pub slot_name: Option<String>,
 }
 
+/// Slot as encoded by `apstag` in the `slots` query parameter, e.g.
+/// `[{"sd":"div-1","s":["300x250"],"sn":"header"}]`. Long field names are accepted too.
+#[derive(Debug, Deserialize)]
+struct ApstagSlot {
+    #[serde(alias = "slotID")]
+    sd: String,
+    #[serde(alias = "sizes")]
+    s: Vec<ApstagSize>,
+    #[serde(alias = "slotName", default)]
+    sn: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ApstagSize {
+    /// `"300x250"`
+    Text(String),
+    /// `[300, 250]`
+    Pair([u32; 2]),
+}
+
+impl ApstagSize {
+    fn dimensions(&self) -> Option<[u32; 2]> {
+        match self {
+            ApstagSize::Pair(pair) => Some(*pair),
+            ApstagSize::Text(text) => {
+                let (w, h) = text.split_once('x')?;
+                Some([w.trim().parse().ok()?, h.trim().parse().ok()?])
+            }
+        }
+    }
+}
+
+impl ApsBidRequest {
+    /// Parse the URL-encoded form `apstag` sends (query string or form body):
+    /// `src` (pubId), `u` (page URL), `t` (timeout) and a JSON `slots` array. The
+    /// JSON body field names (`pubId`, `pageUrl`, `ua`, `timeout`) are accepted as
+    /// well. Returns `None` when `pubId` or `slots` is missing or malformed.
+    pub fn from_urlencoded(input: &[u8]) -> Option<Self> {
+        let mut pub_id = None;
+        let mut slots = None;
+        let mut page_url = None;
+        let mut user_agent = None;
+        let mut timeout = None;
+        for (key, value) in url::form_urlencoded::parse(input) {
+            match key.as_ref() {
+                "src" | "pubId" | "pubid" => pub_id = Some(value.into_owned()),
+                "slots" => slots = Some(value.into_owned()),
+                "u" | "pageUrl" => page_url = Some(value.into_owned()),
+                "ua" => user_agent = Some(value.into_owned()),
+                "t" | "timeout" => timeout = value.parse().ok(),
+                _ => {}
+            }
+        }
+        let slots: Vec<ApstagSlot> = serde_json::from_str(&slots?).ok()?;
+        let slots = slots
+            .into_iter()
+            .map(|slot| {
+                Some(ApsSlot {
+                    slot_id: slot.sd,
+                    sizes: slot
+                        .s
+                        .iter()
+                        .map(ApstagSize::dimensions)
+                        .collect::<Option<_>>()?,
+                    slot_name: slot.sn,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(ApsBidRequest {
+            pub_id: pub_id?,
+            slots,
+            page_url,
+            user_agent,
+            timeout,
+        })
+    }
+}
+
 // ============================================================================
 // APS TAM API Response Types (Real Amazon Format)
 // ============================================================================
@@ -150,4 +229,44 @@ pub struct ApsSlotResponse {
     /// Amazon auction context type ("OPEN", "PRIVATE", etc.)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub amznactt: Option<String>,
+
+    /// Currency of the encoded price (mock extension, only set when `[aps] currency` is configured)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amzncur: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_urlencoded_parses_apstag_query() {
+        let query = "src=5555&u=https%3A%2F%2Fexample.com%2F&t=800&slots=%5B%7B%22sd%22%3A%22div-1%22%2C%22s%22%3A%5B%22300x250%22%2C%22728x90%22%5D%2C%22sn%22%3A%22header%22%7D%5D";
+        let req = ApsBidRequest::from_urlencoded(query.as_bytes()).expect("parses");
+        assert_eq!(req.pub_id, "5555");
+        assert_eq!(req.page_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(req.timeout, Some(800));
+        assert_eq!(req.slots.len(), 1);
+        assert_eq!(req.slots[0].slot_id, "div-1");
+        assert_eq!(req.slots[0].sizes, vec![[300, 250], [728, 90]]);
+        assert_eq!(req.slots[0].slot_name.as_deref(), Some("header"));
+    }
+
+    #[test]
+    fn from_urlencoded_accepts_long_names_and_pairs() {
+        let query = r#"pubId=1&slots=[{"slotID":"a","sizes":[[320,50]]}]"#;
+        let req = ApsBidRequest::from_urlencoded(query.as_bytes()).expect("parses");
+        assert_eq!(req.slots[0].sizes, vec![[320, 50]]);
+        assert!(req.slots[0].slot_name.is_none());
+    }
+
+    #[test]
+    fn from_urlencoded_rejects_incomplete_input() {
+        assert!(ApsBidRequest::from_urlencoded(b"slots=[]").is_none());
+        assert!(ApsBidRequest::from_urlencoded(b"src=1").is_none());
+        assert!(
+            ApsBidRequest::from_urlencoded(br#"src=1&slots=[{"sd":"a","s":["big"]}]"#).is_none()
+        );
+        assert!(ApsBidRequest::from_urlencoded(b"{\"pubId\":").is_none());
+    }
 }
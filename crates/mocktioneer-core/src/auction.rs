@@ -1,14 +1,41 @@
+use std::collections::{HashMap, HashSet};
+
 use crate::aps::{ApsBidRequest, ApsBidResponse, ApsContextual, ApsSlotResponse};
+use crate::clock;
+use crate::config::{
+    AppConfig, AuctionConfig, CreativeDelivery, FillRuleConfig, PriceRounding, SeatConfig,
+    SiteAction, SiteRuleConfig,
+};
+use crate::debug::{Feature, FeatureLog};
+use crate::latency::XorShift64;
+use crate::mediation::second_price;
 use crate::openrtb::{
-    Bid as OpenrtbBid, Imp as OpenrtbImp, MediaType, OpenRTBRequest, OpenRTBResponse, SeatBid,
+    request_warnings, Bid as OpenrtbBid, Deal, Format, Imp as OpenrtbImp, MediaType,
+    OpenRTBRequest, OpenRTBResponse, SeatBid, Video,
+};
+use crate::prebid::attach_bid_meta;
+use crate::render::{
+    iframe_html, light_adm, mraid_adm, native_adm, script_adm, vast_protocol, vast_url,
+    vast_version, vast_xml, AdmHints, CreativeMetadata, SignatureStatus,
 };
-use crate::render::{iframe_html, CreativeMetadata, SignatureStatus};
 use phf::phf_map;
+use serde::Serialize;
 use serde_json::json;
-use uuid::Uuid;
+
+/// Standard size closest to `w`x`h` by Euclidean distance between dimensions;
+/// ties go to the smaller size. A standard size is its own nearest size, and
+/// an empty `standard_sizes` list falls back to 300x250.
+pub fn nearest_standard_size(w: i64, h: i64, cfg: &AuctionConfig) -> (i64, i64) {
+    // In i128, saturating, so arbitrary request sizes cannot overflow
+    let dist = |a: i64, b: i64| (i128::from(a) - i128::from(b)).saturating_pow(2);
+    standard_sizes(cfg)
+        .min_by_key(|&(sw, sh)| dist(sw, w).saturating_add(dist(sh, h)))
+        .unwrap_or((300, 250))
+}
 
 // ============================================================================
-// Standard Ad Sizes - single source of truth for supported sizes and pricing
+// Standard Ad Sizes - `[auction] standard_sizes` decides which sizes are served;
+// SIZE_MAP prices the built-in ones and is the default list
 // ============================================================================
 
 /// Default CPM for non-standard sizes (base price before area adjustment).
@@ -18,7 +45,7 @@ pub const DEFAULT_CPM: f64 = 1.50;
 /// Final CPM = DEFAULT_CPM + min(area/100000, MAX_AREA_BONUS)
 pub const MAX_AREA_BONUS: f64 = 3.00;
 
-/// Compile-time perfect hash map for standard sizes: "WxH" -> cpm.
+/// Compile-time perfect hash map for the built-in standard sizes: "WxH" -> cpm.
 /// Zero runtime initialization cost.
 static SIZE_MAP: phf::Map<&'static str, f64> = phf_map! {
     // Desktop & General Display Sizes
@@ -44,9 +71,13 @@ fn size_key(w: i64, h: i64) -> String {
     format!("{}x{}", w, h)
 }
 
-/// Check if dimensions match a standard ad size.
-pub fn is_standard_size(w: i64, h: i64) -> bool {
-    SIZE_MAP.contains_key(size_key(w, h).as_str())
+/// 1x1 slot used as a marker for outstream and fluid placements. Bid and
+/// served as-is even though it isn't a standard size.
+pub const MARKER_SIZE: (i64, i64) = (1, 1);
+
+/// Whether a display bid may carry `w`x`h`: a standard size or [`MARKER_SIZE`].
+pub fn is_bid_size(w: i64, h: i64, cfg: &AuctionConfig) -> bool {
+    (w, h) == MARKER_SIZE || cfg.is_standard_size(w, h)
 }
 
 /// Get CPM for a size. Returns configured CPM for standard sizes, area-based fallback otherwise.
@@ -56,14 +87,24 @@ pub fn get_cpm(w: i64, h: i64) -> f64 {
         .copied()
         .unwrap_or_else(|| {
             // Fallback: area-based pricing for non-standard sizes
-            let area = (w * h) as f64;
+            // f64 math, so huge sizes cannot overflow
+            let area = w as f64 * h as f64;
             ((DEFAULT_CPM + (area / 100000.0).min(MAX_AREA_BONUS)) * 100.0).round() / 100.0
         })
 }
 
-/// Returns an iterator over all standard ad sizes as (width, height) tuples.
-/// Useful for generating test fixtures or validating external configurations.
-pub fn standard_sizes() -> impl Iterator<Item = (i64, i64)> {
+/// Returns an iterator over all standard ad sizes from `[auction] standard_sizes`
+/// as sorted, distinct (width, height) tuples.
+pub fn standard_sizes(cfg: &AuctionConfig) -> impl Iterator<Item = (i64, i64)> {
+    let mut sizes: Vec<(i64, i64)> = cfg.standard_sizes.iter().map(|&[w, h]| (w, h)).collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    sizes.into_iter()
+}
+
+/// Sizes with a configured CPM in SIZE_MAP, sorted; the default
+/// `[auction] standard_sizes`.
+pub fn priced_sizes() -> impl Iterator<Item = (i64, i64)> {
     let mut sizes: Vec<(i64, i64)> = SIZE_MAP
         .keys()
         .filter_map(|key| {
@@ -82,364 +123,3664 @@ pub fn standard_sizes() -> impl Iterator<Item = (i64, i64)> {
     sizes.into_iter()
 }
 
+// ============================================================================
+// Currency - prices are computed in USD and converted with a static rate table
+// ============================================================================
+
+/// Static exchange rates as units of currency per 1 USD.
+/// Deterministic on purpose: tests can assert exact converted prices.
+static USD_RATES: phf::Map<&'static str, f64> = phf_map! {
+    "USD" => 1.0,
+    "EUR" => 0.92,
+    "GBP" => 0.79,
+    "JPY" => 150.0,
+};
+
+/// Convert `amount` between two currencies via the static USD rate table.
+/// Returns `None` if either currency is missing from the table.
+pub fn convert_price(amount: f64, from: &str, to: &str) -> Option<f64> {
+    let from_rate = USD_RATES.get(from)?;
+    let to_rate = USD_RATES.get(to)?;
+    Some(amount / from_rate * to_rate)
+}
+
+/// Currency of `imp.bidfloor`: explicit `imp.bidfloorcur`, then the configured
+/// `[auction] default_floor_cur`, then the request's first `cur`, then USD.
+pub fn floor_currency<'a>(
+    imp: &'a OpenrtbImp,
+    req: &'a OpenRTBRequest,
+    cfg: &'a AuctionConfig,
+) -> &'a str {
+    imp.bidfloorcur
+        .as_deref()
+        .or(cfg.default_floor_cur.as_deref())
+        .or_else(|| req.cur.as_ref().and_then(|c| c.first()).map(String::as_str))
+        .unwrap_or("USD")
+}
+
+/// `imp.bidfloor` converted to USD so it can be compared against our prices.
+/// Returns `None` when the imp has no positive floor or its currency is unknown.
+pub fn floor_in_usd(imp: &OpenrtbImp, req: &OpenRTBRequest, cfg: &AuctionConfig) -> Option<f64> {
+    let floor = imp.bidfloor.filter(|f| *f > 0.0)?;
+    let cur = floor_currency(imp, req, cfg);
+    let usd = convert_price(floor, cur, "USD");
+    if usd.is_none() {
+        log::warn!("Unknown floor currency '{}' on imp '{}'", cur, imp.id);
+    }
+    usd
+}
+
+/// `deal.bidfloor` in USD, rounded up to the cent so a bid at this price never
+/// sits under the deal floor. The deal's currency defaults to USD per OpenRTB.
+pub fn deal_floor_in_usd(deal: &Deal) -> Option<f64> {
+    let floor = deal.bidfloor.filter(|f| *f > 0.0)?;
+    let cur = deal.bidfloorcur.as_deref().unwrap_or("USD");
+    let usd = convert_price(floor, cur, "USD");
+    if usd.is_none() {
+        log::warn!("Unknown floor currency '{}' on deal '{}'", cur, deal.id);
+    }
+    usd.map(|usd| (usd * 100.0).ceil() / 100.0)
+}
+
+/// No-bid reason sent when every imp was skipped for its floor. OpenRTB has no
+/// standard code for this, so it uses the vendor-specific range (500+).
+pub const NBR_BELOW_FLOOR: i64 = 500;
+
+/// No-bid reason sent when every imp opted out via `imp.ext.mocktioneer.no_bid`
+/// lost its `[[fill_rule]]` draw, or was blocked by `badv`/`bcat` (OpenRTB
+/// "Unknown Error", the generic code).
+pub const NBR_NO_BID: i64 = 0;
+
+/// No-bid reason sent when every imp was skipped and at least one asked for media
+/// we can't serve (vendor-specific, like [`NBR_BELOW_FLOOR`]).
+pub const NBR_UNSUPPORTED_MEDIA: i64 = 501;
+
+/// No-bid reason sent when `[limits] max_response_bytes` left room for no bid at all.
+pub const NBR_RESPONSE_TOO_LARGE: i64 = 502;
+
+/// No-bid reason sent when `wseat`/`bseat` excluded every configured seat
+/// (vendor-specific, like [`NBR_BELOW_FLOOR`]).
+pub const NBR_SEAT_BLOCKED: i64 = 503;
+
+/// No-bid reason sent when imps in a private auction (`pmp.private_auction = 1`)
+/// had no deal open to any seat (vendor-specific, like [`NBR_BELOW_FLOOR`]).
+pub const NBR_NO_DEAL: i64 = 504;
+
+/// Drop bids, the last seatbid's last bid first, until `resp` serializes to at
+/// most `max_bytes`. With `note`, a truncated response records the dropped bid
+/// count in `ext.mocktioneer.truncated`. Returns the number of dropped bids.
+pub fn truncate_to_fit(resp: &mut OpenRTBResponse, max_bytes: usize, note: bool) -> usize {
+    if serialized_len(resp) <= max_bytes {
+        return 0;
+    }
+    let total: usize = resp.seatbid.iter().map(|sb| sb.bid.len()).sum();
+    // Binary search for the most bids that fit; each probe serializes once
+    let (mut fits, mut too_many) = (0, total);
+    while too_many - fits > 1 {
+        let mid = (fits + too_many) / 2;
+        if serialized_len(&keep_bids(resp, mid, max_bytes, note)) <= max_bytes {
+            fits = mid;
+        } else {
+            too_many = mid;
+        }
+    }
+    *resp = keep_bids(resp, fits, max_bytes, note);
+    total - fits
+}
+
+/// Copy of `resp` keeping only its first `keep` bids, annotated as
+/// [`truncate_to_fit`] would leave it.
+fn keep_bids(resp: &OpenRTBResponse, keep: usize, max_bytes: usize, note: bool) -> OpenRTBResponse {
+    let total: usize = resp.seatbid.iter().map(|sb| sb.bid.len()).sum();
+    let mut kept = resp.clone();
+    let mut left = keep;
+    kept.seatbid.retain_mut(|seatbid| {
+        seatbid.bid.truncate(left);
+        left -= seatbid.bid.len();
+        !seatbid.bid.is_empty()
+    });
+    if kept.seatbid.is_empty() && kept.nbr.is_none() {
+        kept.nbr = Some(NBR_RESPONSE_TOO_LARGE);
+    }
+    if note {
+        let ext = kept.ext.get_or_insert_with(|| json!({}));
+        ext["mocktioneer"]["truncated"] =
+            json!({"dropped_bids": total - keep, "max_response_bytes": max_bytes});
+    }
+    kept
+}
+
+/// Size of `resp` as sent on the wire.
+pub fn serialized_len(resp: &OpenRTBResponse) -> usize {
+    serde_json::to_vec(resp).map_or(0, |bytes| bytes.len())
+}
+
+/// Why an imp gets no bid whatever its price, reported per imp in the response
+/// `ext.mocktioneer.skipped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The imp set `imp.ext.mocktioneer.no_bid`.
+    NoBid,
+    /// The imp only offers media we can't serve (audio).
+    UnsupportedMedia,
+    /// The imp lost the `[[fill_rule]]` draw for its size.
+    NoFill,
+    /// The request's `badv` or `bcat` blocks our advertiser domain or category.
+    Blocked,
+}
+
+/// Why `imp` is skipped, if it is. Imps with banner, video or native are served;
+/// imps with no media object at all fall back to a banner.
+pub fn skip_reason(imp: &OpenrtbImp) -> Option<SkipReason> {
+    if wants_no_bid(imp) {
+        Some(SkipReason::NoBid)
+    } else if imp.banner.is_none()
+        && imp.video.is_none()
+        && imp.native.is_none()
+        && imp.audio.is_some()
+    {
+        Some(SkipReason::UnsupportedMedia)
+    } else {
+        None
+    }
+}
+
+/// Whether the request blocks our bids: `badv` lists `[auction] adomain`
+/// (case-insensitively) or `bcat` lists `[auction] cat`.
+pub fn blocked_by_request(req: &OpenRTBRequest, cfg: &AuctionConfig) -> bool {
+    let blocked = req
+        .badv
+        .iter()
+        .flatten()
+        .any(|domain| domain.eq_ignore_ascii_case(&cfg.adomain))
+        || req.bcat.iter().flatten().any(|cat| *cat == cfg.cat);
+    if blocked {
+        log::debug!(
+            "no bid: request blocks adomain '{}' or cat '{}'",
+            cfg.adomain,
+            cfg.cat
+        );
+    }
+    blocked
+}
+
+/// `dsaparams` claimed in our DSA transparency: 1 profiling, 2 basic advertising.
+const DSA_PARAMS: [i64; 2] = [1, 2];
+
+/// `bid.ext.dsa` for requests whose `regs.ext.dsa` requires DSA transparency.
+/// The advertiser is `[auction] adomain`, both on whose behalf the ad is shown
+/// and who paid for it. We render the transparency notice ourselves only when
+/// the publisher says it can't (`pubrender` 0).
+fn dsa_ext(req: &OpenRTBRequest, cfg: &AuctionConfig) -> Option<serde_json::Value> {
+    let dsa = req.regs.as_ref()?.dsa().filter(|dsa| dsa.required())?;
+    Some(json!({
+        "behalf": cfg.adomain,
+        "paid": cfg.adomain,
+        "transparency": [{"domain": cfg.adomain, "dsaparams": DSA_PARAMS}],
+        "adrender": i64::from(dsa.pubrender == Some(0)),
+    }))
+}
+
+/// Imps that lose their `[[fill_rule]]` draw. Rules apply to the size the imp
+/// would be bid at; each imp with a matching rule draws once, in imp order,
+/// from a generator seeded with `[auction] fill_seed`, or with a hash of the
+/// request id when unset, so a replayed request fills the same imps.
+fn unfilled_imps<'a>(
+    req: &'a OpenRTBRequest,
+    rules: &[FillRuleConfig],
+    cfg: &AuctionConfig,
+) -> HashSet<&'a str> {
+    if rules.is_empty() {
+        return HashSet::new();
+    }
+    let mut rng = XorShift64::new(cfg.fill_seed.unwrap_or_else(|| fnv1a(req.id.bytes())));
+    req.imp
+        .iter()
+        .filter(|imp| {
+            let (_, (w, h)) = bid_format(imp, cfg);
+            rules
+                .iter()
+                .find(|rule| rule.w == w && rule.h == h)
+                .is_some_and(|rule| rng.next_f64() >= rule.rate)
+        })
+        .map(|imp| imp.id.as_str())
+        .collect()
+}
+
+/// Whether `imp` asked for no bid via `imp.ext.mocktioneer.no_bid`.
+pub fn wants_no_bid(imp: &OpenrtbImp) -> bool {
+    imp.ext
+        .as_ref()
+        .and_then(|e| e.mocktioneer.as_ref())
+        .and_then(|m| m.no_bid)
+        .unwrap_or(false)
+}
+
+/// `bid.qagmediarating` for creatives rated `rating` (clamped to 1-3). A
+/// stricter `content.qagmediarating` on the site or app lowers it, so the ad
+/// never outrates the content it runs next to.
+pub fn media_rating(req: &OpenRTBRequest, rating: i64) -> i64 {
+    let content = req
+        .site
+        .as_ref()
+        .and_then(|site| site.content.as_ref())
+        .or_else(|| req.app.as_ref().and_then(|app| app.content.as_ref()));
+    let content_rating = content
+        .and_then(|content| content.qagmediarating)
+        .filter(|rating| (1..=3).contains(rating));
+    let rating = rating.clamp(1, 3);
+    content_rating.map_or(rating, |cap| rating.min(cap))
+}
+
+/// Whether `imp` asked for a bid without markup via `imp.ext.mocktioneer.no_creative`.
+pub fn wants_no_creative(imp: &OpenrtbImp) -> bool {
+    imp.ext
+        .as_ref()
+        .and_then(|e| e.mocktioneer.as_ref())
+        .and_then(|m| m.no_creative)
+        .unwrap_or(false)
+}
+
+/// Whether `bid` clears its imp's floor. Imps without a usable floor always pass.
+fn meets_floor(
+    imp: &OpenrtbImp,
+    bid: &OpenrtbBid,
+    req: &OpenRTBRequest,
+    cfg: &AuctionConfig,
+) -> bool {
+    match floor_in_usd(imp, req, cfg) {
+        Some(floor) if bid.price < floor => {
+            log::debug!(
+                "no bid on imp '{}': price {:.2} below floor {:.2} USD",
+                imp.id,
+                bid.price,
+                floor
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Apply `[auction] shading_factor` to `bid`: the price drops by that fraction,
+/// but not below the imp floor or the floor of `deal`. The pre-shade price goes
+/// to `ext.mocktioneer.original_price`. Explicit `imp.ext.mocktioneer.bid`
+/// prices are left alone.
+fn shade_bid(
+    bid: &mut OpenrtbBid,
+    imp: &OpenrtbImp,
+    deal: Option<&Deal>,
+    req: &OpenRTBRequest,
+    cfg: &AuctionConfig,
+) {
+    let Some(factor) = cfg.shading_factor else {
+        return;
+    };
+    let custom_bid = bid
+        .ext
+        .as_ref()
+        .is_some_and(|ext| ext["mocktioneer"].get("bid").is_some());
+    if custom_bid {
+        return;
+    }
+    let floor = floor_in_usd(imp, req, cfg)
+        .map(|f| (f * 100.0).ceil() / 100.0)
+        .into_iter()
+        .chain(deal.and_then(deal_floor_in_usd))
+        .fold(0.0, f64::max);
+    let original = bid.price;
+    let shaded = (original * (1.0 - factor.clamp(0.0, 1.0)) * 100.0).round() / 100.0;
+    bid.price = shaded.max(floor).min(original);
+    let ext = bid.ext.get_or_insert_with(|| json!({}));
+    ext["mocktioneer"]["original_price"] = json!(original);
+}
+
+/// Whether GDPR applies to the request (`regs.gdpr`, or the pre-2.6
+/// `regs.ext.gdpr`) without a consent string in `user.consent` or
+/// `user.ext.consent`. User ids are then not echoed back on bids.
+pub fn gdpr_without_consent(req: &OpenRTBRequest) -> bool {
+    let gdpr = req.regs.as_ref().and_then(|regs| {
+        regs.gdpr
+            .or_else(|| regs.ext.as_ref()?.get("gdpr")?.as_i64())
+    });
+    let consent = req.user.as_ref().and_then(|user| {
+        user.consent
+            .as_deref()
+            .or_else(|| user.ext.as_ref()?.get("consent")?.as_str())
+    });
+    gdpr == Some(1) && consent.is_none_or(str::is_empty)
+}
+
+/// OpenRTB `at` for a second-price auction.
+pub const AUCTION_SECOND_PRICE: i64 = 2;
+
+/// In a second-price request (`at` 2), lower every imp's winning bid to its
+/// [`second_price`] clearing price: one cent above the runner-up, or the imp
+/// floor (rounded up to the cent) for a lone bid. Bids under the floor are
+/// already gone, so the runner-up is never below it. Bids are compared in USD
+/// since seats may bid in different currencies; the winner's first price goes
+/// to `ext.mocktioneer.first_price`. Returns whether any price was lowered.
+fn apply_second_price(
+    req: &OpenRTBRequest,
+    seatbids: &mut [SeatBid],
+    response_cur: &str,
+    cfg: &AuctionConfig,
+) -> bool {
+    if req.at != Some(AUCTION_SECOND_PRICE) {
+        return false;
+    }
+    // (usd price, seatbid index, bid index) of every bid, per imp
+    let mut by_imp: HashMap<&str, Vec<(f64, usize, usize)>> = HashMap::new();
+    for (s, seatbid) in seatbids.iter().enumerate() {
+        for (b, bid) in seatbid.bid.iter().enumerate() {
+            let usd = convert_price(bid.price, bid_currency(bid, response_cur), "USD")
+                .unwrap_or(bid.price);
+            by_imp
+                .entry(bid.impid.as_str())
+                .or_default()
+                .push((usd, s, b));
+        }
+    }
+    let mut clearing: Vec<(usize, usize, f64)> = Vec::new();
+    for (impid, mut bids) in by_imp {
+        // Stable sort: the first bid made wins a tie
+        bids.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let (winner_usd, s, b) = bids[0];
+        let floor = req
+            .imp
+            .iter()
+            .find(|imp| imp.id == impid)
+            .and_then(|imp| floor_in_usd(imp, req, cfg))
+            .map_or(0.0, |f| (f * 100.0).ceil() / 100.0);
+        let runner_up = bids.get(1).map(|&(usd, _, _)| usd);
+        let clearing_usd = second_price(winner_usd, runner_up, floor);
+        if clearing_usd < winner_usd {
+            clearing.push((s, b, clearing_usd));
+        }
+    }
+    let lowered = !clearing.is_empty();
+    for (s, b, clearing_usd) in clearing {
+        let bid = &mut seatbids[s].bid[b];
+        let cur = bid_currency(bid, response_cur);
+        let price = convert_price(clearing_usd, "USD", cur).unwrap_or(clearing_usd);
+        let ext = bid.ext.get_or_insert_with(|| json!({}));
+        ext["mocktioneer"]["first_price"] = json!(bid.price);
+        bid.price = price.min(bid.price);
+    }
+    lowered
+}
+
 fn new_id() -> String {
-    Uuid::now_v7().simple().to_string()
+    clock::uuid_v7(clock::global()).simple().to_string()
 }
 
-pub fn size_from_imp(imp: &OpenrtbImp) -> (i64, i64) {
-    // Prefer imp.banner.w/h; fallback to banner.format[0].w/h; default 300x250
+pub fn size_from_imp(imp: &OpenrtbImp, cfg: &AuctionConfig) -> (i64, i64) {
+    // Prefer imp.banner.w/h; fallback to banner.format[0]; default 300x250
     if let Some(banner) = &imp.banner {
         if let (Some(w), Some(h)) = (banner.w, banner.h) {
             return (w, h);
         }
-        if let Some(fmt) = &banner.format {
-            if let Some(fmt0) = fmt.first() {
-                let w = fmt0.w;
-                let h = fmt0.h;
-                return (w, h);
+        if let Some(fmt0) = banner.format.as_ref().and_then(|fmt| fmt.first()) {
+            if fmt0.w > 0 && fmt0.h > 0 {
+                return (fmt0.w, fmt0.h);
             }
+            return flexible_size(fmt0, cfg);
         }
     }
     (300, 250)
 }
 
-pub fn standard_or_default((w, h): (i64, i64)) -> (i64, i64) {
-    if is_standard_size(w, h) {
+/// Size for a flexible format without absolute `w`/`h`: the largest standard
+/// size matching `wratio:hratio` and at least `wmin`x`hmin`, or 300x250 when
+/// none does.
+pub fn flexible_size(fmt: &Format, cfg: &AuctionConfig) -> (i64, i64) {
+    let ratio = match (fmt.wratio, fmt.hratio) {
+        (Some(wr), Some(hr)) if wr > 0 && hr > 0 => Some((wr, hr)),
+        _ => None,
+    };
+    let (wmin, hmin) = (fmt.wmin.unwrap_or(0), fmt.hmin.unwrap_or(0));
+    if ratio.is_none() && wmin <= 0 && hmin <= 0 {
+        return (300, 250);
+    }
+    standard_sizes(cfg)
+        .filter(|&(w, h)| {
+            ratio.is_none_or(|(wr, hr)| {
+                i128::from(w) * i128::from(hr) == i128::from(h) * i128::from(wr)
+            })
+        })
+        .filter(|&(w, h)| w >= wmin && h >= hmin)
+        .max_by_key(|&(w, h)| w * h)
+        .unwrap_or((300, 250))
+}
+
+pub fn standard_or_default((w, h): (i64, i64), cfg: &AuctionConfig) -> (i64, i64) {
+    if is_bid_size(w, h, cfg) {
         (w, h)
     } else {
         (300, 250)
     }
 }
 
-/// Build an OpenRTB bid response for the given request.
+/// Player size for a video imp: `video.w`/`video.h`, defaulting to 640x480.
+/// Video sizes are not restricted to the standard display sizes.
+pub fn video_size(video: &Video) -> (i64, i64) {
+    match (video.w, video.h) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => (w, h),
+        _ => (640, 480),
+    }
+}
+
+/// Preferred clip length in seconds when the request doesn't force one.
+const DEFAULT_VIDEO_DURATION: i64 = 15;
+
+/// Clip length for a video imp: 15 seconds, clamped into
+/// `[video.minduration, video.maxduration]` when those are set.
+pub fn video_duration(video: &Video) -> i64 {
+    let min = video.minduration.unwrap_or(1).max(1);
+    let max = video
+        .maxduration
+        .filter(|max| *max >= min)
+        .unwrap_or(i64::MAX);
+    DEFAULT_VIDEO_DURATION.clamp(min, max)
+}
+
+/// Name of the default seat (used when no `[[seat]]` is configured).
+pub const SEAT: &str = "mocktioneer";
+
+/// First PMP deal on `imp` that `seat` may bid on.
 ///
-/// - Enforces standard ad sizes (non-standard sizes default to 300x250)
-/// - Uses size-based CPM pricing ($1.70 - $4.20 depending on size)
-/// - Price can be overridden via `imp.ext.mocktioneer.bid`
-/// - Embeds signature verification status, the original request, and a preview
-///   of the response as HTML comments in each creative
-/// - The signature badge is rendered inside the creative via the `sig` query param
-pub fn build_openrtb_response(
+/// A deal with a non-empty `wseat` is only available to the seats it lists; an
+/// absent or empty `wseat` leaves the deal open to any seat.
+pub fn matching_deal<'a>(imp: &'a OpenrtbImp, seat: &str) -> Option<&'a Deal> {
+    imp.pmp
+        .as_ref()?
+        .deals
+        .as_ref()?
+        .iter()
+        .find(|deal| match deal.wseat.as_deref() {
+            Some(wseat) if !wseat.is_empty() => wseat.iter().any(|s| s == seat),
+            _ => true,
+        })
+}
+
+/// Whether `imp` is a private auction (`pmp.private_auction = 1`), where only
+/// bids on one of its deals are allowed.
+pub fn is_private_auction(imp: &OpenrtbImp) -> bool {
+    imp.pmp
+        .as_ref()
+        .is_some_and(|pmp| pmp.private_auction == Some(1))
+}
+
+/// `GET /winnotice` URL for a bid on `impid`, used for `nurl` (`event=win`) and
+/// `burl` (`event=billing`). The exchange substitutes the clearing price for the
+/// OpenRTB `${AUCTION_PRICE}` macro.
+pub fn win_notice_url(base_host: &str, impid: &str, event: &str) -> String {
+    let impid: String = url::form_urlencoded::byte_serialize(impid.as_bytes()).collect();
+    format!(
+        "//{}/winnotice?imp={}&price=${{AUCTION_PRICE}}&event={}",
+        base_host, impid, event
+    )
+}
+
+/// `banner.api` values for MRAID 1.0, 2.0 and 3.0 (OpenRTB API Frameworks list).
+const MRAID_APIS: [i64; 3] = [3, 5, 6];
+
+/// `video.api` values we claim for video bids: VPAID 1.0/2.0, OMID 1.0 and
+/// SIMID 1.0/1.1. The VAST itself is plain; the claim lets callers test API
+/// framework negotiation.
+const VIDEO_APIS: [i64; 5] = [1, 2, 7, 8, 9];
+
+/// Highest API framework in `video.api` that we claim to support.
+pub fn video_api(video: &Video) -> Option<i64> {
+    video
+        .api
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|api| VIDEO_APIS.contains(api))
+        .max()
+}
+
+/// `device.connectiontype` values (2G, 3G) that get the light creative.
+const SLOW_CONNECTIONS: [i64; 2] = [4, 5];
+
+/// Whether the request comes over a slow (2G/3G) connection.
+pub fn slow_connection(req: &OpenRTBRequest) -> bool {
+    req.device
+        .as_ref()
+        .and_then(|d| d.connectiontype)
+        .is_some_and(|ct| SLOW_CONNECTIONS.contains(&ct))
+}
+
+/// Where a redirecting video auction (`[auction] video_response = "redirect"`)
+/// sends the client: the `/vast` URL of the bid on the request's only imp. `None`
+/// unless the request has exactly one imp, it is video and it got a bid with
+/// markup (no `imp.ext.mocktioneer.no_creative`).
+pub fn vast_redirect_url(
     req: &OpenRTBRequest,
+    resp: &OpenRTBResponse,
     base_host: &str,
-    signature_status: SignatureStatus,
-) -> OpenRTBResponse {
-    // Build bids without adm
-    let mut bids: Vec<OpenrtbBid> = Vec::new();
-    for imp in req.imp.iter() {
-        let (w, h) = standard_or_default(size_from_imp(imp));
-        let bid_id = new_id();
-        let crid = format!("mocktioneer-{}", imp.id);
-
-        // Extract custom bid from imp.ext.mocktioneer.bid if present
-        let custom_bid = imp
-            .ext
-            .as_ref()
-            .and_then(|e| e.mocktioneer.as_ref())
-            .and_then(|m| m.bid);
+) -> Option<String> {
+    let [imp] = req.imp.as_slice() else {
+        return None;
+    };
+    if wants_no_creative(imp) {
+        return None;
+    }
+    let video = imp.video.as_ref()?;
+    let bid = resp
+        .seatbid
+        .iter()
+        .flat_map(|sb| &sb.bid)
+        .find(|bid| bid.impid == imp.id)?;
+    let (w, h) = video_size(video);
+    Some(vast_url(
+        base_host,
+        bid.crid.as_deref().unwrap_or("unknown"),
+        bid.w.unwrap_or(w),
+        bid.h.unwrap_or(h),
+        video_duration(video),
+        vast_version(video.protocols.as_deref()),
+    ))
+}
 
-        // Use custom bid if provided, otherwise use size-based CPM
-        let price = custom_bid.unwrap_or_else(|| get_cpm(w, h));
-        let bid_ext = custom_bid.map(|b| json!({"mocktioneer": {"bid": b}}));
+/// Whether the imp's banner advertises any MRAID version in `banner.api`.
+pub fn supports_mraid(imp: &OpenrtbImp) -> bool {
+    imp.banner
+        .as_ref()
+        .and_then(|b| b.api.as_ref())
+        .is_some_and(|apis| apis.iter().any(|api| MRAID_APIS.contains(api)))
+}
 
-        bids.push(OpenrtbBid {
-            id: bid_id,
-            impid: imp.id.clone(),
-            price,
-            adm: None, // Filled after metadata is built
-            crid: Some(crid),
-            w: Some(w),
-            h: Some(h),
-            mtype: Some(MediaType::Banner),
-            adomain: Some(vec!["example.com".to_string()]),
-            ext: bid_ext,
+/// Wrap bids into seatbids.
+///
+/// By default every bid lands in a single `seat` seatbid. With `by_mtype`, bids are
+/// grouped by `mtype` (in order of first appearance) and each seat is suffixed with
+/// the media type, e.g. `mocktioneer-video`. Bids without an `mtype` count as banner.
+pub fn group_seatbids(bids: Vec<OpenrtbBid>, seat: &str, by_mtype: bool) -> Vec<SeatBid> {
+    if !by_mtype {
+        return vec![SeatBid {
+            seat: Some(seat.to_string()),
+            bid: bids,
+            ..Default::default()
+        }];
+    }
+
+    let mut groups: Vec<(MediaType, Vec<OpenrtbBid>)> = Vec::new();
+    for bid in bids {
+        let mtype = bid.mtype.unwrap_or(MediaType::Banner);
+        match groups.iter_mut().find(|(m, _)| *m == mtype) {
+            Some((_, group)) => group.push(bid),
+            None => groups.push((mtype, vec![bid])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(mtype, bid)| SeatBid {
+            seat: Some(format!("{}-{}", seat, mtype.as_str())),
+            bid,
             ..Default::default()
+        })
+        .collect()
+}
+
+/// Sort each seatbid's bids by price, highest first. The sort is stable, so equal
+/// prices keep their imp order.
+pub fn sort_bids_by_price(seatbids: &mut [SeatBid]) {
+    for seatbid in seatbids {
+        seatbid.bid.sort_by(|a, b| {
+            b.price
+                .partial_cmp(&a.price)
+                .unwrap_or(std::cmp::Ordering::Equal)
         });
     }
+}
 
-    // Build preview response for metadata
-    let response_id = if req.id.is_empty() {
-        "req".to_string()
+/// Group one seat's bids into seatbids and apply ordering according to `[auction]` settings.
+fn assemble_seatbids(bids: Vec<OpenrtbBid>, seat: &str, cfg: &AuctionConfig) -> Vec<SeatBid> {
+    let mut seatbids = group_seatbids(bids, seat, cfg.seatbid_by_mtype);
+    if cfg.sort_bids {
+        sort_bids_by_price(&mut seatbids);
+    }
+    seatbids
+}
+
+/// No-bid reason sent when a `[[site_rule]]` blocks the request's site (OpenRTB
+/// "Blocked Publisher or Site").
+pub const NBR_BLOCKED_SITE: i64 = 7;
+
+/// Host the request is for: `site.domain`, else the host of `site.page`.
+pub fn site_host(req: &OpenRTBRequest) -> Option<String> {
+    let site = req.site.as_ref()?;
+    let host = site
+        .domain
+        .as_deref()
+        .filter(|d| !d.is_empty())
+        .map(str::to_string)
+        .or_else(|| {
+            let page = url::Url::parse(site.page.as_deref()?).ok()?;
+            page.host_str().map(str::to_string)
+        })?;
+    Some(host.to_ascii_lowercase())
+}
+
+/// Whether `pattern` (a `[[site_rule]] domain`) covers `host`.
+fn site_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_start_matches("*.").to_ascii_lowercase();
+    pattern == "*"
+        || host == pattern
+        || host
+            .strip_suffix(pattern.as_str())
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Price multiplier from `rules` for the request's site, or `None` when the site
+/// is blocked. Requests without a site are not subject to site rules.
+pub fn site_multiplier(req: &OpenRTBRequest, rules: &[SiteRuleConfig]) -> Option<f64> {
+    let Some(host) = site_host(req) else {
+        return Some(1.0);
+    };
+    match rules.iter().find(|rule| site_matches(&rule.domain, &host)) {
+        Some(rule) if rule.action == Some(SiteAction::Block) => None,
+        Some(rule) => Some(rule.multiplier),
+        // An explicit allow list admits only the sites it names
+        None if rules
+            .iter()
+            .any(|rule| rule.action == Some(SiteAction::Allow)) =>
+        {
+            None
+        }
+        None => Some(1.0),
+    }
+}
+
+/// Configured seats, capped at `[limits] max_seats`.
+pub fn active_seats(cfg: &AppConfig) -> &[SeatConfig] {
+    let max = cfg.limits.max_seats;
+    if cfg.seats.len() > max {
+        log::warn!(
+            "{} seats configured, only the first {} (limits.max_seats) will bid",
+            cfg.seats.len(),
+            max
+        );
+        &cfg.seats[..max]
     } else {
-        req.id.clone()
+        &cfg.seats
+    }
+}
+
+/// Whether the request lets `seat` bid: not listed in `bseat`, and listed in
+/// `wseat` when that is non-empty.
+pub fn seat_allowed(req: &OpenRTBRequest, seat: &SeatConfig) -> bool {
+    let listed = |seats: &Option<Vec<String>>| seats.iter().flatten().any(|s| *s == seat.name);
+    let blocked = listed(&req.bseat);
+    let whitelisted = req.wseat.as_ref().is_none_or(Vec::is_empty) || listed(&req.wseat);
+    if blocked || !whitelisted {
+        log::debug!("seat '{}' excluded by wseat/bseat", seat.name);
+    }
+    !blocked && whitelisted
+}
+
+/// The seat's configured currency, if it is one we can convert to.
+fn seat_currency(seat: &SeatConfig) -> Option<&str> {
+    seat.currency.as_deref().filter(|cur| {
+        let known = USD_RATES.contains_key(cur);
+        if !known {
+            log::warn!(
+                "Unknown currency '{}' for seat '{}', bidding in USD",
+                cur,
+                seat.name
+            );
+        }
+        known
+    })
+}
+
+/// Currency the response is priced in: the request's `cur` when it lists a
+/// single currency we can convert to, otherwise USD.
+pub fn response_currency(req: &OpenRTBRequest) -> &str {
+    let Some([cur]) = req.cur.as_deref() else {
+        return "USD";
     };
+    if !USD_RATES.contains_key(cur.as_str()) {
+        log::warn!("Unknown request currency '{}', bidding in USD", cur);
+        return "USD";
+    }
+    cur
+}
 
-    let preview_response = OpenRTBResponse {
-        id: response_id.clone(),
-        cur: Some("USD".to_string()),
-        seatbid: vec![SeatBid {
-            seat: Some("mocktioneer".to_string()),
-            bid: bids.clone(),
-            ..Default::default()
-        }],
-        ..Default::default()
+/// `price` rounded per `[auction] price_rounding`.
+pub fn round_price(price: f64, rounding: PriceRounding) -> f64 {
+    match rounding {
+        PriceRounding::None => price,
+        PriceRounding::Cents => (price * 100.0).round() / 100.0,
+        // The epsilon keeps whole cents whole: 2.35 * 100.0 is 234.999...
+        PriceRounding::DownCents => ((price * 100.0) + 1e-6).floor() / 100.0,
+    }
+}
+
+/// Currency `bid` is priced in: the seat's `ext.mocktioneer.cur`, otherwise the
+/// response currency.
+fn bid_currency<'a>(bid: &'a OpenrtbBid, response_cur: &'a str) -> &'a str {
+    bid.ext
+        .as_ref()
+        .and_then(|ext| ext["mocktioneer"]["cur"].as_str())
+        .unwrap_or(response_cur)
+}
+
+/// Round `bid` per `[auction] price_rounding` in its own currency, but never
+/// below the floor of its imp rounded up to the cent, as [`shade_bid`] does.
+fn round_bid(bid: &mut OpenrtbBid, req: &OpenRTBRequest, response_cur: &str, cfg: &AuctionConfig) {
+    if cfg.price_rounding == PriceRounding::None {
+        return;
+    }
+    let cur = bid_currency(bid, response_cur);
+    // The epsilon keeps whole-cent floors whole after the trip through USD
+    let floor = req
+        .imp
+        .iter()
+        .find(|imp| imp.id == bid.impid)
+        .and_then(|imp| floor_in_usd(imp, req, cfg))
+        .and_then(|usd| convert_price(usd, "USD", cur))
+        .map_or(0.0, |f| ((f * 100.0) - 1e-6).ceil() / 100.0);
+    bid.price = round_price(bid.price, cfg.price_rounding).max(floor);
+}
+
+/// Convert a USD bid price into `cur`. Rounding is left to
+/// `[auction] price_rounding` (see [`round_bid`]).
+fn convert_bid(bid: &mut OpenrtbBid, cur: &str) {
+    if let Some(price) = convert_price(bid.price, "USD", cur) {
+        bid.price = price;
+    }
+}
+
+/// Convert a USD bid into `cur` and note the currency in
+/// `bid.ext.mocktioneer.cur`.
+fn price_bid_in(bid: &mut OpenrtbBid, cur: &str) {
+    convert_bid(bid, cur);
+    let ext = bid.ext.get_or_insert_with(|| json!({}));
+    ext["mocktioneer"]["cur"] = json!(cur);
+}
+
+/// Upper bound for `imp.ext.mocktioneer.num_bids`.
+pub const MAX_BIDS_PER_IMP: i64 = 10;
+
+/// Price step between successive bids on the same imp (10% of the base each).
+const MULTI_BID_STEP: f64 = 0.1;
+
+/// Bids requested for `imp` via `imp.ext.mocktioneer.num_bids`: missing, zero or
+/// negative means one, and large values are capped at [`MAX_BIDS_PER_IMP`].
+pub fn num_bids(imp: &OpenrtbImp) -> usize {
+    imp.ext
+        .as_ref()
+        .and_then(|e| e.mocktioneer.as_ref())
+        .and_then(|m| m.num_bids)
+        .unwrap_or(1)
+        .clamp(1, MAX_BIDS_PER_IMP) as usize
+}
+
+/// 64-bit FNV-1a hash of `bytes`, stable across platforms and releases.
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes
+        .into_iter()
+        .fold(0xCBF2_9CE4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+        })
+}
+
+/// Price in `range` for imp `imp_id` of request `request_id`, rounded to cents.
+/// The same ids always give the same price.
+pub fn seeded_price(request_id: &str, imp_id: &str, [min, max]: [f64; 2]) -> f64 {
+    let seed = fnv1a(request_id.bytes().chain([0]).chain(imp_id.bytes()));
+    let price = min + (max - min) * XorShift64::new(seed).next_f64();
+    ((price * 100.0).round() / 100.0).clamp(min, max)
+}
+
+/// Media type and creative size we bid on `imp` with: the video player size, or a
+/// standard size for banner and native-only imps.
+fn bid_format(imp: &OpenrtbImp, cfg: &AuctionConfig) -> (MediaType, (i64, i64)) {
+    match &imp.video {
+        Some(video) => (MediaType::Video, video_size(video)),
+        None if imp.banner.is_none() && imp.native.is_some() => (
+            MediaType::Native,
+            standard_or_default(size_from_imp(imp, cfg), cfg),
+        ),
+        None => (
+            MediaType::Banner,
+            standard_or_default(size_from_imp(imp, cfg), cfg),
+        ),
+    }
+}
+
+/// Whether the request asks for generated crids that carry the request id
+/// (`ext.mocktioneer.include_reqid`), to tie creatives back to their auction.
+fn includes_reqid(req: &OpenRTBRequest) -> bool {
+    req.ext
+        .as_ref()
+        .and_then(|ext| ext["mocktioneer"]["include_reqid"].as_bool())
+        .unwrap_or(false)
+}
+
+/// Build `seat`'s `rank`-th bid (1-based) for `imp` (without `adm`, which needs the
+/// full response). Later ranks bid progressively less and get a suffixed `crid`.
+/// Computed prices are scaled by the site rule `multiplier` and raised to the
+/// floor of the PMP deal the bid is made on.
+fn build_bid(
+    req: &OpenRTBRequest,
+    imp: &OpenrtbImp,
+    seat: &SeatConfig,
+    rank: usize,
+    cfg: &AuctionConfig,
+    multiplier: f64,
+) -> OpenrtbBid {
+    let (mtype, (w, h)) = bid_format(imp, cfg);
+    let ext_mocktioneer = imp.ext.as_ref().and_then(|e| e.mocktioneer.as_ref());
+
+    // Echo imp.ext.mocktioneer.crid when provided, otherwise derive from seat and
+    // imp id (and the request id with ext.mocktioneer.include_reqid)
+    let crid = ext_mocktioneer
+        .and_then(|m| m.crid.as_deref())
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            if includes_reqid(req) {
+                format!("{}-{}-{}", seat.name, req.id, imp.id)
+            } else {
+                format!("{}-{}", seat.name, imp.id)
+            }
+        });
+    let crid = if rank > 1 {
+        format!("{}-{}", crid, rank)
+    } else {
+        crid
     };
+    // Echo imp.ext.mocktioneer.adid when provided, otherwise derive from the crid
+    let adid = ext_mocktioneer
+        .and_then(|m| m.adid.as_deref())
+        .filter(|a| !a.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("ad-{}", crid));
 
-    // Serialize response for metadata
-    let sanitized_response = serde_json::to_value(&preview_response).ok();
+    // Extract custom bid from imp.ext.mocktioneer.bid if present
+    let custom_bid = ext_mocktioneer.and_then(|m| m.bid);
+
+    // Use custom bid if provided, then a seeded price in the requested range,
+    // then the configured default, then size-based CPM
+    let ranged = ext_mocktioneer
+        .and_then(|m| m.price_range)
+        .map(|range| seeded_price(&req.id, &imp.id, range));
+    let base = custom_bid.or(ranged).unwrap_or_else(|| {
+        let price = cfg.default_bid_price.unwrap_or_else(|| get_cpm(w, h));
+        if multiplier == 1.0 {
+            price
+        } else {
+            (price * multiplier * 100.0).round() / 100.0
+        }
+    });
+    let price = if rank > 1 {
+        let factor = 1.0 - MULTI_BID_STEP * (rank - 1) as f64;
+        (base * factor * 100.0).round() / 100.0
+    } else {
+        base
+    };
+    let deal = matching_deal(imp, &seat.name);
+    // An explicit ext bid is used as-is, even under the deal floor
+    let price = match deal.and_then(deal_floor_in_usd) {
+        Some(floor) if custom_bid.is_none() => price.max(floor),
+        _ => price,
+    };
+    // Echo the custom bid and the imp's SDK (display manager) for diagnostics
+    let mut bid_ext = serde_json::Map::new();
+    if let Some(b) = custom_bid {
+        bid_ext.insert("bid".to_string(), json!(b));
+    }
+    if let Some(dm) = &imp.displaymanager {
+        bid_ext.insert("displaymanager".to_string(), json!(dm));
+    }
+    if let Some(ver) = &imp.displaymanagerver {
+        bid_ext.insert("displaymanagerver".to_string(), json!(ver));
+    }
+    if let Some(uid) = req.user.as_ref().and_then(|u| u.buyeruid.as_ref()) {
+        if !gdpr_without_consent(req) {
+            bid_ext.insert("buyeruid".to_string(), json!(uid));
+        }
+    }
+    let mut bid_ext = (!bid_ext.is_empty()).then(|| json!({"mocktioneer": bid_ext}));
+    if let Some(dsa) = dsa_ext(req, cfg) {
+        bid_ext.get_or_insert_with(|| json!({}))["dsa"] = dsa;
+    }
+    let dealid = deal.map(|deal| deal.id.clone());
+
+    OpenrtbBid {
+        id: new_id(),
+        impid: imp.id.clone(),
+        price,
+        adm: None, // Filled after metadata is built
+        adid: Some(adid),
+        crid: Some(crid),
+        w: Some(w),
+        h: Some(h),
+        mtype: Some(mtype),
+        adomain: Some(vec![cfg.adomain.clone()]),
+        cat: Some(vec![cfg.cat.clone()]),
+        protocol: imp
+            .video
+            .as_ref()
+            .and_then(|v| vast_protocol(v.protocols.as_deref())),
+        api: imp.video.as_ref().and_then(video_api),
+        exp: imp.exp,
+        dealid,
+        ext: bid_ext,
+        ..Default::default()
+    }
+}
+
+/// Build an OpenRTB bid response for the given request using the embedded
+/// `mocktioneer.toml` settings. See [`build_openrtb_response_with_config`].
+pub fn build_openrtb_response(
+    req: &OpenRTBRequest,
+    base_host: &str,
+    signature_status: SignatureStatus,
+) -> OpenRTBResponse {
+    build_openrtb_response_with_config(req, base_host, signature_status, AppConfig::global())
+}
+
+/// Build an OpenRTB bid response for the given request.
+///
+/// - Enforces standard ad sizes (non-standard sizes default to 300x250)
+/// - Uses size-based CPM pricing ($1.70 - $4.20 depending on size)
+/// - Price can be overridden via `imp.ext.mocktioneer.bid`, drawn from
+///   `imp.ext.mocktioneer.price_range` (seeded by request and imp id), or set
+///   globally via `[auction] default_bid_price`
+/// - Embeds signature verification status, the original request, and a preview
+///   of the response as HTML comments in each creative
+/// - The signature badge is rendered inside the creative via the `sig` query param
+/// - Seatbids are split per media type when `[auction] seatbid_by_mtype` is set
+/// - Bids are ordered by price, highest first, when `[auction] sort_bids` is set
+/// - Every configured `[[seat]]` (up to `[limits] max_seats`) bids on every imp,
+///   unless the request's `wseat`/`bseat` excludes it; with no seat left,
+///   `seatbid` is empty and `nbr` is [`NBR_SEAT_BLOCKED`]
+/// - `imp.ext.mocktioneer.num_bids = N` yields N bids per imp at decreasing prices,
+///   the k-th bids grouped into seat `{seat}-{k}`; `[limits] max_bids_per_imp`
+///   caps the bids per imp across all seats
+/// - When `cur` lists a single known currency, prices are converted into it from
+///   USD and the top-level `cur` names it (see [`response_currency`]); an
+///   unknown currency falls back to USD
+/// - Seats with a `currency` bid in it instead (converted from USD), noted in the
+///   bid and seatbid `ext.mocktioneer.cur`
+/// - Private auctions (`pmp.private_auction = 1`) only get bids on a deal open to
+///   the seat; when that leaves no bid at all, `nbr` is [`NBR_NO_DEAL`]
+/// - Imps whose `bidfloor` (normalized to USD) exceeds our price get no bid; when
+///   no imp is bid on, `seatbid` is empty and `nbr` is [`NBR_BELOW_FLOOR`]
+/// - `[auction] shading_factor` shades computed prices of bids that met the floor
+///   (never below it), keeping the bid in `ext.mocktioneer.original_price`
+/// - With `at = 2` (second price), an imp's winning bid is lowered to one cent above
+///   the runner-up, or to the imp floor when it is the only bid (see
+///   [`apply_second_price`])
+/// - `[auction] price_rounding` rounds every final price, after second pricing,
+///   in the bid's currency and never below the imp floor (see [`round_bid`])
+/// - Imps with `imp.ext.mocktioneer.no_bid = true`, and audio-only imps, get no
+///   bid and are listed with a [`SkipReason`] in `ext.mocktioneer.skipped`,
+///   as are imps losing their `[[fill_rule]]` draw and every imp of a request
+///   whose `badv`/`bcat` blocks `[auction] adomain`/`cat`. When every imp is skipped,
+///   `seatbid` is empty and `nbr` is [`NBR_UNSUPPORTED_MEDIA`] if any imp asked
+///   for unsupported media, otherwise [`NBR_NO_BID`]
+/// - `ext.prebid.meta` reports the bid's `adomain`, media type and
+///   `[prebid] network_name` for Prebid.js
+/// - `qagmediarating` is `[creative] media_rating`, lowered to the site or app
+///   `content.qagmediarating` when that is stricter
+/// - `adm` is an iframe, or a script tag with `[creative] delivery = "script"`;
+///   imps with `imp.ext.mocktioneer.no_creative = true` get neither `adm` nor `nurl`
+/// - Video imps get `mtype` video and an inline VAST `adm` at the highest version
+///   in `video.protocols` (VAST 3.0 when none is listed)
+/// - `[[site_rule]]` entries matching `site.domain` (or the `site.page` host) scale
+///   computed prices by their `multiplier`, or block the site: no bids and `nbr`
+///   [`NBR_BLOCKED_SITE`]
+/// - Deprecated or misspelled request fields are listed, without failing the
+///   request, under `ext.warnings.general` (see [`request_warnings`])
+/// - `[debug] inject_duplicate_bids` appends a copy of each seatbid's first bid
+///   with the same `impid` and a `-dup` suffixed `id`
+/// - With `[debug] enabled`, `ext.mocktioneer.features` lists the optional
+///   behaviors that fired (see [`Feature`])
+pub fn build_openrtb_response_with_config(
+    req: &OpenRTBRequest,
+    base_host: &str,
+    signature_status: SignatureStatus,
+    cfg: &AppConfig,
+) -> OpenRTBResponse {
+    let mut features = FeatureLog::default();
+    let multiplier = site_multiplier(req, &cfg.site_rules);
+    let response_cur = response_currency(req);
+    let blocked = blocked_by_request(req, &cfg.auction);
+    let unfilled = unfilled_imps(req, &cfg.fill_rules, &cfg.auction);
+    let skip = |imp: &OpenrtbImp| {
+        skip_reason(imp)
+            .or(blocked.then_some(SkipReason::Blocked))
+            .or_else(|| {
+                unfilled
+                    .contains(imp.id.as_str())
+                    .then_some(SkipReason::NoFill)
+            })
+    };
+    if multiplier.is_none() {
+        features.record(Feature::SiteBlocked);
+    }
+    // Build every seat's bids without adm, skipping imps whose floor we can't meet
+    let mut seatbids: Vec<SeatBid> = Vec::new();
+    // With multiple bids per imp, the k-th bids of every imp form seat "{seat}-{k}"
+    let ranks = req.imp.iter().map(num_bids).max().unwrap_or(1);
+    let seats: Vec<&SeatConfig> = active_seats(cfg)
+        .iter()
+        .filter(|seat| seat_allowed(req, seat))
+        .collect();
+    // Bids made so far per imp, for `[limits] max_bids_per_imp`
+    let mut imp_bids: HashMap<&str, usize> = HashMap::new();
+    // Whether a private auction turned away an open-market bid
+    let mut missed_deal = false;
+    for seat in seats.iter().copied() {
+        let currency = seat_currency(seat);
+        for rank in 1..=ranks {
+            let bids: Vec<OpenrtbBid> = req
+                .imp
+                .iter()
+                .filter(|imp| skip(imp).is_none() && num_bids(imp) >= rank)
+                .filter_map(|imp| {
+                    let bid = build_bid(req, imp, seat, rank, &cfg.auction, multiplier?);
+                    Some((imp, bid))
+                })
+                .filter(|(imp, bid)| {
+                    let open_bid_in_private = bid.dealid.is_none() && is_private_auction(imp);
+                    missed_deal |= open_bid_in_private;
+                    !open_bid_in_private
+                })
+                .filter(|(imp, bid)| {
+                    let meets = meets_floor(imp, bid, req, &cfg.auction);
+                    if !meets {
+                        features.record(Feature::FloorEnforced);
+                    }
+                    meets
+                })
+                .filter(|(imp, _)| {
+                    let made = imp_bids.entry(imp.id.as_str()).or_default();
+                    *made += 1;
+                    cfg.limits.max_bids_per_imp.is_none_or(|max| *made <= max)
+                })
+                .map(|(imp, mut bid)| {
+                    let deal = matching_deal(imp, &seat.name);
+                    shade_bid(&mut bid, imp, deal, req, &cfg.auction);
+                    match currency {
+                        Some(cur) => price_bid_in(&mut bid, cur),
+                        None => convert_bid(&mut bid, response_cur),
+                    }
+                    bid
+                })
+                .collect();
+            if bids.is_empty() {
+                continue;
+            }
+            let seat_name = if ranks > 1 {
+                format!("{}-{}", seat.name, rank)
+            } else {
+                seat.name.clone()
+            };
+            let mut seat_seatbids = assemble_seatbids(bids, &seat_name, &cfg.auction);
+            if let Some(cur) = currency {
+                for seatbid in &mut seat_seatbids {
+                    seatbid.ext = Some(json!({"mocktioneer": {"cur": cur}}));
+                }
+            }
+            seatbids.extend(seat_seatbids);
+        }
+    }
+    if apply_second_price(req, &mut seatbids, response_cur, &cfg.auction) {
+        features.record(Feature::SecondPrice);
+    }
+    let has_buyeruid = req.user.as_ref().is_some_and(|u| u.buyeruid.is_some());
+    if has_buyeruid && !seatbids.is_empty() && gdpr_without_consent(req) {
+        features.record(Feature::GdprSuppression);
+    }
+    for bid in seatbids.iter_mut().flat_map(|sb| sb.bid.iter_mut()) {
+        round_bid(bid, req, response_cur, &cfg.auction);
+    }
+    let skipped: Vec<(&str, SkipReason)> = req
+        .imp
+        .iter()
+        .filter_map(|imp| Some((imp.id.as_str(), skip(imp)?)))
+        .collect();
+    let nbr = seatbids.is_empty().then(|| {
+        if multiplier.is_none() {
+            NBR_BLOCKED_SITE
+        } else if seats.is_empty() {
+            NBR_SEAT_BLOCKED
+        } else if missed_deal {
+            NBR_NO_DEAL
+        } else if skipped.len() < req.imp.len() {
+            NBR_BELOW_FLOOR
+        } else if skipped
+            .iter()
+            .any(|(_, r)| *r == SkipReason::UnsupportedMedia)
+        {
+            NBR_UNSUPPORTED_MEDIA
+        } else {
+            NBR_NO_BID
+        }
+    });
+    if nbr.is_some() {
+        features.record(Feature::NoFill);
+    }
+
+    // Build preview response for metadata
+    let response_id = if req.id.is_empty() {
+        "req".to_string()
+    } else {
+        req.id.clone()
+    };
+
+    let preview_response = OpenRTBResponse {
+        id: response_id.clone(),
+        cur: Some(response_cur.to_string()),
+        seatbid: seatbids.clone(),
+        ..Default::default()
+    };
+
+    // Serialize response for metadata
+    let sanitized_response = serde_json::to_value(&preview_response).ok();
+
+    // Build metadata with sanitized response
+    let metadata = CreativeMetadata {
+        signature: signature_status,
+        request: req,
+        response: sanitized_response,
+    };
+
+    // Fill in adm and win notice URLs for each bid; MRAID-capable imps get MRAID
+    // markup, and slow connections the static image instead of the HTML creative
+    let default_adm = match cfg.creative.delivery {
+        _ if slow_connection(req) => light_adm,
+        CreativeDelivery::Iframe => iframe_html,
+        CreativeDelivery::Script => script_adm,
+    };
+    let rating = media_rating(req, cfg.creative.media_rating);
+    for bid in seatbids.iter_mut().flat_map(|sb| sb.bid.iter_mut()) {
+        let imp = req.imp.iter().find(|imp| imp.id == bid.impid);
+        bid.qagmediarating = Some(rating);
+        attach_bid_meta(bid, &cfg.prebid.network_name);
+        let render_adm = if imp.is_some_and(supports_mraid) {
+            mraid_adm
+        } else {
+            default_adm
+        };
+        let bid_for_iframe = bid
+            .ext
+            .as_ref()
+            .and_then(|ext| ext["mocktioneer"].get("bid"))
+            .map(|_| bid.price);
+        let crid = bid.crid.as_deref().unwrap_or("unknown");
+        let w = bid.w.unwrap_or(300);
+        let h = bid.h.unwrap_or(250);
+        let video = imp.and_then(|imp| imp.video.as_ref());
+        bid.burl = Some(win_notice_url(base_host, &bid.impid, "billing"));
+        if imp.is_some_and(wants_no_creative) {
+            continue;
+        }
+        bid.nurl = Some(win_notice_url(base_host, &bid.impid, "win"));
+        bid.adm = Some(match video {
+            _ if bid.mtype == Some(MediaType::Native) => native_adm(base_host, crid, w, h),
+            Some(video) => vast_xml(
+                base_host,
+                crid,
+                w,
+                h,
+                video_duration(video),
+                vast_version(video.protocols.as_deref()),
+            ),
+            None => {
+                let hints = AdmHints {
+                    sandbox: cfg.creative.sandbox.clone(),
+                    mraid_resize_ms: cfg.creative.mraid_resize_ms,
+                    iframe_template: cfg.templates.iframe.clone(),
+                    ..imp.map(AdmHints::from_imp).unwrap_or_default()
+                };
+                render_adm(base_host, crid, w, h, bid_for_iframe, &metadata, &hints)
+            }
+        });
+    }
+
+    if cfg.debug.inject_duplicate_bids && !seatbids.is_empty() {
+        for seatbid in &mut seatbids {
+            if let Some(first) = seatbid.bid.first() {
+                let mut duplicate = first.clone();
+                duplicate.id = format!("{}-dup", duplicate.id);
+                seatbid.bid.push(duplicate);
+            }
+        }
+        features.record(Feature::DuplicateBids);
+    }
+
+    let mut resp = OpenRTBResponse {
+        id: response_id,
+        cur: Some(response_cur.to_string()),
+        seatbid: seatbids,
+        nbr,
+        ..Default::default()
+    };
+    let warnings = request_warnings(req);
+    if !warnings.is_empty() {
+        let ext = resp.ext.get_or_insert_with(|| json!({}));
+        ext["warnings"]["general"] = json!(warnings);
+    }
+    if !skipped.is_empty() {
+        let skipped: Vec<_> = skipped
+            .iter()
+            .map(|(impid, reason)| json!({"impid": impid, "reason": reason}))
+            .collect();
+        let ext = resp.ext.get_or_insert_with(|| json!({}));
+        ext["mocktioneer"]["skipped"] = json!(skipped);
+    }
+    if cfg.debug.enabled {
+        features.attach(&mut resp);
+    }
+    resp
+}
+
+// ============================================================================
+// APS TAM API Response Builder
+// ============================================================================
+
+/// Encode APS price using base64 for mock testing.
+///
+/// Note: Real Amazon APS uses proprietary encoding that cannot be decoded without Amazon's keys.
+/// Our mock uses transparent base64 encoding that CAN be decoded for testing/debugging purposes.
+/// Example: `echo "Mi41MA==" | base64 -d` → `2.50`
+fn encode_aps_price(price: f64) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let price_str = price.to_string();
+    STANDARD.encode(price_str.as_bytes())
+}
+
+/// Decode APS price from base64 (mock format only).
+///
+/// Returns `None` if the string is not valid base64 or doesn't contain a valid price.
+/// This only works with mocktioneer-encoded prices; real APS prices cannot be decoded.
+pub fn decode_aps_price(encoded: &str) -> Option<f64> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let decoded = STANDARD.decode(encoded).ok()?;
+    let price_str = String::from_utf8(decoded).ok()?;
+    price_str.parse().ok()
+}
+
+/// Build APS TAM response from an APS bid request matching real Amazon API format.
+///
+/// This function generates mock bids for all slots with standard sizes:
+/// - Variable bid prices based on ad size (via `get_cpm()`)
+///   - Ranges from $1.70 - $4.20 CPM for standard sizes
+///   - Example: 300x250 = $2.50, 970x250 = $4.20, 320x50 = $1.80
+/// - 100% fill rate for standard sizes
+/// - Returns contextual format matching real Amazon APS API
+/// - No creative HTML (APS doesn't return adm field)
+/// - Generates base64-encoded price strings (recoverable in mock, unlike real APS)
+pub fn build_aps_response(req: &ApsBidRequest, base_host: &str) -> ApsBidResponse {
+    build_aps_response_with_config(req, base_host, AppConfig::global())
+}
+
+/// Build APS TAM response with explicit settings.
+///
+/// When `[aps] currency` names a known currency, encoded prices are converted from
+/// USD (rounded to cents) and each slot carries an `amzncur` targeting key.
+pub fn build_aps_response_with_config(
+    req: &ApsBidRequest,
+    base_host: &str,
+    cfg: &AppConfig,
+) -> ApsBidResponse {
+    let currency = cfg.aps.currency.as_deref().filter(|cur| {
+        let known = USD_RATES.contains_key(cur);
+        if !known {
+            log::warn!("APS: Unknown currency '{}' in config, using USD", cur);
+        }
+        known
+    });
+
+    let mut slots: Vec<ApsSlotResponse> = Vec::new();
+
+    for slot in req.slots.iter() {
+        // Find the standard size with the highest CPM from all sizes in the slot
+        let best_size = slot
+            .sizes
+            .iter()
+            .filter_map(|&[w, h]| {
+                let w_i64 = w as i64;
+                let h_i64 = h as i64;
+                if cfg.auction.is_standard_size(w_i64, h_i64) {
+                    let price = get_cpm(w_i64, h_i64);
+                    Some((w, h, price))
+                } else {
+                    None
+                }
+            })
+            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+
+        let Some((w, h, price)) = best_size else {
+            // No standard sizes found, skip this slot
+            log::debug!(
+                "APS: Skipping slot '{}' - no standard sizes in {:?}",
+                slot.slot_id,
+                slot.sizes
+            );
+            continue;
+        };
+
+        // Generate bid components (price already calculated in best_size selection)
+        let impression_id = new_id();
+        let crid = format!("{}-{}", new_id(), "mocktioneer");
+        let size_str = format!("{}x{}", w, h);
+
+        // Convert into the configured currency before encoding
+        let price = match currency {
+            Some(cur) => convert_price(price, "USD", cur)
+                .map(|p| (p * 100.0).round() / 100.0)
+                .unwrap_or(price),
+            None => price,
+        };
+
+        // Generate base64-encoded price string (recoverable in mock - real APS uses proprietary encoding)
+        let encoded_price = encode_aps_price(price);
+
+        let mut targeting = vec![
+            "amzniid".to_string(),
+            "amznp".to_string(),
+            "amznsz".to_string(),
+            "amznbid".to_string(),
+            "amznactt".to_string(),
+        ];
+        if currency.is_some() {
+            targeting.push("amzncur".to_string());
+        }
+
+        // Build slot response matching real Amazon format
+        slots.push(ApsSlotResponse {
+            slot_id: slot.slot_id.clone(),
+            size: size_str.clone(),
+            crid: Some(crid),
+            media_type: Some("d".to_string()), // "d" for display
+            fif: Some("1".to_string()),        // "1" = filled
+            targeting,
+            meta: vec![
+                "slotID".to_string(),
+                "mediaType".to_string(),
+                "size".to_string(),
+            ],
+            // Targeting key-value pairs (flat on slot object)
+            amzniid: Some(impression_id),
+            amznbid: Some(encoded_price.clone()),
+            amznp: Some(encoded_price), // Same encoding for both fields
+            amznsz: Some(size_str),
+            amznactt: Some("OPEN".to_string()),
+            amzncur: currency.map(str::to_string),
+        });
+
+        log::debug!(
+            "APS: Generated bid for slot '{}' ({}x{}) at {:.2} {}",
+            slot.slot_id,
+            w,
+            h,
+            price,
+            currency.unwrap_or("USD")
+        );
+    }
+
+    ApsBidResponse {
+        contextual: ApsContextual {
+            slots,
+            host: Some(format!("https://{}", base_host)),
+            status: Some("ok".to_string()),
+            cfe: Some(true),
+            ev: Some(true),
+            cfn: Some("bao-csm/direct/csm_othersv6.js".to_string()),
+            cb: Some("6".to_string()),
+            cmp: None, // Optional campaign tracking URL
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aps::ApsSlot;
+    use crate::config::{DefaultMedia, LimitsConfig};
+    use crate::openrtb::{
+        apply_default_media, Audio, Banner, Content, Device, ExtMocktioneer, Format, ImpExt,
+        Native, Pmp, Regs, Site, User,
+    };
+
+    fn test_signature() -> SignatureStatus {
+        SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_size_from_imp_defaults_and_format() {
+        let cfg = AuctionConfig::default();
+        // Empty banner defaults to 300x250
+        let imp = OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner::default()),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp, &cfg), (300, 250));
+
+        // Uses format[0] when w/h not set
+        let imp = OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner {
+                format: Some(vec![Format {
+                    w: 320,
+                    h: 50,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp, &cfg), (320, 50));
+
+        // Prefers explicit w/h over format
+        let imp = OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner {
+                w: Some(728),
+                h: Some(90),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp, &cfg), (728, 90));
+    }
+
+    #[test]
+    fn test_size_from_imp_flexible_format() {
+        let cfg = AuctionConfig::default();
+        let imp = |fmt: Format| OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner {
+                format: Some(vec![fmt]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Ratio-only: largest standard size with the same aspect ratio
+        let ratio = Format {
+            wratio: Some(3),
+            hratio: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp(ratio), &cfg), (480, 320));
+        let ratio = Format {
+            wratio: Some(6),
+            hratio: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp(ratio), &cfg), (300, 50));
+
+        // Minimum size only: largest standard size at least that big
+        let min = Format {
+            wmin: Some(900),
+            hmin: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp(min), &cfg), (970, 250));
+
+        // No standard size fits, or nothing to go on: 300x250
+        let odd = Format {
+            wratio: Some(7),
+            hratio: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp(odd), &cfg), (300, 250));
+        assert_eq!(size_from_imp(&imp(Format::default()), &cfg), (300, 250));
+
+        // Huge ratios are compared without overflowing
+        let huge = Format {
+            wratio: Some(1),
+            hratio: Some(100_000_000_000_000_000),
+            ..Default::default()
+        };
+        assert_eq!(size_from_imp(&imp(huge), &cfg), (300, 250));
+    }
+
+    #[test]
+    fn test_marker_size_passes_through() {
+        let cfg = AuctionConfig::default();
+        assert!(is_bid_size(1, 1, &cfg));
+        assert!(!cfg.is_standard_size(1, 1));
+        assert_eq!(standard_or_default((1, 1), &cfg), (1, 1));
+        assert_eq!(standard_or_default((2, 2), &cfg), (300, 250));
+
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner {
+                    w: Some(1),
+                    h: Some(1),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!((bid.w, bid.h), (Some(1), Some(1)));
+        assert!(bid
+            .adm
+            .as_deref()
+            .unwrap()
+            .contains("/static/creatives/1x1.html"));
+    }
+
+    #[test]
+    fn test_build_openrtb_response_structure() {
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner {
+                    w: Some(300),
+                    h: Some(250),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.id, "r1");
+        assert_eq!(resp.cur.as_deref(), Some("USD"));
+        assert_eq!(resp.seatbid.len(), 1);
+        assert!(!resp.seatbid[0].bid.is_empty());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.impid, "1");
+        assert_eq!(bid.w, Some(300));
+        assert_eq!(bid.h, Some(250));
+        assert_eq!(bid.mtype, Some(MediaType::Banner));
+        assert!(bid.adm.is_some());
+    }
+
+    fn floor_imp(bidfloor: f64, bidfloorcur: Option<&str>) -> OpenrtbImp {
+        OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner::default()),
+            bidfloor: Some(bidfloor),
+            bidfloorcur: bidfloorcur.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_convert_price_uses_rate_table() {
+        assert_eq!(convert_price(2.0, "USD", "USD"), Some(2.0));
+        assert_eq!(convert_price(1.0, "USD", "JPY"), Some(150.0));
+        assert_eq!(convert_price(150.0, "JPY", "USD"), Some(1.0));
+        assert_eq!(convert_price(1.0, "USD", "XYZ"), None);
+    }
+
+    #[test]
+    fn test_floor_without_bidfloorcur_uses_configured_default() {
+        let req = OpenRTBRequest {
+            cur: Some(vec!["GBP".to_string()]),
+            ..Default::default()
+        };
+        let cfg = AuctionConfig {
+            default_floor_cur: Some("JPY".to_string()),
+            ..Default::default()
+        };
+        let imp = floor_imp(300.0, None);
+        assert_eq!(floor_currency(&imp, &req, &cfg), "JPY");
+        assert_eq!(floor_in_usd(&imp, &req, &cfg), Some(2.0));
+
+        // An explicit bidfloorcur always wins over the configured default
+        let imp = floor_imp(2.0, Some("USD"));
+        assert_eq!(floor_in_usd(&imp, &req, &cfg), Some(2.0));
+    }
+
+    #[test]
+    fn test_floor_currency_falls_back_to_request_cur_then_usd() {
+        let cfg = AuctionConfig::default();
+        let imp = floor_imp(1.0, None);
+        let req = OpenRTBRequest {
+            cur: Some(vec!["EUR".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(floor_currency(&imp, &req, &cfg), "EUR");
+        assert_eq!(
+            floor_currency(&imp, &OpenRTBRequest::default(), &cfg),
+            "USD"
+        );
+    }
+
+    #[test]
+    fn test_floor_in_usd_ignores_missing_or_unknown_floors() {
+        let req = OpenRTBRequest::default();
+        let cfg = AuctionConfig::default();
+        assert_eq!(floor_in_usd(&floor_imp(0.0, None), &req, &cfg), None);
+        assert_eq!(floor_in_usd(&floor_imp(1.0, Some("XYZ")), &req, &cfg), None);
+    }
+
+    #[test]
+    fn test_bid_exp_echoes_imp_exp() {
+        let req = OpenRTBRequest {
+            id: "r-exp".to_string(),
+            imp: vec![
+                OpenrtbImp {
+                    id: "1".to_string(),
+                    banner: Some(Banner::default()),
+                    exp: Some(300),
+                    ..Default::default()
+                },
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    banner: Some(Banner::default()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids[0].exp, Some(300));
+        assert_eq!(bids[1].exp, None);
+    }
+
+    #[test]
+    fn test_creative_delivery_selects_adm_wrapper() {
+        let req = OpenRTBRequest {
+            id: "r-delivery".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let adm = resp.seatbid[0].bid[0].adm.as_deref().unwrap();
+        assert!(adm.contains("<iframe"));
+        assert!(!adm.contains("<script"));
+
+        cfg.creative.delivery = CreativeDelivery::Script;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let adm = resp.seatbid[0].bid[0].adm.as_deref().unwrap();
+        assert!(adm.contains("<script"));
+        assert!(adm.contains("/static/creatives/300x250.html"));
+    }
+
+    #[test]
+    fn test_price_rounding_modes() {
+        let mut imp = sized_imp("1", 300, 250);
+        imp.ext = Some(ImpExt {
+            mocktioneer: Some(ExtMocktioneer {
+                bid: Some(2.347),
+                ..Default::default()
+            }),
+        });
+        let req = OpenRTBRequest {
+            id: "r-round".to_string(),
+            imp: vec![imp],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        let cases = [
+            (PriceRounding::None, 2.347),
+            (PriceRounding::Cents, 2.35),
+            (PriceRounding::DownCents, 2.34),
+        ];
+        for (rounding, price) in cases {
+            cfg.auction.price_rounding = rounding;
+            let resp =
+                build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+            assert_eq!(resp.seatbid[0].bid[0].price, price, "{:?}", rounding);
+        }
+
+        // Non-USD prices are rounded once, in their own currency, and never
+        // below the imp floor rounded up to the cent
+        let mut req = cur_request(&["EUR"]);
+        req.imp[0].ext = Some(ImpExt {
+            mocktioneer: Some(ExtMocktioneer {
+                bid: Some(2.347 / 0.92),
+                ..Default::default()
+            }),
+        });
+        cfg.auction.price_rounding = PriceRounding::DownCents;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.cur.as_deref(), Some("EUR"));
+        assert_eq!(resp.seatbid[0].bid[0].price, 2.34);
+
+        req.imp[0].bidfloor = Some(2.345);
+        req.imp[0].ext = Some(ImpExt {
+            mocktioneer: Some(ExtMocktioneer {
+                bid: Some(2.349 / 0.92),
+                ..Default::default()
+            }),
+        });
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].price, 2.35);
+
+        // Whole cents survive rounding down despite float error
+        assert_eq!(round_price(2.35, PriceRounding::DownCents), 2.35);
+        assert_eq!(round_price(0.07, PriceRounding::DownCents), 0.07);
+    }
+
+    #[test]
+    fn test_prebid_meta_matches_adomain_and_media_type() {
+        let req = OpenRTBRequest {
+            id: "r-meta".to_string(),
+            imp: vec![
+                sized_imp("1", 300, 250),
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    video: Some(Video {
+                        w: Some(640),
+                        h: Some(360),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        cfg.auction.adomain = "brand.test".to_string();
+        cfg.prebid.network_name = "Mock Network".to_string();
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids.len(), 2);
+        for (bid, media_type) in bids.iter().zip(["banner", "video"]) {
+            let meta = &bid.ext.as_ref().unwrap()["prebid"]["meta"];
+            assert_eq!(
+                meta["advertiserDomains"],
+                serde_json::json!(bid.adomain.as_ref().unwrap())
+            );
+            assert_eq!(meta["advertiserDomains"], serde_json::json!(["brand.test"]));
+            assert_eq!(meta["mediaType"], media_type);
+            assert_eq!(meta["mediaType"], bid.mtype.unwrap().as_str());
+            assert_eq!(meta["networkName"], "Mock Network");
+        }
+    }
+
+    #[test]
+    fn test_media_rating_defaults_to_all_audiences() {
+        let req = OpenRTBRequest {
+            id: "r-rating".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid[0].qagmediarating, Some(1));
+    }
+
+    #[test]
+    fn test_media_rating_from_config_capped_by_content() {
+        let mut req = OpenRTBRequest {
+            id: "r-rating".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        cfg.creative.media_rating = 3;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].qagmediarating, Some(3));
+
+        req.site = Some(Site {
+            content: Some(Content {
+                qagmediarating: Some(2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].qagmediarating, Some(2));
+
+        cfg.creative.media_rating = 9;
+        req.site = None;
+        assert_eq!(media_rating(&req, cfg.creative.media_rating), 3);
+    }
+
+    #[test]
+    fn test_templates_iframe_replaces_iframe_wrapper() {
+        let req = OpenRTBRequest {
+            id: "r-template".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        cfg.templates.iframe = Some(r#"<div class="custom">{{W}}x{{H}} {{CRID}}</div>"#.into());
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(
+            bid.adm.as_deref().unwrap(),
+            format!(
+                r#"<div class="custom">300x250 {}</div>"#,
+                bid.crid.as_deref().unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_displaymanager_echoed_in_bid_ext() {
+        let req: OpenRTBRequest = serde_json::from_value(json!({
+            "id": "r-sdk",
+            "imp": [
+                {
+                    "id": "1",
+                    "banner": {"w": 320, "h": 50},
+                    "displaymanager": "GoogleMobileAds",
+                    "displaymanagerver": "23.0.0"
+                },
+                {"id": "2", "banner": {"w": 320, "h": 50}}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            req.imp[0].displaymanager.as_deref(),
+            Some("GoogleMobileAds")
+        );
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        let ext = bids[0].ext.as_ref().unwrap();
+        assert_eq!(ext["mocktioneer"]["displaymanager"], "GoogleMobileAds");
+        assert_eq!(ext["mocktioneer"]["displaymanagerver"], "23.0.0");
+        // Only echoed bids get ext.mocktioneer; the price is not a custom bid
+        assert!(ext["mocktioneer"].get("bid").is_none());
+        assert!(bids[1].ext.as_ref().unwrap().get("mocktioneer").is_none());
+    }
+
+    #[test]
+    fn test_shading_factor_lowers_price_and_keeps_original() {
+        let req = OpenRTBRequest {
+            id: "r-shade".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        cfg.auction.default_bid_price = Some(4.00);
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].price, 4.00);
+        assert!(resp.seatbid[0].bid[0]
+            .ext
+            .as_ref()
+            .unwrap()
+            .get("mocktioneer")
+            .is_none());
+
+        cfg.auction.shading_factor = Some(0.25);
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.price, 3.00);
+        assert_eq!(
+            bid.ext.as_ref().unwrap()["mocktioneer"]["original_price"],
+            4.0
+        );
+
+        // The shaded price stops at the floor
+        let mut floored = req.clone();
+        floored.imp[0].bidfloor = Some(3.50);
+        let resp =
+            build_openrtb_response_with_config(&floored, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].price, 3.50);
+    }
+
+    #[test]
+    fn test_inject_duplicate_bids_repeats_impid() {
+        let req = OpenRTBRequest {
+            id: "r-dup".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid.len(), 1);
+
+        cfg.debug.inject_duplicate_bids = true;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids.len(), 2);
+        assert_eq!(bids[0].impid, bids[1].impid);
+        assert_eq!(bids[1].id, format!("{}-dup", bids[0].id));
+    }
+
+    fn deal_imp(deals: Vec<Deal>) -> OpenrtbImp {
+        OpenrtbImp {
+            id: "1".to_string(),
+            banner: Some(Banner::default()),
+            pmp: Some(Pmp {
+                deals: Some(deals),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn deal(id: &str, wseat: Option<&[&str]>) -> Deal {
+        Deal {
+            id: id.to_string(),
+            wseat: wseat.map(|w| w.iter().map(|s| s.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matching_deal_respects_wseat() {
+        let imp = deal_imp(vec![
+            deal("other-seat-deal", Some(&["someone-else"])),
+            deal("our-deal", Some(&["someone-else", SEAT])),
+        ]);
+        assert_eq!(matching_deal(&imp, SEAT).unwrap().id, "our-deal");
+        assert!(matching_deal(&imp, "third-party").is_none());
+
+        // Missing or empty wseat means any seat may use the deal
+        let imp = deal_imp(vec![deal("open", None), deal("empty", Some(&[]))]);
+        assert_eq!(matching_deal(&imp, "anyone").unwrap().id, "open");
+        let imp = deal_imp(vec![deal("empty", Some(&[]))]);
+        assert_eq!(matching_deal(&imp, "anyone").unwrap().id, "empty");
+    }
+
+    #[test]
+    fn test_build_openrtb_response_sets_dealid_only_for_allowed_seat() {
+        let mut req = OpenRTBRequest {
+            id: "r-deal".to_string(),
+            imp: vec![deal_imp(vec![deal("blocked", Some(&["someone-else"]))])],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid[0].dealid, None);
+
+        req.imp = vec![deal_imp(vec![deal("allowed", Some(&[SEAT]))])];
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid[0].dealid.as_deref(), Some("allowed"));
+    }
+
+    #[test]
+    fn test_private_auction_only_returns_deal_bids() {
+        let private_imp = |private_auction, deals| {
+            let mut imp = deal_imp(deals);
+            imp.pmp.as_mut().unwrap().private_auction = Some(private_auction);
+            imp
+        };
+        let mut req = OpenRTBRequest {
+            id: "r-private".to_string(),
+            imp: vec![private_imp(
+                1,
+                vec![deal("elsewhere", Some(&["someone-else"]))],
+            )],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_NO_DEAL));
+
+        req.imp = vec![private_imp(1, vec![deal("ours", Some(&[SEAT]))])];
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid[0].dealid.as_deref(), Some("ours"));
+
+        // An open auction still takes the open-market bid
+        req.imp = vec![private_imp(
+            0,
+            vec![deal("elsewhere", Some(&["someone-else"]))],
+        )];
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.dealid, None);
+        assert!(resp.nbr.is_none());
+    }
+
+    #[test]
+    fn test_build_openrtb_response_respects_deal_floor() {
+        let mut high = deal("pmp-high", None);
+        high.bidfloor = Some(7.5);
+        let req = OpenRTBRequest {
+            id: "r-deal-floor".to_string(),
+            imp: vec![deal_imp(vec![high])],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.dealid.as_deref(), Some("pmp-high"));
+        assert_eq!(bid.price, 7.5);
+
+        // A floor under our default price leaves the price alone
+        let mut low = deal("pmp-low", None);
+        low.bidfloor = Some(0.01);
+        let req = OpenRTBRequest {
+            id: "r-deal-floor".to_string(),
+            imp: vec![deal_imp(vec![low])],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.dealid.as_deref(), Some("pmp-low"));
+        assert!(bid.price > 0.01);
+    }
+
+    #[test]
+    fn test_deal_floor_converts_currency() {
+        let mut eur = deal("eur", None);
+        eur.bidfloor = Some(4.6);
+        eur.bidfloorcur = Some("EUR".to_string());
+        assert_eq!(deal_floor_in_usd(&eur), Some(5.0));
+
+        eur.bidfloorcur = Some("XXX".to_string());
+        assert_eq!(deal_floor_in_usd(&eur), None);
+        assert_eq!(deal_floor_in_usd(&deal("none", None)), None);
+    }
+
+    #[test]
+    fn test_sort_bids_puts_highest_price_first() {
+        let imp = |id: &str, w: i64, h: i64| OpenrtbImp {
+            id: id.to_string(),
+            banner: Some(Banner {
+                w: Some(w),
+                h: Some(h),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let req = OpenRTBRequest {
+            id: "r-sort".to_string(),
+            imp: vec![imp("a", 320, 50), imp("b", 970, 250), imp("c", 300, 250)],
+            ..Default::default()
+        };
+
+        // Default keeps imp order
+        let mut cfg = AppConfig::default();
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let order: Vec<&str> = resp.seatbid[0]
+            .bid
+            .iter()
+            .map(|b| b.impid.as_str())
+            .collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+
+        cfg.auction.sort_bids = true;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let prices: Vec<f64> = resp.seatbid[0].bid.iter().map(|b| b.price).collect();
+        assert_eq!(prices, vec![4.20, 2.50, 1.80]);
+        assert_eq!(resp.seatbid[0].bid[0].impid, "b");
+    }
+
+    #[test]
+    fn test_slow_connection_gets_light_creative() {
+        let adm = |connectiontype: i64| {
+            let req = OpenRTBRequest {
+                id: "r-conn".to_string(),
+                imp: vec![sized_imp("1", 300, 250)],
+                device: Some(Device {
+                    connectiontype: Some(connectiontype),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let resp = build_openrtb_response(&req, "host.test", test_signature());
+            resp.seatbid[0].bid[0].adm.clone().unwrap()
+        };
+        // 2G: the SVG placeholder in a click-through link, no HTML creative
+        let light = adm(4);
+        assert!(light.contains("//host.test/static/img/300x250.svg"));
+        // Handlebars entity-escapes the click URL inside the href attribute
+        assert!(light.contains(r#"<a href="//host.test/click?crid&#x3D;mocktioneer-1&amp;w"#));
+        assert!(!light.contains("<iframe"));
+        assert!(!light.contains("/static/creatives/"));
+        assert!(slow_connection(&OpenRTBRequest {
+            device: Some(Device {
+                connectiontype: Some(5),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+
+        // WiFi: the full creative
+        let full = adm(2);
+        assert!(full.contains("<iframe"));
+        assert!(full.contains("/static/creatives/300x250.html"));
+    }
+
+    #[test]
+    fn test_include_reqid_puts_request_id_in_crid() {
+        let mut req = OpenRTBRequest {
+            id: "r-77".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(
+            resp.seatbid[0].bid[0].crid.as_deref(),
+            Some("mocktioneer-1")
+        );
+
+        req.ext = Some(serde_json::json!({"mocktioneer": {"include_reqid": true}}));
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.crid.as_deref(), Some("mocktioneer-r-77-1"));
+        assert_eq!(bid.adid.as_deref(), Some("ad-mocktioneer-r-77-1"));
+        let adm = bid.adm.as_deref().unwrap();
+        assert!(adm.contains("/static/creatives/300x250.html?crid=mocktioneer-r-77-1&"));
+
+        // The light creative links the click-through straight to the crid
+        req.device = Some(Device {
+            connectiontype: Some(4),
+            ..Default::default()
+        });
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let adm = resp.seatbid[0].bid[0].adm.clone().unwrap();
+        assert!(adm.contains("click?crid&#x3D;mocktioneer-r-77-1&amp;"));
+    }
+
+    #[test]
+    fn test_crid_from_ext_is_echoed_in_bid_and_iframe() {
+        let req = OpenRTBRequest {
+            id: "r-crid".to_string(),
+            imp: vec![
+                OpenrtbImp {
+                    id: "1".to_string(),
+                    banner: Some(Banner::default()),
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            crid: Some("approved-creative-42".to_string()),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                },
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    banner: Some(Banner::default()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids[0].crid.as_deref(), Some("approved-creative-42"));
+        assert!(bids[0]
+            .adm
+            .as_ref()
+            .unwrap()
+            .contains("?crid=approved-creative-42&"));
+        // Without the extension the generated crid is kept
+        assert_eq!(bids[1].crid.as_deref(), Some("mocktioneer-2"));
+    }
+
+    #[test]
+    fn test_bids_carry_win_and_billing_notice_urls() {
+        let req = OpenRTBRequest {
+            id: "r-nurl".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "slot 1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(
+            bid.nurl.as_deref(),
+            Some("//host.test/winnotice?imp=slot+1&price=${AUCTION_PRICE}&event=win")
+        );
+        assert_eq!(
+            bid.burl.as_deref(),
+            Some("//host.test/winnotice?imp=slot+1&price=${AUCTION_PRICE}&event=billing")
+        );
+    }
+
+    #[test]
+    fn test_adid_derived_from_crid_or_echoed_from_ext() {
+        let req = OpenRTBRequest {
+            id: "r-adid".to_string(),
+            imp: vec![
+                OpenrtbImp {
+                    id: "1".to_string(),
+                    banner: Some(Banner::default()),
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            adid: Some("trafficked-ad-7".to_string()),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                },
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    banner: Some(Banner::default()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids[0].adid.as_deref(), Some("trafficked-ad-7"));
+        assert_eq!(bids[1].adid.as_deref(), Some("ad-mocktioneer-2"));
+    }
+
+    #[test]
+    fn test_max_seats_caps_configured_seats() {
+        let req = OpenRTBRequest {
+            id: "r-seats".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let cfg = AppConfig {
+            seats: (1..=20)
+                .map(|i| SeatConfig {
+                    name: format!("seat-{}", i),
+                    ..Default::default()
+                })
+                .collect(),
+            limits: LimitsConfig {
+                max_seats: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid.len(), 5);
+        let seats: Vec<&str> = resp
+            .seatbid
+            .iter()
+            .map(|sb| sb.seat.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            seats,
+            vec!["seat-1", "seat-2", "seat-3", "seat-4", "seat-5"]
+        );
+        // Generated crids are prefixed with the seat name
+        assert_eq!(resp.seatbid[4].bid[0].crid.as_deref(), Some("seat-5-1"));
+        assert!(resp.seatbid[4].bid[0].adm.is_some());
+    }
+
+    #[test]
+    fn test_nearest_standard_size() {
+        let cfg = AuctionConfig::default();
+        assert_eq!(nearest_standard_size(300, 250, &cfg), (300, 250));
+        assert_eq!(nearest_standard_size(333, 222, &cfg), (300, 250));
+        assert_eq!(nearest_standard_size(730, 95, &cfg), (728, 90));
+        assert_eq!(nearest_standard_size(0, 0, &cfg), (300, 50));
+        // Huge sizes pick the largest standard size instead of overflowing
+        assert_eq!(nearest_standard_size(4_000_000_000, 1, &cfg), (970, 90));
+        assert_eq!(nearest_standard_size(i64::MAX, i64::MIN, &cfg), (970, 90));
+    }
+
+    #[test]
+    fn test_default_media_imp_gets_banner_bid() {
+        let mut req = OpenRTBRequest {
+            id: "r-lenient".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        apply_default_media(&mut req, DefaultMedia::Banner);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.impid, "1");
+        assert_eq!((bid.w, bid.h), (Some(300), Some(250)));
+        assert_eq!(bid.mtype, Some(MediaType::Banner));
+    }
+
+    #[test]
+    fn test_mraid_banner_gets_mraid_creative() {
+        let imp = |id: &str, api: Option<Vec<i64>>| OpenrtbImp {
+            id: id.to_string(),
+            banner: Some(Banner {
+                w: Some(320),
+                h: Some(50),
+                api,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let req = OpenRTBRequest {
+            id: "r-mraid".to_string(),
+            imp: vec![imp("mraid", Some(vec![5])), imp("plain", Some(vec![7]))],
+            ..Default::default()
+        };
+        assert!(supports_mraid(&req.imp[0]));
+        assert!(!supports_mraid(&req.imp[1]));
+
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        let mraid = bids[0].adm.as_deref().unwrap();
+        assert!(mraid.contains("mraid.js"));
+        assert!(mraid.contains("mraid.addEventListener"));
+        let plain = bids[1].adm.as_deref().unwrap();
+        assert!(!plain.contains("mraid.js"));
+        assert!(plain.contains("<iframe"));
+    }
+
+    #[test]
+    fn test_expdir_and_clickbrowser_reach_adm() {
+        let req = OpenRTBRequest {
+            id: "r-expand".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner {
+                    w: Some(300),
+                    h: Some(250),
+                    expdir: Some(vec![2]),
+                    ..Default::default()
+                }),
+                clickbrowser: Some(0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let adm = resp.seatbid[0].bid[0].adm.as_deref().unwrap();
+        assert!(adm.contains(r#"data-expdir="right""#));
+        assert!(adm.contains("width:600px;height:250px"));
+        assert!(adm.contains("&clickbrowser=0"));
+    }
+
+    #[test]
+    fn test_safeframe_toggle_reaches_adm() {
+        let imp = |id: &str, safeframe: Option<bool>| OpenrtbImp {
+            id: id.to_string(),
+            banner: Some(Banner {
+                w: Some(300),
+                h: Some(250),
+                ..Default::default()
+            }),
+            ext: Some(ImpExt {
+                mocktioneer: Some(ExtMocktioneer {
+                    safeframe,
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        };
+        let req = OpenRTBRequest {
+            id: "r-safeframe".to_string(),
+            imp: vec![imp("1", Some(true)), imp("2", None)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let adm = |impid: &str| {
+            resp.seatbid
+                .iter()
+                .flat_map(|sb| &sb.bid)
+                .find(|b| b.impid == impid)
+                .and_then(|b| b.adm.clone())
+                .unwrap()
+        };
+        assert!(adm("1").contains("&safeframe=true"));
+        assert!(!adm("2").contains("&safeframe=true"));
+    }
+
+    #[test]
+    fn test_default_bid_price_from_config() {
+        let cfg = AppConfig::from_toml_str("[auction]\ndefault_bid_price = 4.2").unwrap();
+        let req = OpenRTBRequest {
+            id: "r-default-price".to_string(),
+            imp: vec![
+                OpenrtbImp {
+                    id: "1".to_string(),
+                    banner: Some(Banner {
+                        w: Some(300),
+                        h: Some(250),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    banner: Some(Banner::default()),
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            bid: Some(9.99),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids[0].price, 4.2);
+        // An explicit ext bid still wins over the configured default
+        assert_eq!(bids[1].price, 9.99);
+
+        // Without the key, pricing stays size-based
+        let resp = build_openrtb_response_with_config(
+            &req,
+            "host.test",
+            test_signature(),
+            &AppConfig::default(),
+        );
+        assert_eq!(resp.seatbid[0].bid[0].price, get_cpm(300, 250));
+    }
+
+    #[test]
+    fn test_seat_currency_converts_and_tags_bids() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [[seat]]
+            name = "usd-seat"
+
+            [[seat]]
+            name = "eur-seat"
+            currency = "EUR"
+            "#,
+        )
+        .unwrap();
+        let req = OpenRTBRequest {
+            id: "r-cur".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ext: Some(ImpExt {
+                    mocktioneer: Some(ExtMocktioneer {
+                        bid: Some(2.5),
+                        ..Default::default()
+                    }),
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.cur.as_deref(), Some("USD"));
+
+        let usd = &resp.seatbid[0];
+        assert_eq!(usd.bid[0].price, 2.5);
+        assert!(usd.ext.is_none());
+
+        let eur = &resp.seatbid[1];
+        assert_eq!(eur.seat.as_deref(), Some("eur-seat"));
+        assert_eq!(eur.ext.as_ref().unwrap()["mocktioneer"]["cur"], "EUR");
+        let bid = &eur.bid[0];
+        // Converted, but left unrounded without `price_rounding`
+        assert!((bid.price - 2.3).abs() < 1e-9);
+        let ext = bid.ext.as_ref().unwrap();
+        assert_eq!(ext["mocktioneer"]["cur"], "EUR");
+        assert_eq!(ext["mocktioneer"]["bid"], 2.5);
+        assert!(bid.adm.as_deref().unwrap().contains("bid=2.30"));
+    }
+
+    fn cur_request(cur: &[&str]) -> OpenRTBRequest {
+        OpenRTBRequest {
+            id: "r-cur".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ext: Some(ImpExt {
+                    mocktioneer: Some(ExtMocktioneer {
+                        bid: Some(2.5),
+                        ..Default::default()
+                    }),
+                }),
+                ..Default::default()
+            }],
+            cur: Some(cur.iter().map(|c| c.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_request_currency_converts_response() {
+        let resp = build_openrtb_response(&cur_request(&["EUR"]), "host.test", test_signature());
+        assert_eq!(resp.cur.as_deref(), Some("EUR"));
+        let bid = &resp.seatbid[0].bid[0];
+        assert!((bid.price - 2.3).abs() < 1e-9);
+        assert!(bid.adm.as_deref().unwrap().contains("bid=2.30"));
+
+        // Several currencies leave the choice to us: USD
+        let resp =
+            build_openrtb_response(&cur_request(&["EUR", "GBP"]), "host.test", test_signature());
+        assert_eq!(resp.cur.as_deref(), Some("USD"));
+        assert_eq!(resp.seatbid[0].bid[0].price, 2.5);
+    }
+
+    #[test]
+    fn test_unknown_request_currency_falls_back_to_usd() {
+        let resp = build_openrtb_response(&cur_request(&["XYZ"]), "host.test", test_signature());
+        assert_eq!(resp.cur.as_deref(), Some("USD"));
+        assert_eq!(resp.seatbid[0].bid[0].price, 2.5);
+    }
+
+    fn multi_bid_request(num_bids: &[i64]) -> OpenRTBRequest {
+        OpenRTBRequest {
+            id: "r-multi".to_string(),
+            imp: num_bids
+                .iter()
+                .enumerate()
+                .map(|(i, n)| OpenrtbImp {
+                    id: (i + 1).to_string(),
+                    banner: Some(Banner {
+                        w: Some(300),
+                        h: Some(250),
+                        ..Default::default()
+                    }),
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            num_bids: Some(*n),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_num_bids_returns_ranked_seats() {
+        let req = multi_bid_request(&[3]);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+
+        let seats: Vec<&str> = resp
+            .seatbid
+            .iter()
+            .map(|sb| sb.seat.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            seats,
+            vec!["mocktioneer-1", "mocktioneer-2", "mocktioneer-3"]
+        );
+
+        let bids: Vec<&OpenrtbBid> = resp.seatbid.iter().flat_map(|sb| &sb.bid).collect();
+        assert_eq!(bids.len(), 3);
+        assert!(bids.iter().all(|b| b.impid == "1"));
+
+        let ids: std::collections::HashSet<&str> = bids.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids.len(), 3);
+        let crids: Vec<&str> = bids.iter().map(|b| b.crid.as_deref().unwrap()).collect();
+        assert_eq!(
+            crids,
+            vec!["mocktioneer-1", "mocktioneer-1-2", "mocktioneer-1-3"]
+        );
+
+        let base = get_cpm(300, 250);
+        let prices: Vec<f64> = bids.iter().map(|b| b.price).collect();
+        assert_eq!(prices[0], base);
+        assert!(prices.windows(2).all(|p| p[0] > p[1]), "{:?}", prices);
+        assert_eq!(prices[2], (base * 0.8 * 100.0).round() / 100.0);
+    }
+
+    #[test]
+    fn test_num_bids_bounds() {
+        assert_eq!(num_bids(&multi_bid_request(&[0]).imp[0]), 1);
+        assert_eq!(num_bids(&multi_bid_request(&[-4]).imp[0]), 1);
+        assert_eq!(num_bids(&multi_bid_request(&[50]).imp[0]), 10);
+
+        // Zero and negative counts behave like a plain single-bid request
+        let resp =
+            build_openrtb_response(&multi_bid_request(&[0, -1]), "host.test", test_signature());
+        assert_eq!(resp.seatbid.len(), 1);
+        assert_eq!(resp.seatbid[0].seat.as_deref(), Some(SEAT));
+        assert_eq!(resp.seatbid[0].bid.len(), 2);
+
+        let resp = build_openrtb_response(&multi_bid_request(&[50]), "host.test", test_signature());
+        assert_eq!(resp.seatbid.len(), MAX_BIDS_PER_IMP as usize);
+    }
+
+    #[test]
+    fn test_auction_type_sets_winning_price() {
+        let prices = |at: Option<i64>| -> Vec<f64> {
+            let req = OpenRTBRequest {
+                at,
+                ..multi_bid_request(&[2])
+            };
+            let resp = build_openrtb_response(&req, "host.test", test_signature());
+            resp.seatbid
+                .iter()
+                .flat_map(|sb| &sb.bid)
+                .map(|b| b.price)
+                .collect()
+        };
+        // First price (and no `at`): bids as computed
+        assert_eq!(prices(Some(1)), vec![2.5, 2.25]);
+        assert_eq!(prices(None), vec![2.5, 2.25]);
+        // Second price: the winner clears a cent above the runner-up
+        assert_eq!(prices(Some(AUCTION_SECOND_PRICE)), vec![2.26, 2.25]);
+
+        let req = OpenRTBRequest {
+            at: Some(AUCTION_SECOND_PRICE),
+            ..multi_bid_request(&[2])
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let winner = &resp.seatbid[0].bid[0];
+        assert_eq!(
+            winner.ext.as_ref().unwrap()["mocktioneer"]["first_price"],
+            2.5
+        );
+
+        // A lone bid has no runner-up to clear against
+        let mut req = OpenRTBRequest {
+            at: Some(AUCTION_SECOND_PRICE),
+            ..multi_bid_request(&[1])
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid[0].price, 2.5);
+
+        // ...so with a floor it clears at the floor, rounded up to the cent
+        req.imp[0].bidfloor = Some(1.234);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.price, 1.24);
+        assert_eq!(bid.ext.as_ref().unwrap()["mocktioneer"]["first_price"], 2.5);
+    }
+
+    #[test]
+    fn test_max_bids_per_imp_caps_requested_bids() {
+        let mut cfg = AppConfig::default();
+        cfg.limits.max_bids_per_imp = Some(3);
+        let req = multi_bid_request(&[10, 2]);
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let count = |impid: &str| {
+            resp.seatbid
+                .iter()
+                .flat_map(|sb| &sb.bid)
+                .filter(|b| b.impid == impid)
+                .count()
+        };
+        assert_eq!(count("1"), 3);
+        assert_eq!(count("2"), 2);
+
+        // The cap is per imp across every seat
+        cfg.seats.push(SeatConfig {
+            name: "second".to_string(),
+            ..Default::default()
+        });
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bids = resp.seatbid.iter().flat_map(|sb| &sb.bid);
+        assert_eq!(bids.filter(|b| b.impid == "1").count(), 3);
+
+        // Uncapped, every seat makes all the bids asked for
+        cfg.limits.max_bids_per_imp = None;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bids = resp.seatbid.iter().flat_map(|sb| &sb.bid);
+        assert_eq!(bids.filter(|b| b.impid == "1").count(), 20);
+    }
+
+    #[test]
+    fn test_num_bids_with_mixed_counts_and_sorting() {
+        let mut cfg = AppConfig::default();
+        cfg.auction.sort_bids = true;
+        let req = multi_bid_request(&[1, 2]);
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert_eq!(resp.seatbid.len(), 2);
+        // Rank 1 holds both imps, rank 2 only the imp that asked for two bids
+        assert_eq!(resp.seatbid[0].bid.len(), 2);
+        let rank2: Vec<&str> = resp.seatbid[1]
+            .bid
+            .iter()
+            .map(|b| b.impid.as_str())
+            .collect();
+        assert_eq!(rank2, vec!["2"]);
+    }
+
+    #[test]
+    fn test_video_imp_gets_vast_adm() {
+        let req = OpenRTBRequest {
+            id: "r-video".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "v1".to_string(),
+                video: Some(Video {
+                    w: Some(640),
+                    h: Some(360),
+                    minduration: Some(20),
+                    maxduration: Some(30),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.mtype, Some(MediaType::Video));
+        assert_eq!((bid.w, bid.h), (Some(640), Some(360)));
+        let adm = bid.adm.as_deref().unwrap();
+        assert!(adm.contains(r#"<VAST version="3.0">"#));
+        assert!(adm.contains("<MediaFile"));
+        assert!(adm.contains("//host.test/static/video/640x360.mp4"));
+        assert!(adm.contains("<Duration>00:00:20</Duration>"));
+        let click = format!(
+            "<ClickThrough><![CDATA[//host.test/click?crid={}&w=640&h=360]]></ClickThrough>",
+            bid.crid.as_deref().unwrap()
+        );
+        assert!(adm.contains(&click), "{}", adm);
+    }
+
+    #[test]
+    fn test_native_imp_gets_native_adm_with_click_link() {
+        let req = OpenRTBRequest {
+            id: "r-native".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "n1".to_string(),
+                native: Some(Native::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.ext.is_none());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.mtype, Some(MediaType::Native));
+        assert_eq!((bid.w, bid.h), (Some(300), Some(250)));
+        let adm: serde_json::Value = serde_json::from_str(bid.adm.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            adm["native"]["link"]["url"],
+            format!(
+                "//host.test/click?crid={}&w=300&h=250",
+                bid.crid.as_deref().unwrap()
+            )
+        );
+        assert_eq!(
+            adm["native"]["assets"][1]["img"]["url"],
+            "//host.test/static/img/300x250.svg"
+        );
+        assert!(adm["native"]["imptrackers"][0]
+            .as_str()
+            .unwrap()
+            .starts_with("//host.test/pixel?pid="));
+    }
+
+    #[test]
+    fn test_vast_redirect_url_needs_single_video_imp() {
+        let video_imp = |id: &str| OpenrtbImp {
+            id: id.to_string(),
+            video: Some(Video {
+                w: Some(640),
+                h: Some(360),
+                minduration: Some(20),
+                protocols: Some(vec![7]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let req = OpenRTBRequest {
+            id: "r-video".to_string(),
+            imp: vec![video_imp("v1")],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let url = vast_redirect_url(&req, &resp, "host.test").unwrap();
+        assert!(url.starts_with("//host.test/vast?crid="));
+        assert!(url.ends_with("&w=640&h=360&dur=20&ver=4.0"));
+
+        // Several imps, banner imps and unbid imps stay inline
+        let req = OpenRTBRequest {
+            imp: vec![video_imp("v1"), video_imp("v2")],
+            ..req
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(vast_redirect_url(&req, &resp, "host.test"), None);
+        let banner = cur_request(&[]);
+        let resp = build_openrtb_response(&banner, "host.test", test_signature());
+        assert_eq!(vast_redirect_url(&banner, &resp, "host.test"), None);
+        let req = OpenRTBRequest {
+            imp: vec![video_imp("v1")],
+            ..req
+        };
+        assert_eq!(
+            vast_redirect_url(&req, &OpenRTBResponse::default(), "host.test"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_video_protocols_pick_vast_version() {
+        let vast_adm = |protocols: Vec<i64>| {
+            let req = OpenRTBRequest {
+                id: "r-video".to_string(),
+                imp: vec![OpenrtbImp {
+                    id: "v1".to_string(),
+                    video: Some(Video {
+                        w: Some(640),
+                        h: Some(360),
+                        protocols: Some(protocols),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            let resp = build_openrtb_response(&req, "host.test", test_signature());
+            resp.seatbid[0].bid[0].adm.clone().unwrap()
+        };
+        assert!(vast_adm(vec![2]).contains(r#"<VAST version="2.0">"#));
+        assert!(vast_adm(vec![2, 3, 7, 11]).contains(r#"<VAST version="4.1">"#));
+    }
+
+    #[test]
+    fn test_video_bid_reports_protocol_and_api() {
+        let protocols = vec![2, 3, 6];
+        let req = OpenRTBRequest {
+            id: "r-video".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "v1".to_string(),
+                video: Some(Video {
+                    protocols: Some(protocols.clone()),
+                    api: Some(vec![3, 2, 7]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        let protocol = bid.protocol.unwrap();
+        assert!(protocols.contains(&protocol));
+        assert_eq!(protocol, 3);
+        assert!(bid
+            .adm
+            .as_deref()
+            .unwrap()
+            .contains(r#"<VAST version="3.0">"#));
+        assert_eq!(bid.api, Some(7));
+
+        // Banner bids carry neither
+        let resp = build_openrtb_response(&cur_request(&[]), "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!((bid.protocol, bid.api), (None, None));
+    }
+
+    #[test]
+    fn test_video_duration_respects_bounds() {
+        let video = |min, max| Video {
+            minduration: min,
+            maxduration: max,
+            ..Default::default()
+        };
+        assert_eq!(video_duration(&video(None, None)), 15);
+        assert_eq!(video_duration(&video(Some(5), Some(10))), 10);
+        assert_eq!(video_duration(&video(Some(30), None)), 30);
+        // An inverted range is ignored beyond the minimum
+        assert_eq!(video_duration(&video(Some(20), Some(10))), 20);
+        assert_eq!(video_size(&Video::default()), (640, 480));
+    }
+
+    #[test]
+    fn test_get_cpm_caps_huge_sizes_without_overflow() {
+        let huge = 4_000_000_000;
+        assert_eq!(get_cpm(huge, huge), DEFAULT_CPM + MAX_AREA_BONUS);
+        assert_eq!(get_cpm(i64::MAX, i64::MAX), DEFAULT_CPM + MAX_AREA_BONUS);
+    }
+
+    fn floor_request(floors: &[Option<f64>]) -> OpenRTBRequest {
+        OpenRTBRequest {
+            id: "r-floor".to_string(),
+            imp: floors
+                .iter()
+                .enumerate()
+                .map(|(i, floor)| OpenrtbImp {
+                    id: (i + 1).to_string(),
+                    banner: Some(Banner {
+                        w: Some(300),
+                        h: Some(250),
+                        ..Default::default()
+                    }),
+                    bidfloor: *floor,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_floor_above_price_is_no_bid() {
+        let price = get_cpm(300, 250);
+        let req = floor_request(&[Some(price + 1.0)]);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_BELOW_FLOOR));
+    }
+
+    #[test]
+    fn test_floor_below_price_bids() {
+        let price = get_cpm(300, 250);
+        let req = floor_request(&[Some(price - 1.0)]);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.seatbid[0].bid.len(), 1);
+        assert_eq!(resp.seatbid[0].bid[0].price, price);
+        assert!(resp.nbr.is_none());
+    }
+
+    #[test]
+    fn test_mixed_floors_skip_only_unmet_imps() {
+        let price = get_cpm(300, 250);
+        let req = floor_request(&[Some(price + 1.0), None, Some(price)]);
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let impids: Vec<&str> = resp.seatbid[0]
+            .bid
+            .iter()
+            .map(|b| b.impid.as_str())
+            .collect();
+        assert_eq!(impids, vec!["2", "3"]);
+        assert!(resp.nbr.is_none());
+
+        // Floors in another currency are converted before comparing
+        let mut req = floor_request(&[Some(price)]);
+        req.imp[0].bidfloorcur = Some("GBP".to_string());
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(resp.nbr, Some(NBR_BELOW_FLOOR));
+    }
+
+    fn no_bid_imp(id: &str, no_bid: bool) -> OpenrtbImp {
+        OpenrtbImp {
+            id: id.to_string(),
+            banner: Some(Banner {
+                w: Some(300),
+                h: Some(250),
+                ..Default::default()
+            }),
+            ext: Some(ImpExt {
+                mocktioneer: Some(ExtMocktioneer {
+                    no_bid: Some(no_bid),
+                    ..Default::default()
+                }),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_no_bid_imp_yields_empty_seatbid_with_nbr() {
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![no_bid_imp("1", true)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_NO_BID));
+        let json = serde_json::to_value(&resp).unwrap();
+        assert_eq!(json["seatbid"], serde_json::json!([]));
+        assert_eq!(json["nbr"], 0);
+    }
+
+    #[test]
+    fn test_no_bid_imp_skipped_alongside_bidding_imp() {
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![no_bid_imp("1", true), no_bid_imp("2", false)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let impids: Vec<&str> = resp.seatbid[0]
+            .bid
+            .iter()
+            .map(|b| b.impid.as_str())
+            .collect();
+        assert_eq!(impids, vec!["2"]);
+        assert!(resp.nbr.is_none());
+    }
+
+    #[test]
+    fn test_no_creative_imp_bids_without_adm_or_nurl() {
+        let mut imp = no_bid_imp("1", false);
+        imp.ext
+            .as_mut()
+            .unwrap()
+            .mocktioneer
+            .as_mut()
+            .unwrap()
+            .no_creative = Some(true);
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![imp, no_bid_imp("2", false)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bids = &resp.seatbid[0].bid;
+        assert_eq!(bids[0].impid, "1");
+        assert!(bids[0].adm.is_none());
+        assert!(bids[0].nurl.is_none());
+        assert!(bids[0].price > 0.0);
+        assert_eq!((bids[0].w, bids[0].h), (Some(300), Some(250)));
+
+        // Other imps keep their markup
+        assert!(bids[1].adm.is_some());
+        assert!(bids[1].nurl.is_some());
+    }
+
+    #[test]
+    fn test_audio_only_imp_is_unsupported_not_banner() {
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![OpenrtbImp {
+                id: "a1".to_string(),
+                audio: Some(Audio::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_UNSUPPORTED_MEDIA));
+        let skipped = &resp.ext.as_ref().unwrap()["mocktioneer"]["skipped"];
+        assert_eq!(
+            skipped,
+            &serde_json::json!([{"impid": "a1", "reason": "unsupported_media"}])
+        );
+    }
+
+    #[test]
+    fn test_deprecated_fields_reported_as_warnings() {
+        let mut req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![no_bid_imp("1", false)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.ext.is_none_or(|ext| ext.get("warnings").is_none()));
+
+        req.user = Some(User {
+            gender: Some("M".to_string()),
+            ..Default::default()
+        });
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(!resp.seatbid.is_empty());
+        assert_eq!(
+            resp.ext.unwrap()["warnings"]["general"],
+            serde_json::json!([
+                {"field": "user.gender", "message": "deprecated in OpenRTB 2.6"}
+            ])
+        );
+    }
+
+    fn sized_imp(id: &str, w: i64, h: i64) -> OpenrtbImp {
+        OpenrtbImp {
+            id: id.to_string(),
+            banner: Some(Banner {
+                w: Some(w),
+                h: Some(h),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn seat_list_request(wseat: &[&str], bseat: &[&str]) -> OpenRTBRequest {
+        let list = |seats: &[&str]| Some(seats.iter().map(|s| s.to_string()).collect());
+        OpenRTBRequest {
+            id: "r-seats".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            wseat: list(wseat),
+            bseat: list(bseat),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bseat_blocks_our_seat() {
+        let resp = build_openrtb_response(
+            &seat_list_request(&[], &["mocktioneer"]),
+            "host.test",
+            test_signature(),
+        );
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_SEAT_BLOCKED));
+    }
+
+    #[test]
+    fn test_wseat_without_our_seat_blocks_it() {
+        let resp = build_openrtb_response(
+            &seat_list_request(&["other-dsp"], &[]),
+            "host.test",
+            test_signature(),
+        );
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_SEAT_BLOCKED));
+    }
+
+    #[test]
+    fn test_seat_lists_allowing_our_seat_bid() {
+        for req in [
+            seat_list_request(&["other-dsp", "mocktioneer"], &["other-dsp"]),
+            seat_list_request(&[], &[]),
+        ] {
+            let resp = build_openrtb_response(&req, "host.test", test_signature());
+            assert_eq!(resp.seatbid[0].seat.as_deref(), Some("mocktioneer"));
+            assert!(resp.nbr.is_none());
+        }
+
+        // Only the excluded one of several seats drops out
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [[seat]]
+            name = "alpha"
+
+            [[seat]]
+            name = "beta"
+            "#,
+        )
+        .unwrap();
+        let resp = build_openrtb_response_with_config(
+            &seat_list_request(&[], &["alpha"]),
+            "host.test",
+            test_signature(),
+            &cfg,
+        );
+        let seats: Vec<_> = resp.seatbid.iter().map(|sb| sb.seat.as_deref()).collect();
+        assert_eq!(seats, vec![Some("beta")]);
+    }
+
+    #[test]
+    fn test_dsa_required_adds_bid_ext_dsa() {
+        let dsa_request = |dsa: serde_json::Value| OpenRTBRequest {
+            id: "r-dsa".to_string(),
+            imp: vec![sized_imp("1", 300, 250)],
+            regs: Some(Regs {
+                ext: Some(serde_json::json!({ "dsa": dsa })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let req = dsa_request(serde_json::json!({"dsarequired": 2, "pubrender": 0}));
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let ext = resp.seatbid[0].bid[0].ext.clone().unwrap();
+        assert_eq!(
+            ext["dsa"],
+            serde_json::json!({
+                "behalf": "example.com",
+                "paid": "example.com",
+                "transparency": [{"domain": "example.com", "dsaparams": [1, 2]}],
+                "adrender": 1
+            })
+        );
+        let req = dsa_request(serde_json::json!({"dsarequired": 3, "pubrender": 2}));
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(
+            resp.seatbid[0].bid[0].ext.as_ref().unwrap()["dsa"]["adrender"],
+            0
+        );
+
+        // Merely supported, or no DSA object at all: no dsa block
+        let req = dsa_request(serde_json::json!({"dsarequired": 1}));
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid[0].bid[0]
+            .ext
+            .as_ref()
+            .unwrap()
+            .get("dsa")
+            .is_none());
+        let req = OpenRTBRequest { regs: None, ..req };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert!(resp.seatbid[0].bid[0]
+            .ext
+            .as_ref()
+            .unwrap()
+            .get("dsa")
+            .is_none());
+    }
+
+    #[test]
+    fn test_badv_and_bcat_suppress_bids() {
+        let req = OpenRTBRequest {
+            id: "r-block".to_string(),
+            imp: vec![sized_imp("1", 300, 250), sized_imp("2", 728, 90)],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!(bid.adomain, Some(vec!["example.com".to_string()]));
+        assert_eq!(bid.cat, Some(vec!["IAB24".to_string()]));
+
+        let blocked = OpenRTBRequest {
+            badv: Some(vec!["other.com".to_string(), "Example.com".to_string()]),
+            ..req.clone()
+        };
+        let resp = build_openrtb_response(&blocked, "host.test", test_signature());
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_NO_BID));
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["skipped"],
+            serde_json::json!([
+                {"impid": "1", "reason": "blocked"},
+                {"impid": "2", "reason": "blocked"}
+            ])
+        );
+
+        // Configured category and domain are what gets matched
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            adomain = "brand.test"
+            cat = "IAB3"
+            "#,
+        )
+        .unwrap();
+        let resp =
+            build_openrtb_response_with_config(&blocked, "host.test", test_signature(), &cfg);
+        assert_eq!(
+            resp.seatbid[0].bid[0].adomain,
+            Some(vec!["brand.test".to_string()])
+        );
+        let bcat = OpenRTBRequest {
+            bcat: Some(vec!["IAB3".to_string()]),
+            ..req
+        };
+        let resp = build_openrtb_response_with_config(&bcat, "host.test", test_signature(), &cfg);
+        assert!(resp.seatbid.is_empty());
+    }
 
-    // Build metadata with sanitized response
-    let metadata = CreativeMetadata {
-        signature: signature_status,
-        request: req,
-        response: sanitized_response,
-    };
+    #[test]
+    fn test_fill_rules_decide_per_size() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            fill_seed = 42
 
-    // Fill in adm for each bid
-    let final_bids: Vec<OpenrtbBid> = bids
-        .into_iter()
-        .map(|mut bid| {
-            let bid_for_iframe = if bid.ext.is_some() {
-                Some(bid.price)
-            } else {
-                None
-            };
-            let crid = bid.crid.as_deref().unwrap_or("unknown");
-            let w = bid.w.unwrap_or(300);
-            let h = bid.h.unwrap_or(250);
-            bid.adm = Some(iframe_html(
-                base_host,
-                crid,
-                w,
-                h,
-                bid_for_iframe,
-                &metadata,
-            ));
-            bid
-        })
-        .collect();
+            [[fill_rule]]
+            w = 970
+            h = 250
+            rate = 0.0
 
-    OpenRTBResponse {
-        id: response_id,
-        cur: Some("USD".to_string()),
-        seatbid: vec![SeatBid {
-            seat: Some("mocktioneer".to_string()),
-            bid: final_bids,
+            [[fill_rule]]
+            w = 300
+            h = 250
+            rate = 1.0
+            "#,
+        )
+        .unwrap();
+        let req = OpenRTBRequest {
+            id: "r-fill".to_string(),
+            imp: vec![
+                sized_imp("1", 970, 250),
+                sized_imp("2", 300, 250),
+                sized_imp("3", 728, 90),
+            ],
             ..Default::default()
-        }],
-        ..Default::default()
+        };
+        for _ in 0..20 {
+            let resp =
+                build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+            let impids: Vec<&str> = resp.seatbid[0]
+                .bid
+                .iter()
+                .map(|b| b.impid.as_str())
+                .collect();
+            assert_eq!(impids, vec!["2", "3"]);
+            assert_eq!(
+                resp.ext.unwrap()["mocktioneer"]["skipped"],
+                serde_json::json!([{"impid": "1", "reason": "no_fill"}])
+            );
+        }
+
+        // Nothing filled at all is a plain no-bid
+        let req = OpenRTBRequest {
+            imp: vec![sized_imp("1", 970, 250)],
+            ..req
+        };
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_NO_BID));
     }
-}
 
-// ============================================================================
-// APS TAM API Response Builder
-// ============================================================================
+    #[test]
+    fn test_fill_rule_rate_is_reproducible_with_seed() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            fill_seed = 7
 
-/// Encode APS price using base64 for mock testing.
-///
-/// Note: Real Amazon APS uses proprietary encoding that cannot be decoded without Amazon's keys.
-/// Our mock uses transparent base64 encoding that CAN be decoded for testing/debugging purposes.
-/// Example: `echo "Mi41MA==" | base64 -d` → `2.50`
-fn encode_aps_price(price: f64) -> String {
-    use base64::{engine::general_purpose::STANDARD, Engine as _};
+            [[fill_rule]]
+            w = 300
+            h = 250
+            rate = 0.5
+            "#,
+        )
+        .unwrap();
+        let req = OpenRTBRequest {
+            id: "r-fill".to_string(),
+            imp: (0..100)
+                .map(|i| sized_imp(&i.to_string(), 300, 250))
+                .collect(),
+            ..Default::default()
+        };
+        let filled = || {
+            build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg).seatbid[0]
+                .bid
+                .len()
+        };
+        let first = filled();
+        assert!((30..=70).contains(&first), "filled {} of 100", first);
+        assert_eq!(filled(), first);
 
-    let price_str = price.to_string();
-    STANDARD.encode(price_str.as_bytes())
-}
+        // Without a seed the draws follow the request id
+        let unseeded = AppConfig::from_toml_str(
+            r#"
+            [[fill_rule]]
+            w = 300
+            h = 250
+            rate = 0.5
+            "#,
+        )
+        .unwrap();
+        let filled_ids = |id: &str| -> Vec<String> {
+            let req = OpenRTBRequest {
+                id: id.to_string(),
+                ..req.clone()
+            };
+            build_openrtb_response_with_config(&req, "host.test", test_signature(), &unseeded)
+                .seatbid[0]
+                .bid
+                .iter()
+                .map(|b| b.impid.clone())
+                .collect()
+        };
+        assert_eq!(filled_ids("r-a"), filled_ids("r-a"));
+        assert_ne!(filled_ids("r-a"), filled_ids("r-b"));
+    }
 
-/// Decode APS price from base64 (mock format only).
-///
-/// Returns `None` if the string is not valid base64 or doesn't contain a valid price.
-/// This only works with mocktioneer-encoded prices; real APS prices cannot be decoded.
-pub fn decode_aps_price(encoded: &str) -> Option<f64> {
-    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    #[test]
+    fn test_skipped_imps_listed_alongside_bids() {
+        let mut audio_and_banner = no_bid_imp("3", false);
+        audio_and_banner.audio = Some(Audio::default());
+        let req = OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![
+                no_bid_imp("1", true),
+                OpenrtbImp {
+                    id: "2".to_string(),
+                    audio: Some(Audio::default()),
+                    ..Default::default()
+                },
+                audio_and_banner,
+            ],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response(&req, "host.test", test_signature());
+        let impids: Vec<&str> = resp.seatbid[0]
+            .bid
+            .iter()
+            .map(|b| b.impid.as_str())
+            .collect();
+        assert_eq!(impids, vec!["3"]);
+        assert!(resp.nbr.is_none());
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["skipped"],
+            serde_json::json!([
+                {"impid": "1", "reason": "no_bid"},
+                {"impid": "2", "reason": "unsupported_media"}
+            ])
+        );
+    }
 
-    let decoded = STANDARD.decode(encoded).ok()?;
-    let price_str = String::from_utf8(decoded).ok()?;
-    price_str.parse().ok()
-}
+    #[test]
+    fn test_truncate_to_fit_drops_bids_under_cap() {
+        let req = OpenRTBRequest {
+            id: "big".to_string(),
+            imp: (1..=5)
+                .map(|i| OpenrtbImp {
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            num_bids: Some(MAX_BIDS_PER_IMP),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..no_bid_imp(&i.to_string(), false)
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut resp = build_openrtb_response(&req, "host.test", test_signature());
+        let full_len = serialized_len(&resp);
+        let total_bids: usize = resp.seatbid.iter().map(|sb| sb.bid.len()).sum();
+        assert_eq!(total_bids, 50);
 
-/// Build APS TAM response from an APS bid request matching real Amazon API format.
-///
-/// This function generates mock bids for all slots with standard sizes:
-/// - Variable bid prices based on ad size (via `get_cpm()`)
-///   - Ranges from $1.70 - $4.20 CPM for standard sizes
-///   - Example: 300x250 = $2.50, 970x250 = $4.20, 320x50 = $1.80
-/// - 100% fill rate for standard sizes
-/// - Returns contextual format matching real Amazon APS API
-/// - No creative HTML (APS doesn't return adm field)
-/// - Generates base64-encoded price strings (recoverable in mock, unlike real APS)
-pub fn build_aps_response(req: &ApsBidRequest, base_host: &str) -> ApsBidResponse {
-    let mut slots: Vec<ApsSlotResponse> = Vec::new();
+        let cap = full_len / 3;
+        let dropped = truncate_to_fit(&mut resp, cap, true);
+        assert!(dropped > 0);
+        assert!(serialized_len(&resp) <= cap);
+        let kept: usize = resp.seatbid.iter().map(|sb| sb.bid.len()).sum();
+        assert_eq!(kept + dropped, total_bids);
+        assert!(kept > 0);
+        assert!(resp.nbr.is_none());
+        let note = &resp.ext.as_ref().unwrap()["mocktioneer"]["truncated"];
+        assert_eq!(note["dropped_bids"], dropped);
+        assert_eq!(note["max_response_bytes"], cap);
 
-    for slot in req.slots.iter() {
-        // Find the standard size with the highest CPM from all sizes in the slot
-        let best_size = slot
-            .sizes
-            .iter()
-            .filter_map(|&[w, h]| {
-                let w_i64 = w as i64;
-                let h_i64 = h as i64;
-                if is_standard_size(w_i64, h_i64) {
-                    let price = get_cpm(w_i64, h_i64);
-                    Some((w, h, price))
-                } else {
-                    None
-                }
-            })
-            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal));
+        // A response that already fits is left alone
+        assert_eq!(truncate_to_fit(&mut resp, cap, true), 0);
+    }
 
-        let Some((w, h, price)) = best_size else {
-            // No standard sizes found, skip this slot
-            log::debug!(
-                "APS: Skipping slot '{}' - no standard sizes in {:?}",
-                slot.slot_id,
-                slot.sizes
-            );
-            continue;
+    #[test]
+    fn test_truncate_to_fit_without_room_is_no_bid() {
+        let req = OpenRTBRequest {
+            id: "big".to_string(),
+            imp: vec![no_bid_imp("1", false)],
+            ..Default::default()
         };
+        let mut resp = build_openrtb_response(&req, "host.test", test_signature());
+        assert_eq!(truncate_to_fit(&mut resp, 10, false), 1);
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_RESPONSE_TOO_LARGE));
+        assert!(resp.ext.is_none());
+    }
 
-        // Generate bid components (price already calculated in best_size selection)
-        let impression_id = new_id();
-        let crid = format!("{}-{}", new_id(), "mocktioneer");
-        let size_str = format!("{}x{}", w, h);
+    fn site_request(domain: Option<&str>, page: Option<&str>) -> OpenRTBRequest {
+        OpenRTBRequest {
+            id: "r-site".to_string(),
+            imp: vec![no_bid_imp("1", false)],
+            site: Some(Site {
+                domain: domain.map(str::to_string),
+                page: page.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
 
-        // Generate base64-encoded price string (recoverable in mock - real APS uses proprietary encoding)
-        let encoded_price = encode_aps_price(price);
+    fn site_rules_config(toml: &str) -> AppConfig {
+        AppConfig::from_toml_str(toml).unwrap()
+    }
 
-        // Build slot response matching real Amazon format
-        slots.push(ApsSlotResponse {
-            slot_id: slot.slot_id.clone(),
-            size: size_str.clone(),
-            crid: Some(crid),
-            media_type: Some("d".to_string()), // "d" for display
-            fif: Some("1".to_string()),        // "1" = filled
-            targeting: vec![
-                "amzniid".to_string(),
-                "amznp".to_string(),
-                "amznsz".to_string(),
-                "amznbid".to_string(),
-                "amznactt".to_string(),
-            ],
-            meta: vec![
-                "slotID".to_string(),
-                "mediaType".to_string(),
-                "size".to_string(),
-            ],
-            // Targeting key-value pairs (flat on slot object)
-            amzniid: Some(impression_id),
-            amznbid: Some(encoded_price.clone()),
-            amznp: Some(encoded_price), // Same encoding for both fields
-            amznsz: Some(size_str),
-            amznactt: Some("OPEN".to_string()),
-        });
+    #[test]
+    fn test_site_rule_multiplier_applies_to_matching_domain() {
+        let cfg =
+            site_rules_config("[[site_rule]]\ndomain = \"premium.example\"\nmultiplier = 2.0");
+        let base = get_cpm(300, 250);
+        for req in [
+            site_request(Some("premium.example"), None),
+            site_request(Some("News.Premium.example"), None),
+            site_request(None, Some("https://www.premium.example/article?id=1")),
+        ] {
+            let resp = build_openrtb_response_with_config(&req, "h", test_signature(), &cfg);
+            assert_eq!(resp.seatbid[0].bid[0].price, base * 2.0);
+        }
 
-        log::debug!(
-            "APS: Generated bid for slot '{}' ({}x{}) at ${:.2}",
-            slot.slot_id,
-            w,
-            h,
-            price
+        // A multiplier alone leaves other sites bidding at the usual price
+        let resp = build_openrtb_response_with_config(
+            &site_request(Some("notpremium.example"), None),
+            "h",
+            test_signature(),
+            &cfg,
         );
+        assert_eq!(resp.seatbid[0].bid[0].price, base);
     }
 
-    ApsBidResponse {
-        contextual: ApsContextual {
-            slots,
-            host: Some(format!("https://{}", base_host)),
-            status: Some("ok".to_string()),
-            cfe: Some(true),
-            ev: Some(true),
-            cfn: Some("bao-csm/direct/csm_othersv6.js".to_string()),
-            cb: Some("6".to_string()),
-            cmp: None, // Optional campaign tracking URL
-        },
+    #[test]
+    fn test_site_rule_explicit_allow_makes_allow_list() {
+        let cfg = site_rules_config(
+            "[[site_rule]]\ndomain = \"premium.example\"\naction = \"allow\"\nmultiplier = 2.0",
+        );
+        let base = get_cpm(300, 250);
+        let resp = build_openrtb_response_with_config(
+            &site_request(Some("premium.example"), None),
+            "h",
+            test_signature(),
+            &cfg,
+        );
+        assert_eq!(resp.seatbid[0].bid[0].price, base * 2.0);
+
+        // Other sites get no bid, requests without a site are unaffected
+        let resp = build_openrtb_response_with_config(
+            &site_request(Some("notpremium.example"), None),
+            "h",
+            test_signature(),
+            &cfg,
+        );
+        assert_eq!(resp.nbr, Some(NBR_BLOCKED_SITE));
+        let mut unsited = site_request(None, None);
+        unsited.site = None;
+        let resp = build_openrtb_response_with_config(&unsited, "h", test_signature(), &cfg);
+        assert_eq!(resp.seatbid[0].bid[0].price, base);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::aps::ApsSlot;
-    use crate::openrtb::{Banner, ExtMocktioneer, Format, ImpExt};
+    #[test]
+    fn test_site_rule_blocked_domain_no_bids() {
+        let cfg = site_rules_config("[[site_rule]]\ndomain = \"spam.example\"\naction = \"block\"");
+        let resp = build_openrtb_response_with_config(
+            &site_request(Some("spam.example"), None),
+            "h",
+            test_signature(),
+            &cfg,
+        );
+        assert!(resp.seatbid.is_empty());
+        assert_eq!(resp.nbr, Some(NBR_BLOCKED_SITE));
 
-    fn test_signature() -> SignatureStatus {
-        SignatureStatus::NotPresent {
-            reason: "test".to_string(),
-        }
+        // Block-only rules leave every other site alone
+        let resp = build_openrtb_response_with_config(
+            &site_request(Some("fine.example"), None),
+            "h",
+            test_signature(),
+            &cfg,
+        );
+        assert_eq!(resp.seatbid[0].bid[0].price, get_cpm(300, 250));
     }
 
     #[test]
-    fn test_size_from_imp_defaults_and_format() {
-        // Empty banner defaults to 300x250
-        let imp = OpenrtbImp {
-            id: "1".to_string(),
-            banner: Some(Banner::default()),
+    fn test_price_range_is_reproducible_and_in_range() {
+        let ranged_request = |id: &str| OpenRTBRequest {
+            id: id.to_string(),
+            imp: (1..=20)
+                .map(|i| OpenrtbImp {
+                    ext: Some(ImpExt {
+                        mocktioneer: Some(ExtMocktioneer {
+                            price_range: Some([0.5, 3.0]),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..no_bid_imp(&i.to_string(), false)
+                })
+                .collect(),
             ..Default::default()
         };
-        assert_eq!(size_from_imp(&imp), (300, 250));
-
-        // Uses format[0] when w/h not set
-        let imp = OpenrtbImp {
-            id: "1".to_string(),
-            banner: Some(Banner {
-                format: Some(vec![Format {
-                    w: 320,
-                    h: 50,
-                    ..Default::default()
-                }]),
-                ..Default::default()
-            }),
-            ..Default::default()
+        let prices = |id: &str| -> Vec<f64> {
+            let resp = build_openrtb_response(&ranged_request(id), "h", test_signature());
+            resp.seatbid[0].bid.iter().map(|b| b.price).collect()
         };
-        assert_eq!(size_from_imp(&imp), (320, 50));
 
-        // Prefers explicit w/h over format
-        let imp = OpenrtbImp {
-            id: "1".to_string(),
-            banner: Some(Banner {
-                w: Some(728),
-                h: Some(90),
-                ..Default::default()
-            }),
+        let first = prices("req-a");
+        assert_eq!(first, prices("req-a"));
+        assert!(first.iter().all(|p| (0.5..=3.0).contains(p)));
+        // Prices vary across imps and across requests
+        assert!(first.iter().any(|p| *p != first[0]));
+        assert_ne!(first, prices("req-b"));
+    }
+
+    #[test]
+    fn test_seeded_price_degenerate_range() {
+        assert_eq!(seeded_price("r", "1", [1.25, 1.25]), 1.25);
+    }
+
+    fn mtype_bid(impid: &str, mtype: MediaType) -> OpenrtbBid {
+        OpenrtbBid {
+            id: format!("b-{}", impid),
+            impid: impid.to_string(),
+            price: 1.0,
+            mtype: Some(mtype),
             ..Default::default()
-        };
-        assert_eq!(size_from_imp(&imp), (728, 90));
+        }
     }
 
     #[test]
-    fn test_build_openrtb_response_structure() {
+    fn test_group_seatbids_by_mtype_splits_mixed_bids() {
+        let bids = vec![
+            mtype_bid("1", MediaType::Banner),
+            mtype_bid("2", MediaType::Video),
+            mtype_bid("3", MediaType::Banner),
+        ];
+
+        let seatbids = group_seatbids(bids.clone(), SEAT, true);
+        assert_eq!(seatbids.len(), 2);
+        assert_eq!(seatbids[0].seat.as_deref(), Some("mocktioneer-banner"));
+        let banner_imps: Vec<&str> = seatbids[0].bid.iter().map(|b| b.impid.as_str()).collect();
+        assert_eq!(banner_imps, vec!["1", "3"]);
+        assert_eq!(seatbids[1].seat.as_deref(), Some("mocktioneer-video"));
+        assert_eq!(seatbids[1].bid.len(), 1);
+        assert_eq!(seatbids[1].bid[0].mtype, Some(MediaType::Video));
+
+        // Toggle off keeps the single seatbid
+        let seatbids = group_seatbids(bids, SEAT, false);
+        assert_eq!(seatbids.len(), 1);
+        assert_eq!(seatbids[0].seat.as_deref(), Some("mocktioneer"));
+        assert_eq!(seatbids[0].bid.len(), 3);
+    }
+
+    #[test]
+    fn test_build_openrtb_response_seatbid_by_mtype_suffixes_seat() {
         let req = OpenRTBRequest {
-            id: "r1".to_string(),
+            id: "r-mtype".to_string(),
             imp: vec![OpenrtbImp {
                 id: "1".to_string(),
-                banner: Some(Banner {
-                    w: Some(300),
-                    h: Some(250),
-                    ..Default::default()
-                }),
+                banner: Some(Banner::default()),
                 ..Default::default()
             }],
             ..Default::default()
         };
-        let resp = build_openrtb_response(&req, "host.test", test_signature());
-        assert_eq!(resp.id, "r1");
-        assert_eq!(resp.cur.as_deref(), Some("USD"));
+        let mut cfg = AppConfig::default();
+        cfg.auction.seatbid_by_mtype = true;
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
         assert_eq!(resp.seatbid.len(), 1);
-        assert!(!resp.seatbid[0].bid.is_empty());
-        let bid = &resp.seatbid[0].bid[0];
-        assert_eq!(bid.impid, "1");
-        assert_eq!(bid.w, Some(300));
-        assert_eq!(bid.h, Some(250));
-        assert_eq!(bid.mtype, Some(MediaType::Banner));
-        assert!(bid.adm.is_some());
+        assert_eq!(resp.seatbid[0].seat.as_deref(), Some("mocktioneer-banner"));
     }
 
     #[test]
     fn test_is_standard_size() {
+        let cfg = AuctionConfig::default();
         // Standard sizes should be recognized
-        assert!(is_standard_size(300, 250));
-        assert!(is_standard_size(728, 90));
+        assert!(cfg.is_standard_size(300, 250));
+        assert!(cfg.is_standard_size(728, 90));
         // Non-standard sizes should not
-        assert!(!is_standard_size(333, 222));
-        assert!(!is_standard_size(0, 0));
-        assert!(!is_standard_size(300, 251));
+        assert!(!cfg.is_standard_size(333, 222));
+        assert!(!cfg.is_standard_size(0, 0));
+        assert!(!cfg.is_standard_size(300, 251));
     }
 
     #[test]
     fn test_standard_or_default_behavior() {
-        assert_eq!(standard_or_default((333, 222)), (300, 250));
-        assert_eq!(standard_or_default((320, 50)), (320, 50));
+        let cfg = AuctionConfig::default();
+        assert_eq!(standard_or_default((333, 222), &cfg), (300, 250));
+        assert_eq!(standard_or_default((320, 50), &cfg), (320, 50));
     }
 
     #[test]
@@ -462,6 +3803,14 @@ mod tests {
         // Non-standard should default to 300x250
         assert_eq!(bid.w, Some(300));
         assert_eq!(bid.h, Some(250));
+
+        // ...unless the config lists it
+        let mut cfg = AppConfig::default();
+        cfg.auction.standard_sizes.push([333, 222]);
+        let resp = build_openrtb_response_with_config(&req, "host.test", test_signature(), &cfg);
+        let bid = &resp.seatbid[0].bid[0];
+        assert_eq!((bid.w, bid.h), (Some(333), Some(222)));
+        assert!(bid.adm.as_deref().unwrap().contains("333x222"));
     }
 
     #[test]
@@ -503,7 +3852,10 @@ mod tests {
                     ..Default::default()
                 }),
                 ext: Some(ImpExt {
-                    mocktioneer: Some(ExtMocktioneer { bid: Some(2.5) }),
+                    mocktioneer: Some(ExtMocktioneer {
+                        bid: Some(2.5),
+                        ..Default::default()
+                    }),
                 }),
                 ..Default::default()
             }],
@@ -647,6 +3999,43 @@ mod tests {
         assert_eq!(slot.amznactt, Some("OPEN".to_string()));
     }
 
+    #[test]
+    fn test_build_aps_response_configured_currency() {
+        let req = ApsBidRequest {
+            pub_id: "test".to_string(),
+            slots: vec![ApsSlot {
+                slot_id: "slot1".to_string(),
+                sizes: vec![[300, 250]], // CPM is $2.50
+                slot_name: None,
+            }],
+            page_url: None,
+            user_agent: None,
+            timeout: None,
+        };
+
+        // Default: USD price, no currency key
+        let slot = &build_aps_response(&req, "mock.test").contextual.slots[0];
+        assert_eq!(slot.amzncur, None);
+        assert!(!slot.targeting.contains(&"amzncur".to_string()));
+
+        let mut cfg = AppConfig::default();
+        cfg.aps.currency = Some("EUR".to_string());
+        let resp = build_aps_response_with_config(&req, "mock.test", &cfg);
+        let slot = &resp.contextual.slots[0];
+        assert_eq!(slot.amzncur.as_deref(), Some("EUR"));
+        assert!(slot.targeting.contains(&"amzncur".to_string()));
+        // 2.50 USD * 0.92 = 2.30 EUR
+        assert_eq!(decode_aps_price(slot.amznbid.as_ref().unwrap()), Some(2.3));
+        assert_eq!(slot.amznp, slot.amznbid);
+
+        // Unknown currencies fall back to plain USD
+        cfg.aps.currency = Some("XYZ".to_string());
+        let resp = build_aps_response_with_config(&req, "mock.test", &cfg);
+        let slot = &resp.contextual.slots[0];
+        assert_eq!(slot.amzncur, None);
+        assert_eq!(decode_aps_price(slot.amznbid.as_ref().unwrap()), Some(2.5));
+    }
+
     #[test]
     fn test_decode_aps_price_roundtrip() {
         // Valid encoded prices
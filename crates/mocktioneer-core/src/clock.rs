@@ -0,0 +1,110 @@
+//! Injectable time source for ids and cookie expiry.
+//!
+//! Everything time-dependent goes through [`Clock`] so tests (or a deployment with
+//! `[clock] fixed_unix_ms`) can pin "now" and get time-stable output.
+
+use std::sync::LazyLock;
+use std::time::{Duration, UNIX_EPOCH};
+
+use uuid::{NoContext, Timestamp, Uuid};
+
+use crate::config::{AppConfig, ClockConfig};
+
+static CLOCK: LazyLock<Box<dyn Clock>> = LazyLock::new(|| from_config(&AppConfig::global().clock));
+
+pub trait Clock: Send + Sync {
+    /// Time elapsed since the Unix epoch.
+    fn now(&self) -> Duration;
+}
+
+/// Wall clock. Reads time through `uuid`, which also works on wasm targets.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        let (secs, nanos) = Timestamp::now(NoContext).to_unix();
+        Duration::new(secs, nanos)
+    }
+}
+
+/// Clock frozen at a fixed instant.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub Duration);
+
+impl FixedClock {
+    pub fn from_unix_ms(ms: u64) -> Self {
+        FixedClock(Duration::from_millis(ms))
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> Duration {
+        self.0
+    }
+}
+
+/// Clock selected by `[clock]`: fixed when `fixed_unix_ms` is set, real otherwise.
+pub fn from_config(cfg: &ClockConfig) -> Box<dyn Clock> {
+    match cfg.fixed_unix_ms {
+        Some(ms) => Box::new(FixedClock::from_unix_ms(ms)),
+        None => Box::new(SystemClock),
+    }
+}
+
+/// Process-wide clock built from the embedded `mocktioneer.toml`.
+pub fn global() -> &'static dyn Clock {
+    CLOCK.as_ref()
+}
+
+/// UUID v7 whose timestamp comes from `clock`.
+pub fn uuid_v7(clock: &dyn Clock) -> Uuid {
+    let now = clock.now();
+    Uuid::new_v7(Timestamp::from_unix(
+        NoContext,
+        now.as_secs(),
+        now.subsec_nanos(),
+    ))
+}
+
+/// HTTP date (`Expires` format) `offset` after the clock's current time.
+pub fn http_date_after(clock: &dyn Clock, offset: Duration) -> String {
+    httpdate::fmt_http_date(UNIX_EPOCH + clock.now() + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_clock_pins_uuid_timestamp() {
+        let clock = FixedClock::from_unix_ms(1_700_000_000_123);
+        let a = uuid_v7(&clock);
+        let b = uuid_v7(&clock);
+        assert_ne!(a, b, "random bits still differ");
+        let (secs, nanos) = a.get_timestamp().unwrap().to_unix();
+        assert_eq!(secs, 1_700_000_000);
+        assert_eq!(nanos / 1_000_000, 123);
+        assert_eq!(a.get_timestamp(), b.get_timestamp());
+    }
+
+    #[test]
+    fn http_date_after_uses_clock() {
+        let clock = FixedClock::from_unix_ms(0);
+        assert_eq!(
+            http_date_after(&clock, Duration::from_secs(86_400)),
+            "Fri, 02 Jan 1970 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn from_config_defaults_to_system_clock() {
+        let clock = from_config(&ClockConfig::default());
+        assert!(clock.now() > Duration::from_secs(1_600_000_000));
+
+        let clock = from_config(&ClockConfig {
+            fixed_unix_ms: Some(42),
+        });
+        assert_eq!(clock.now(), Duration::from_millis(42));
+    }
+}
@@ -0,0 +1,925 @@
+//! Runtime settings loaded from `mocktioneer.toml`.
+//!
+//! The file lives at the workspace root next to `edgezero.toml` and is embedded at
+//! compile time, so every adapter ships with identical settings. Every key is
+//! optional: an empty file reproduces the built-in behavior.
+
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+const EMBEDDED_CONFIG: &str = include_str!("../../../mocktioneer.toml");
+
+static CONFIG: LazyLock<AppConfig> = LazyLock::new(|| {
+    AppConfig::from_toml_str(EMBEDDED_CONFIG).unwrap_or_else(|e| {
+        log::error!("Invalid mocktioneer.toml, falling back to defaults: {}", e);
+        AppConfig::default()
+    })
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Invalid [templates] {name}: {reason}")]
+    Template { name: &'static str, reason: String },
+    #[error("Invalid [[site_rule]] for {domain}: {reason}")]
+    SiteRule { domain: String, reason: String },
+}
+
+/// Top-level settings, one field per `[section]` of `mocktioneer.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub auction: AuctionConfig,
+    pub aps: ApsConfig,
+    pub debug: DebugConfig,
+    pub creative: CreativeConfig,
+    pub clock: ClockConfig,
+    pub limits: LimitsConfig,
+    pub server: ServerConfig,
+    pub parsing: ParsingConfig,
+    pub verification: VerificationConfig,
+    pub rate_limit: RateLimitConfig,
+    pub prebid: PrebidConfig,
+    pub templates: TemplatesConfig,
+    /// `[[seat]]` entries; each seat bids on every imp.
+    #[serde(rename = "seat")]
+    pub seats: Vec<SeatConfig>,
+    /// `[[site_rule]]` entries, checked in order against the request's site.
+    #[serde(rename = "site_rule")]
+    pub site_rules: Vec<SiteRuleConfig>,
+    /// `[[fill_rule]]` entries; the first one matching an imp's size applies.
+    #[serde(rename = "fill_rule")]
+    pub fill_rules: Vec<FillRuleConfig>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            auction: AuctionConfig::default(),
+            aps: ApsConfig::default(),
+            debug: DebugConfig::default(),
+            creative: CreativeConfig::default(),
+            clock: ClockConfig::default(),
+            limits: LimitsConfig::default(),
+            server: ServerConfig::default(),
+            parsing: ParsingConfig::default(),
+            verification: VerificationConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            prebid: PrebidConfig::default(),
+            templates: TemplatesConfig::default(),
+            seats: vec![SeatConfig::default()],
+            site_rules: Vec::new(),
+            fill_rules: Vec::new(),
+        }
+    }
+}
+
+/// `[[seat]]` — a bidder seat in OpenRTB responses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeatConfig {
+    /// `seatbid.seat` value; also prefixes generated creative ids.
+    pub name: String,
+    /// Currency this seat bids in; prices are converted from USD. Unset bids in USD.
+    pub currency: Option<String>,
+}
+
+impl Default for SeatConfig {
+    fn default() -> Self {
+        Self {
+            name: "mocktioneer".to_string(),
+            currency: None,
+        }
+    }
+}
+
+/// `[[site_rule]]` — targeting by `site.domain` (or the host of `site.page`).
+///
+/// The first rule matching the site decides: `block` means no bid, otherwise we
+/// bid at `multiplier` times the usual price. Only an explicit `action = "allow"`
+/// makes an allow list: once one exists, sites matching no rule get no bid.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteRuleConfig {
+    /// Host to match, including its subdomains (`example.com` also matches
+    /// `news.example.com`); `*` matches any site.
+    pub domain: String,
+    /// Unset only adjusts prices by `multiplier`.
+    pub action: Option<SiteAction>,
+    /// Applied to computed prices on matching sites; explicit
+    /// `imp.ext.mocktioneer.bid` prices are left alone. Must be finite and not
+    /// negative.
+    pub multiplier: f64,
+}
+
+impl SiteRuleConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !self.multiplier.is_finite() || self.multiplier < 0.0 {
+            return Err(ConfigError::SiteRule {
+                domain: self.domain.clone(),
+                reason: format!(
+                    "multiplier must be finite and not negative, got {}",
+                    self.multiplier
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for SiteRuleConfig {
+    fn default() -> Self {
+        Self {
+            domain: "*".to_string(),
+            action: None,
+            multiplier: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SiteAction {
+    Allow,
+    Block,
+}
+
+/// `[[fill_rule]]` — how often imps of one size get bids.
+///
+/// Each imp whose bid size is `w`x`h` fills with probability `rate` (0–1),
+/// drawn once per imp from the `[auction] fill_seed` generator. Sizes matching
+/// no rule always fill.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FillRuleConfig {
+    pub w: i64,
+    pub h: i64,
+    pub rate: f64,
+}
+
+impl Default for FillRuleConfig {
+    fn default() -> Self {
+        Self {
+            w: 0,
+            h: 0,
+            rate: 1.0,
+        }
+    }
+}
+
+/// `[limits]` — guards against pathological configs and requests.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum seats bidding in one response; extra `[[seat]]` entries are ignored.
+    pub max_seats: usize,
+    /// Largest serialized auction response in bytes; bids are dropped to fit.
+    /// Unset means no cap.
+    pub max_response_bytes: Option<usize>,
+    /// Answer 500 instead of dropping bids when a response exceeds
+    /// `max_response_bytes`.
+    pub strict_response_size: bool,
+    /// Largest a `Content-Encoding: gzip` auction body may inflate to, in bytes.
+    pub max_inflated_bytes: usize,
+    /// Most bids one imp yields across all seats, whatever `num_bids` asks for.
+    /// Unset means no cap.
+    pub max_bids_per_imp: Option<usize>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_seats: 10,
+            max_response_bytes: None,
+            strict_response_size: false,
+            max_inflated_bytes: 1024 * 1024,
+            max_bids_per_imp: None,
+        }
+    }
+}
+
+/// `[server]` — how the service refers to itself.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Host used in creative, pixel and click URLs when the request carries
+    /// neither `X-Forwarded-Host` nor `Host`.
+    pub default_host: String,
+    /// Path prefix the app is mounted under (e.g. `/mock`). Prepended to every
+    /// generated URL, and every route also answers under it. Empty mounts at `/`.
+    pub base_path: String,
+    /// Origins allowed to make credentialed CORS requests. A listed request
+    /// `Origin` is echoed back; an empty list answers every origin with `*`.
+    pub cors_allowed_origins: Vec<String>,
+    /// `text` logs requests with `RequestLogger`'s plain lines; `json` logs one
+    /// structured record per request instead.
+    pub access_log_format: AccessLogFormat,
+}
+
+/// Shape of per-request access logging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            default_host: "mocktioneer.edgecompute.app".to_string(),
+            base_path: String::new(),
+            cors_allowed_origins: Vec::new(),
+            access_log_format: AccessLogFormat::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// `base_path` as `/prefix` without a trailing slash, or `None` when unset.
+    pub fn base_path(&self) -> Option<String> {
+        let trimmed = self.base_path.trim_matches('/');
+        (!trimmed.is_empty()).then(|| format!("/{}", trimmed))
+    }
+}
+
+/// `[auction]` — OpenRTB auction behavior.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuctionConfig {
+    /// Currency assumed for `imp.bidfloor` when `imp.bidfloorcur` is absent.
+    /// When unset, the request's first `cur` entry is used, then USD.
+    pub default_floor_cur: Option<String>,
+    /// Emit one seatbid per media type, suffixing the seat name
+    /// (`mocktioneer-banner`, `mocktioneer-video`, ...).
+    pub seatbid_by_mtype: bool,
+    /// Upper bound (seconds) accepted for `imp.exp`.
+    pub max_exp: i64,
+    /// Value of the `x-openrtb-version` header on auction responses.
+    pub openrtb_version: String,
+    /// Sort each seatbid's bids by price, highest first. Off keeps imp order.
+    pub sort_bids: bool,
+    /// Price for bids without `imp.ext.mocktioneer.bid`. Unset keeps size-based CPM.
+    pub default_bid_price: Option<f64>,
+    /// Distribution of the simulated delay before each auction response.
+    pub latency_dist: LatencyDist,
+    /// Mean delay for `latency_dist = "normal"`, in milliseconds.
+    pub latency_mean_ms: f64,
+    /// Standard deviation for `latency_dist = "normal"`, in milliseconds.
+    pub latency_stddev_ms: f64,
+    /// Lower bound for `latency_dist = "uniform"`, in milliseconds.
+    pub latency_min_ms: u64,
+    /// Upper bound for `latency_dist = "uniform"`, in milliseconds.
+    pub latency_max_ms: u64,
+    /// Seed for the latency generator. Unset draws each delay from a hash of the
+    /// request id.
+    pub latency_seed: Option<u64>,
+    /// Fraction (0–1) taken off computed bid prices to simulate bid shading,
+    /// never going below the imp or deal floor. Unset disables shading.
+    pub shading_factor: Option<f64>,
+    /// Seed for `[[fill_rule]]` draws, restarted for every response so a fixed
+    /// seed fills the same imps each time. Unset seeds from the request id.
+    pub fill_seed: Option<u64>,
+    /// Advertiser domain in `bid.adomain`; requests listing it in `badv` get no bid.
+    pub adomain: String,
+    /// IAB content category in `bid.cat`; requests listing it in `bcat` get no bid.
+    pub cat: String,
+    /// `[w, h]` sizes bid as-is and served by `/static/img` and
+    /// `/static/creatives`. Defaults to the sizes with a built-in CPM.
+    pub standard_sizes: Vec<[i64; 2]>,
+    /// How single-imp video auctions answer: inline VAST in `adm`, or a 302 to
+    /// `/vast`.
+    pub video_response: VideoResponse,
+    /// Rounding applied to every bid's final `price`.
+    pub price_rounding: PriceRounding,
+}
+
+impl Default for AuctionConfig {
+    fn default() -> Self {
+        Self {
+            default_floor_cur: None,
+            seatbid_by_mtype: false,
+            max_exp: 3600,
+            openrtb_version: "2.6".to_string(),
+            sort_bids: false,
+            default_bid_price: None,
+            latency_dist: LatencyDist::None,
+            latency_mean_ms: 0.0,
+            latency_stddev_ms: 0.0,
+            latency_min_ms: 0,
+            latency_max_ms: 0,
+            latency_seed: None,
+            shading_factor: None,
+            fill_seed: None,
+            adomain: "example.com".to_string(),
+            cat: "IAB24".to_string(),
+            standard_sizes: crate::auction::priced_sizes()
+                .map(|(w, h)| [w, h])
+                .collect(),
+            video_response: VideoResponse::default(),
+            price_rounding: PriceRounding::default(),
+        }
+    }
+}
+
+impl AuctionConfig {
+    /// Whether `w`x`h` is listed in `standard_sizes`.
+    pub fn is_standard_size(&self, w: i64, h: i64) -> bool {
+        self.standard_sizes.contains(&[w, h])
+    }
+}
+
+/// Answer to a single-imp video auction that gets a bid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoResponse {
+    /// OpenRTB JSON with the VAST inline in `adm`.
+    #[default]
+    Inline,
+    /// `302` to the bid's VAST at `/vast`, like SSPs that redirect video requests.
+    Redirect,
+}
+
+/// Rounding of final bid prices, for clients asserting on exact amounts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceRounding {
+    /// Prices as computed; custom `ext.mocktioneer.bid` values and converted
+    /// prices pass through.
+    #[default]
+    None,
+    /// To the nearest cent.
+    Cents,
+    /// Down to the cent, but not below the imp floor.
+    DownCents,
+}
+
+/// Shape of the simulated auction latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencyDist {
+    /// Respond immediately.
+    #[default]
+    None,
+    /// Uniform between `latency_min_ms` and `latency_max_ms`.
+    Uniform,
+    /// Normal around `latency_mean_ms` with `latency_stddev_ms`, clamped at zero.
+    Normal,
+}
+
+/// `[parsing]` — how strictly bid requests are read.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParsingConfig {
+    /// Media object assumed for imps without banner/video/audio/native. Unset
+    /// rejects such imps with 422.
+    pub default_media: Option<DefaultMedia>,
+    /// Reject auction requests carrying top-level fields `OpenRTBRequest` doesn't
+    /// know with 422, to catch typos. Off ignores them.
+    pub strict_request: bool,
+}
+
+/// Media object injected into media-less imps when `[parsing] default_media` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultMedia {
+    /// 300x250 banner.
+    Banner,
+    /// Video with the default 640x480 player.
+    Video,
+}
+
+/// `[aps]` — APS TAM response behavior.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApsConfig {
+    /// Currency the encoded APS price is expressed in. Prices are converted from
+    /// USD and the slot carries an `amzncur` targeting key. Unset keeps plain USD.
+    pub currency: Option<String>,
+}
+
+/// `[verification]` — request signature checks.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerificationConfig {
+    /// Fetch signing keys over plain `http://` instead of `https://`. Only for
+    /// local testing against a key server without TLS.
+    pub allow_insecure_jwks: bool,
+    /// Answer 401 to auctions whose `ext.trusted_server` signature fails to
+    /// verify. Off: failures only show in the creative badge. Unsigned requests
+    /// are never rejected.
+    pub enforce: bool,
+    /// Most domains whose signing keys are cached; the least recently used
+    /// domain is evicted beyond this.
+    pub jwks_cache_size: usize,
+    /// Seconds a domain's signing keys are served from the cache before they
+    /// are fetched again; 0 fetches on every verification.
+    pub jwks_cache_ttl_secs: u64,
+    /// Most keys accepted in one domain's JWKS; larger key sets fail to load
+    /// rather than bloat the cache.
+    pub jwks_max_keys: usize,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            allow_insecure_jwks: false,
+            enforce: false,
+            jwks_cache_size: 64,
+            jwks_cache_ttl_secs: 600,
+            jwks_max_keys: 100,
+        }
+    }
+}
+
+/// `[rate_limit.*]` — per-route request limits. Routes without a section are
+/// unlimited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// `[rate_limit.auction]` — `POST /openrtb2/auction`.
+    pub auction: Option<RouteLimit>,
+    /// `[rate_limit.pixel]` — `GET /pixel`.
+    pub pixel: Option<RouteLimit>,
+    /// `[rate_limit.client]` — every route, one token bucket per client IP.
+    pub client: Option<ClientLimit>,
+}
+
+/// Token bucket per client: `burst` requests at once, refilled at `rps`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientLimit {
+    /// Tokens added per second (at least 0.001).
+    pub rps: f64,
+    /// Bucket size: requests a client can make back to back (at least 1).
+    pub burst: u32,
+    /// Key clients on `CF-Connecting-IP`, `Fastly-Client-IP`, `X-Forwarded-For`
+    /// or `X-Real-IP`. Clients can set these themselves, so only enable this
+    /// behind a proxy that overwrites them; otherwise the peer address is used.
+    pub trust_forwarded: bool,
+}
+
+impl Default for ClientLimit {
+    fn default() -> Self {
+        Self {
+            rps: 10.0,
+            burst: 20,
+            trust_forwarded: false,
+        }
+    }
+}
+
+/// Requests allowed on one route per fixed window, across all clients.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteLimit {
+    pub requests: u32,
+    /// Window length in seconds (at least 1).
+    pub window_secs: u64,
+}
+
+impl Default for RouteLimit {
+    fn default() -> Self {
+        Self {
+            requests: 100,
+            window_secs: 1,
+        }
+    }
+}
+
+/// `[creative]` — bid markup delivery and creative rendering.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CreativeConfig {
+    pub delivery: CreativeDelivery,
+    /// Upper bound for the `?slow=N` render delay on HTML creatives, in milliseconds.
+    pub max_slow_ms: u64,
+    /// Tokens for the creative iframe's `sandbox` attribute (e.g.
+    /// `"allow-scripts allow-popups"`). Unset leaves the iframe unsandboxed; an
+    /// empty string applies every restriction.
+    pub sandbox: Option<String>,
+    /// Milliseconds after showing before MRAID creatives call `mraid.resize()`
+    /// and beacon `/pixel?event=mraid_resize`. Unset never resizes.
+    pub mraid_resize_ms: Option<u64>,
+    /// IQG media rating sent as `bid.qagmediarating`: 1 = all audiences,
+    /// 2 = everyone over 12, 3 = mature. Out-of-range values are clamped.
+    pub media_rating: i64,
+}
+
+impl Default for CreativeConfig {
+    fn default() -> Self {
+        Self {
+            delivery: CreativeDelivery::default(),
+            max_slow_ms: 10_000,
+            sandbox: None,
+            mraid_resize_ms: None,
+            media_rating: 1,
+        }
+    }
+}
+
+/// Wrapper used for `adm`; both load `/static/creatives/{W}x{H}.html`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CreativeDelivery {
+    /// Plain `<iframe>` markup.
+    #[default]
+    Iframe,
+    /// `<script>` tag that `document.write`s the iframe.
+    Script,
+}
+
+/// `[clock]` — time source for ids and cookie expiry.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    /// Freeze "now" at this Unix time in milliseconds. Unset uses the real clock.
+    pub fixed_unix_ms: Option<u64>,
+}
+
+/// `[prebid]` — imitate Prebid Server rather than a single bidder.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrebidConfig {
+    /// Add PBS's response `ext`: per-bidder `responsetimemillis` and, only with
+    /// `[debug] enabled`, a `debug` block echoing the resolved request.
+    pub server_mode: bool,
+    /// `networkName` in each bid's `ext.prebid.meta`.
+    pub network_name: String,
+}
+
+impl Default for PrebidConfig {
+    fn default() -> Self {
+        Self {
+            server_mode: false,
+            network_name: "Mocktioneer".to_string(),
+        }
+    }
+}
+
+/// `[templates]` — Handlebars sources replacing the embedded templates. Each one
+/// must survive a test render when the config loads.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplatesConfig {
+    /// Replaces `iframe.html.hbs`, the iframe `adm` wrapper.
+    pub iframe: Option<String>,
+    /// Replaces `creative.html.hbs`, served at `/static/creatives/{W}x{H}.html`.
+    pub creative: Option<String>,
+}
+
+impl TemplatesConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let templates = [("iframe", &self.iframe), ("creative", &self.creative)];
+        for (name, tmpl) in templates {
+            if let Some(tmpl) = tmpl {
+                crate::render::check_template(tmpl)
+                    .map_err(|reason| ConfigError::Template { name, reason })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[debug]` — diagnostics that should stay off in shared deployments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebugConfig {
+    /// Enables the `/debug/*` routes and request recording.
+    pub enabled: bool,
+    /// Maximum number of recorded auction requests kept for replay.
+    pub record_limit: usize,
+    /// Append a copy of each seatbid's first bid (same `impid`, new `id`) to
+    /// exercise client-side bid deduplication.
+    pub inject_duplicate_bids: bool,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            record_limit: 50,
+            inject_duplicate_bids: false,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Settings embedded from `mocktioneer.toml` at build time.
+    pub fn global() -> &'static AppConfig {
+        &CONFIG
+    }
+
+    pub fn from_toml_str(input: &str) -> Result<Self, ConfigError> {
+        let cfg: AppConfig = toml::from_str(input)?;
+        cfg.templates.validate()?;
+        for rule in &cfg.site_rules {
+            rule.validate()?;
+        }
+        Ok(cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let cfg = AppConfig::from_toml_str("").unwrap();
+        assert!(cfg.auction.default_floor_cur.is_none());
+        assert!(!cfg.auction.seatbid_by_mtype);
+        assert_eq!(cfg.auction.max_exp, 3600);
+        assert_eq!(cfg.auction.openrtb_version, "2.6");
+        assert!(!cfg.auction.sort_bids);
+        assert!(cfg.auction.default_bid_price.is_none());
+        assert_eq!(cfg.creative.media_rating, 1);
+        assert_eq!(cfg.auction.video_response, VideoResponse::Inline);
+        assert_eq!(cfg.auction.price_rounding, PriceRounding::None);
+        assert_eq!(cfg.auction.latency_dist, LatencyDist::None);
+        assert!(cfg.auction.latency_seed.is_none());
+        assert!(cfg.auction.shading_factor.is_none());
+        assert!(cfg.auction.fill_seed.is_none());
+        assert_eq!(cfg.auction.adomain, "example.com");
+        assert_eq!(cfg.auction.cat, "IAB24");
+        assert_eq!(cfg.auction.standard_sizes.len(), 13);
+        assert!(cfg.auction.is_standard_size(300, 250));
+        assert!(cfg.aps.currency.is_none());
+        assert!(!cfg.debug.enabled);
+        assert_eq!(cfg.debug.record_limit, 50);
+        assert!(!cfg.debug.inject_duplicate_bids);
+        assert_eq!(cfg.creative.delivery, CreativeDelivery::Iframe);
+        assert_eq!(cfg.creative.max_slow_ms, 10_000);
+        assert!(cfg.creative.sandbox.is_none());
+        assert!(cfg.creative.mraid_resize_ms.is_none());
+        assert!(cfg.clock.fixed_unix_ms.is_none());
+        assert_eq!(cfg.limits.max_seats, 10);
+        assert!(cfg.limits.max_response_bytes.is_none());
+        assert!(!cfg.limits.strict_response_size);
+        assert_eq!(cfg.limits.max_inflated_bytes, 1024 * 1024);
+        assert!(cfg.limits.max_bids_per_imp.is_none());
+        assert_eq!(cfg.server.default_host, "mocktioneer.edgecompute.app");
+        assert!(cfg.server.base_path().is_none());
+        assert!(cfg.server.cors_allowed_origins.is_empty());
+        assert_eq!(cfg.server.access_log_format, AccessLogFormat::Text);
+        assert!(cfg.parsing.default_media.is_none());
+        assert!(!cfg.verification.allow_insecure_jwks);
+        assert_eq!(cfg.verification.jwks_max_keys, 100);
+        assert_eq!(cfg.verification.jwks_cache_size, 64);
+        assert_eq!(cfg.verification.jwks_cache_ttl_secs, 600);
+        assert!(!cfg.verification.enforce);
+        assert!(cfg.rate_limit.auction.is_none());
+        assert!(cfg.rate_limit.client.is_none());
+        assert!(!cfg.prebid.server_mode);
+        assert_eq!(cfg.prebid.network_name, "Mocktioneer");
+        assert!(!cfg.parsing.strict_request);
+        assert!(cfg.rate_limit.pixel.is_none());
+        assert_eq!(cfg.seats.len(), 1);
+        assert_eq!(cfg.seats[0].name, "mocktioneer");
+        assert!(cfg.site_rules.is_empty());
+        assert!(cfg.fill_rules.is_empty());
+    }
+
+    #[test]
+    fn parses_auction_section() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            default_floor_cur = "EUR"
+            seatbid_by_mtype = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.auction.default_floor_cur.as_deref(), Some("EUR"));
+        assert!(cfg.auction.seatbid_by_mtype);
+    }
+
+    #[test]
+    fn parses_latency_distributions() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            latency_dist = "normal"
+            latency_mean_ms = 80.0
+            latency_stddev_ms = 25.0
+            latency_seed = 7
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.auction.latency_dist, LatencyDist::Normal);
+        assert_eq!(cfg.auction.latency_mean_ms, 80.0);
+        assert_eq!(cfg.auction.latency_stddev_ms, 25.0);
+        assert_eq!(cfg.auction.latency_seed, Some(7));
+
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            latency_dist = "uniform"
+            latency_min_ms = 20
+            latency_max_ms = 150
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.auction.latency_dist, LatencyDist::Uniform);
+        assert_eq!(cfg.auction.latency_min_ms, 20);
+        assert_eq!(cfg.auction.latency_max_ms, 150);
+
+        assert!(AppConfig::from_toml_str("[auction]\nlatency_dist = \"pareto\"").is_err());
+    }
+
+    #[test]
+    fn normalizes_base_path() {
+        for raw in ["/mock", "mock", "/mock/", "mock/"] {
+            let toml = format!("[server]\nbase_path = \"{}\"", raw);
+            let cfg = AppConfig::from_toml_str(&toml).unwrap();
+            assert_eq!(cfg.server.base_path().as_deref(), Some("/mock"));
+        }
+        let cfg = AppConfig::from_toml_str("[server]\nbase_path = \"/\"").unwrap();
+        assert!(cfg.server.base_path().is_none());
+    }
+
+    #[test]
+    fn parses_parsing_section() {
+        let cfg = AppConfig::from_toml_str("[parsing]\ndefault_media = \"banner\"").unwrap();
+        assert_eq!(cfg.parsing.default_media, Some(DefaultMedia::Banner));
+        assert!(AppConfig::from_toml_str("[parsing]\ndefault_media = \"audio\"").is_err());
+    }
+
+    #[test]
+    fn parses_aps_section() {
+        let cfg = AppConfig::from_toml_str("[aps]\ncurrency = \"EUR\"").unwrap();
+        assert_eq!(cfg.aps.currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn parses_verification_section() {
+        let cfg = AppConfig::from_toml_str(
+            "[verification]
+allow_insecure_jwks = true",
+        )
+        .unwrap();
+        assert!(cfg.verification.allow_insecure_jwks);
+    }
+
+    #[test]
+    fn parses_templates_and_rejects_broken_ones() {
+        let cfg = AppConfig::from_toml_str(
+            r#"[templates]
+iframe = "<div>{{W}}x{{H}}</div>""#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.templates.iframe.as_deref(),
+            Some("<div>{{W}}x{{H}}</div>")
+        );
+        assert!(cfg.templates.creative.is_none());
+
+        let err =
+            AppConfig::from_toml_str("[templates]\ncreative = \"{{#if W}}unclosed\"").unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Template {
+                name: "creative",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parses_rate_limit_sections() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [rate_limit.auction]
+            requests = 500
+            [rate_limit.pixel]
+            requests = 20
+            window_secs = 10
+            [rate_limit.client]
+            rps = 2.5
+            "#,
+        )
+        .unwrap();
+        let auction = cfg.rate_limit.auction.unwrap();
+        assert_eq!((auction.requests, auction.window_secs), (500, 1));
+        let pixel = cfg.rate_limit.pixel.unwrap();
+        assert_eq!((pixel.requests, pixel.window_secs), (20, 10));
+        let client = cfg.rate_limit.client.unwrap();
+        assert_eq!((client.rps, client.burst), (2.5, 20));
+        assert!(!client.trust_forwarded);
+        assert!(AppConfig::from_toml_str(
+            "[rate_limit.aps]
+requests = 1"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parses_creative_delivery() {
+        let cfg = AppConfig::from_toml_str("[creative]\ndelivery = \"script\"").unwrap();
+        assert_eq!(cfg.creative.delivery, CreativeDelivery::Script);
+        assert!(AppConfig::from_toml_str("[creative]\ndelivery = \"vast\"").is_err());
+    }
+
+    #[test]
+    fn parses_seat_list() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [[seat]]
+            name = "alpha"
+
+            [[seat]]
+            name = "beta"
+            currency = "EUR"
+            "#,
+        )
+        .unwrap();
+        let names: Vec<&str> = cfg.seats.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["alpha", "beta"]);
+        assert!(cfg.seats[0].currency.is_none());
+        assert_eq!(cfg.seats[1].currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn parses_site_rules() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [[site_rule]]
+            domain = "premium.example"
+            multiplier = 1.5
+
+            [[site_rule]]
+            domain = "spam.example"
+            action = "block"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.site_rules.len(), 2);
+        assert_eq!(cfg.site_rules[0].action, None);
+        assert_eq!(cfg.site_rules[0].multiplier, 1.5);
+        assert_eq!(cfg.site_rules[1].action, Some(SiteAction::Block));
+        assert_eq!(cfg.site_rules[1].multiplier, 1.0);
+    }
+
+    #[test]
+    fn rejects_bad_site_rule_multipliers() {
+        for multiplier in ["-1.0", "inf", "nan"] {
+            let toml = format!(
+                "[[site_rule]]\ndomain = \"a.example\"\nmultiplier = {}",
+                multiplier
+            );
+            let err = AppConfig::from_toml_str(&toml).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::SiteRule { .. }),
+                "{}: {}",
+                multiplier,
+                err
+            );
+        }
+        assert!(AppConfig::from_toml_str("[[site_rule]]\nmultiplier = 0.0").is_ok());
+    }
+
+    #[test]
+    fn parses_fill_rules() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [[fill_rule]]
+            w = 970
+            h = 250
+            rate = 0.5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(cfg.fill_rules.len(), 1);
+        assert_eq!((cfg.fill_rules[0].w, cfg.fill_rules[0].h), (970, 250));
+        assert_eq!(cfg.fill_rules[0].rate, 0.5);
+    }
+
+    #[test]
+    fn standard_sizes_can_add_custom_inventory() {
+        let cfg = AppConfig::from_toml_str(
+            r#"
+            [auction]
+            standard_sizes = [[300, 250], [300, 1050]]
+            "#,
+        )
+        .unwrap();
+        assert!(cfg.auction.is_standard_size(300, 1050));
+        assert!(cfg.auction.is_standard_size(300, 250));
+        // The list replaces the defaults
+        assert!(!cfg.auction.is_standard_size(728, 90));
+        assert!(!AuctionConfig::default().is_standard_size(300, 1050));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(AppConfig::from_toml_str("[auction]\ndefault_floor_currency = \"EUR\"").is_err());
+    }
+
+    #[test]
+    fn embedded_config_parses() {
+        AppConfig::from_toml_str(EMBEDDED_CONFIG).expect("mocktioneer.toml should parse");
+    }
+}
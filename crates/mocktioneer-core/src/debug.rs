@@ -0,0 +1,357 @@
+//! Debug-only tooling: request recording, replay, response self-checks and the
+//! feature log.
+//!
+//! Everything here is gated by `[debug] enabled` in `mocktioneer.toml`; with debug
+//! off the `/debug/*` routes answer 404 and nothing is recorded.
+
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
+use edgezero_core::error::EdgeError;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::auction::is_bid_size;
+use crate::config::{AuctionConfig, DebugConfig};
+use crate::openrtb::{MediaType, OpenRTBRequest, OpenRTBResponse};
+
+static RECORDER: LazyLock<RequestRecorder> = LazyLock::new(RequestRecorder::default);
+
+/// Fail with 404 unless debug mode is enabled, so debug routes look absent.
+pub fn require_debug(cfg: &DebugConfig, path: &str) -> Result<(), EdgeError> {
+    if cfg.enabled {
+        Ok(())
+    } else {
+        Err(EdgeError::not_found(path.to_string()))
+    }
+}
+
+/// Bounded in-memory log of incoming OpenRTB requests, oldest first.
+///
+/// Storage is per instance: on edge platforms each worker keeps its own log.
+#[derive(Debug, Default)]
+pub struct RequestRecorder {
+    requests: Mutex<VecDeque<OpenRTBRequest>>,
+}
+
+impl RequestRecorder {
+    /// Process-wide recorder used by the HTTP handlers.
+    pub fn global() -> &'static RequestRecorder {
+        &RECORDER
+    }
+
+    /// Append a request, evicting the oldest entries beyond `limit`.
+    pub fn record(&self, req: &OpenRTBRequest, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+        let mut requests = self.lock();
+        while requests.len() >= limit {
+            requests.pop_front();
+        }
+        requests.push_back(req.clone());
+    }
+
+    /// The `index`-th retained request (0 = oldest).
+    pub fn get(&self, index: usize) -> Option<OpenRTBRequest> {
+        self.lock().get(index).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<OpenRTBRequest>> {
+        // A panic mid-push cannot leave the deque inconsistent; keep serving.
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Consistency problems in an auction response, one message per violation.
+///
+/// Checks that every bid answers an imp of the request, has a non-negative finite
+/// price and, for display bids with a size, uses a standard size. An empty list means the response
+/// is consistent.
+pub fn self_check(
+    req: &OpenRTBRequest,
+    resp: &OpenRTBResponse,
+    cfg: &AuctionConfig,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+    for bid in resp.seatbid.iter().flat_map(|sb| sb.bid.iter()) {
+        if !req.imp.iter().any(|imp| imp.id == bid.impid) {
+            violations.push(format!(
+                "bid {}: impid {:?} not in request",
+                bid.id, bid.impid
+            ));
+        }
+        if !bid.price.is_finite() || bid.price < 0.0 {
+            violations.push(format!("bid {}: invalid price {}", bid.id, bid.price));
+        }
+        // Standard sizes apply to display only; video players come in any size
+        let display = matches!(bid.mtype, None | Some(MediaType::Banner));
+        if let (Some(w), Some(h)) = (bid.w, bid.h) {
+            if display && !is_bid_size(w, h, cfg) {
+                violations.push(format!("bid {}: non-standard size {}x{}", bid.id, w, h));
+            }
+        }
+    }
+    violations
+}
+
+/// Run [`self_check`] and report the result under `ext.mocktioneer.self_check`.
+pub fn attach_self_check(req: &OpenRTBRequest, resp: &mut OpenRTBResponse, cfg: &AuctionConfig) {
+    let violations = self_check(req, resp, cfg);
+    if !violations.is_empty() {
+        log::warn!(
+            "auction {} failed self-check: {}",
+            resp.id,
+            violations.join("; ")
+        );
+    }
+    let ext = resp.ext.get_or_insert_with(|| json!({}));
+    ext["mocktioneer"]["self_check"] = json!(violations);
+}
+
+/// Optional auction behavior that fired for a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// An imp's `bidfloor` suppressed at least one bid.
+    FloorEnforced,
+    /// No seat bid on any imp, so the response carries `nbr`.
+    NoFill,
+    /// A `[[site_rule]]` blocked the request's site.
+    SiteBlocked,
+    /// `[debug] inject_duplicate_bids` added duplicate bids.
+    DuplicateBids,
+    /// A second-price auction (`at` 2) lowered a winning bid's price.
+    SecondPrice,
+    /// GDPR applies without a consent string, so the user's `buyeruid` was not
+    /// echoed on bids.
+    GdprSuppression,
+}
+
+/// Features fired while building one response, in firing order without
+/// duplicates. Reported under `ext.mocktioneer.features` in debug mode.
+#[derive(Debug, Default)]
+pub struct FeatureLog(Vec<Feature>);
+
+impl FeatureLog {
+    pub fn record(&mut self, feature: Feature) {
+        if !self.0.contains(&feature) {
+            self.0.push(feature);
+        }
+    }
+
+    pub fn attach(&self, resp: &mut OpenRTBResponse) {
+        let ext = resp.ext.get_or_insert_with(|| json!({}));
+        ext["mocktioneer"]["features"] = json!(self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auction::build_openrtb_response_with_config;
+    use crate::config::AppConfig;
+    use crate::openrtb::{Banner, Imp};
+    use crate::render::SignatureStatus;
+
+    fn req(id: &str) -> OpenRTBRequest {
+        OpenRTBRequest {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn recorder_keeps_newest_within_limit() {
+        let recorder = RequestRecorder::default();
+        recorder.record(&req("a"), 2);
+        recorder.record(&req("b"), 2);
+        recorder.record(&req("c"), 2);
+        assert_eq!(recorder.len(), 2);
+        assert_eq!(recorder.get(0).unwrap().id, "b");
+        assert_eq!(recorder.get(1).unwrap().id, "c");
+        assert!(recorder.get(2).is_none());
+    }
+
+    #[test]
+    fn recorder_with_zero_limit_records_nothing() {
+        let recorder = RequestRecorder::default();
+        recorder.record(&req("a"), 0);
+        assert!(recorder.is_empty());
+    }
+
+    #[test]
+    fn require_debug_is_not_found_when_disabled() {
+        let cfg = DebugConfig::default();
+        assert!(require_debug(&cfg, "/debug/replay").is_err());
+        let cfg = DebugConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(require_debug(&cfg, "/debug/replay").is_ok());
+    }
+    fn auction() -> (OpenRTBRequest, OpenRTBResponse) {
+        let req = OpenRTBRequest {
+            id: "r-check".to_string(),
+            imp: vec![Imp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let resp = build_openrtb_response_with_config(
+            &req,
+            "host.test",
+            SignatureStatus::NotPresent {
+                reason: "test".to_string(),
+            },
+            &AppConfig::default(),
+        );
+        (req, resp)
+    }
+
+    #[test]
+    fn self_check_passes_for_normal_response() {
+        let (req, mut resp) = auction();
+        attach_self_check(&req, &mut resp, &AuctionConfig::default());
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["self_check"],
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn self_check_reports_broken_bids() {
+        let (req, mut resp) = auction();
+        let bid = &mut resp.seatbid[0].bid[0];
+        bid.impid = "missing".to_string();
+        bid.price = -1.0;
+        bid.w = Some(123);
+        bid.h = Some(45);
+
+        let violations = self_check(&req, &resp, &AuctionConfig::default());
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations[0].contains("impid \"missing\" not in request"));
+        assert!(violations[1].contains("invalid price -1"));
+        assert!(violations[2].contains("non-standard size 123x45"));
+
+        attach_self_check(&req, &mut resp, &AuctionConfig::default());
+        let reported = &resp.ext.unwrap()["mocktioneer"]["self_check"];
+        assert_eq!(reported.as_array().unwrap().len(), 3);
+    }
+
+    fn debug_auction(bidfloor: Option<f64>) -> OpenRTBResponse {
+        debug_auction_for(OpenRTBRequest {
+            id: "r1".to_string(),
+            imp: vec![Imp {
+                id: "1".to_string(),
+                banner: Some(Banner {
+                    w: Some(300),
+                    h: Some(250),
+                    ..Default::default()
+                }),
+                bidfloor,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+    }
+
+    fn debug_auction_for(req: OpenRTBRequest) -> OpenRTBResponse {
+        let cfg = AppConfig::from_toml_str("[debug]\nenabled = true").unwrap();
+        build_openrtb_response_with_config(
+            &req,
+            "host.test",
+            SignatureStatus::NotPresent {
+                reason: "test".to_string(),
+            },
+            &cfg,
+        )
+    }
+
+    #[test]
+    fn features_list_floor_enforcement_and_no_fill() {
+        let resp = debug_auction(Some(1000.0));
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["features"],
+            serde_json::json!(["floor_enforced", "no_fill"])
+        );
+    }
+
+    #[test]
+    fn features_empty_for_plain_request() {
+        let resp = debug_auction(None);
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["features"],
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn features_list_second_price() {
+        let req: OpenRTBRequest = serde_json::from_value(serde_json::json!({
+            "id": "r2",
+            "at": 2,
+            "imp": [{
+                "id": "1",
+                "banner": {"w": 300, "h": 250},
+                "ext": {"mocktioneer": {"num_bids": 2}}
+            }]
+        }))
+        .unwrap();
+        let resp = debug_auction_for(req);
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["features"],
+            serde_json::json!(["second_price"])
+        );
+    }
+
+    #[test]
+    fn features_list_gdpr_suppression_without_consent() {
+        let request = |user: serde_json::Value| -> OpenRTBRequest {
+            serde_json::from_value(serde_json::json!({
+                "id": "r3",
+                "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+                "regs": {"gdpr": 1},
+                "user": user
+            }))
+            .unwrap()
+        };
+        let resp = debug_auction_for(request(serde_json::json!({"buyeruid": "u-1"})));
+        let bid_ext = resp.seatbid[0].bid[0].ext.as_ref().unwrap();
+        assert!(bid_ext["mocktioneer"].get("buyeruid").is_none());
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["features"],
+            serde_json::json!(["gdpr_suppression"])
+        );
+
+        let resp = debug_auction_for(request(
+            serde_json::json!({"buyeruid": "u-1", "consent": "CPXxRfAPXxRfAAfKABENB"}),
+        ));
+        assert_eq!(
+            resp.seatbid[0].bid[0].ext.as_ref().unwrap()["mocktioneer"]["buyeruid"],
+            "u-1"
+        );
+        assert_eq!(
+            resp.ext.unwrap()["mocktioneer"]["features"],
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn features_omitted_without_debug() {
+        let (_, resp) = auction();
+        assert!(resp.ext.is_none());
+    }
+}
@@ -0,0 +1,293 @@
+//! Simulated bidder latency for the auction endpoint.
+//!
+//! `[auction] latency_dist` selects a distribution; samples are drawn from a seeded
+//! xorshift generator so a fixed `latency_seed` reproduces the same delays.
+//! Without one, each delay is drawn from a hash of the request id. A request can
+//! also ask for its own delay with `ext.mocktioneer.delay_ms`.
+//!
+//! Waiting is done by a [`Timer`] each adapter installs at startup, so the delay
+//! sleeps on the platform's own timer instead of spinning the executor.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::auction::fnv1a;
+use crate::config::{AppConfig, AuctionConfig, LatencyDist};
+use crate::openrtb::OpenRTBRequest;
+
+static RNG: LazyLock<Mutex<XorShift64>> = LazyLock::new(|| {
+    let seed = AppConfig::global().auction.latency_seed.unwrap_or_default();
+    Mutex::new(XorShift64::new(seed))
+});
+
+static TIMER: OnceLock<Box<dyn Timer>> = OnceLock::new();
+
+/// Longest delay a latency distribution can produce; larger (or infinite) samples
+/// are capped here.
+pub const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Platform sleep for simulated latency, in real time even when `[clock]` is
+/// frozen. The future need not be `Send`: edge runtimes drive each request on a
+/// single thread.
+pub trait Timer: Send + Sync {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+/// Timer that blocks the calling thread once polled. A deliberate fallback for
+/// Fastly only: Compute has no async timer and each instance serves a single
+/// request, so nothing else waits on the thread. The axum and Cloudflare
+/// adapters install their host timers instead; without any installed timer
+/// (tests driven by a blocking executor) this is also the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlockingTimer;
+
+impl Timer for BlockingTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async move { std::thread::sleep(duration) })
+    }
+}
+
+/// Install the process-wide timer. Only the first call takes effect, so adapters
+/// whose entry point runs per request can call it unconditionally.
+pub fn install_timer(timer: Box<dyn Timer>) {
+    let _ = TIMER.set(timer);
+}
+
+/// Process-wide timer: whatever the adapter installed, else [`BlockingTimer`].
+pub fn timer() -> &'static dyn Timer {
+    TIMER.get_or_init(|| Box::new(BlockingTimer)).as_ref()
+}
+
+/// Small xorshift64 generator; plenty for latency jitter, not for anything secret.
+#[derive(Debug, Clone)]
+pub struct XorShift64(u64);
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift; substitute an arbitrary odd constant.
+        XorShift64(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Uniform sample in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Draw one delay from the configured distribution, or `None` when latency is off.
+/// Samples are clamped to `[0, MAX_DELAY]`; a NaN sample counts as zero.
+pub fn sample(cfg: &AuctionConfig, rng: &mut XorShift64) -> Option<Duration> {
+    let ms = match cfg.latency_dist {
+        LatencyDist::None => return None,
+        LatencyDist::Uniform => {
+            let min = cfg.latency_min_ms as f64;
+            let max = cfg.latency_max_ms.max(cfg.latency_min_ms) as f64;
+            min + (max - min) * rng.next_f64()
+        }
+        LatencyDist::Normal => {
+            // Box-Muller; 1 - u keeps the log argument in (0, 1]
+            let u1 = 1.0 - rng.next_f64();
+            let u2 = rng.next_f64();
+            let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+            cfg.latency_mean_ms + cfg.latency_stddev_ms * z
+        }
+    };
+    let max_ms = MAX_DELAY.as_millis() as f64;
+    Some(Duration::from_secs_f64(ms.max(0.0).min(max_ms) / 1000.0))
+}
+
+/// Next delay for the response to `req`: drawn from the process-wide generator
+/// seeded with `latency_seed`, or without one from a hash of the request id, so
+/// the same request always waits as long.
+pub fn next_delay(cfg: &AuctionConfig, req: &OpenRTBRequest) -> Option<Duration> {
+    if cfg.latency_seed.is_none() {
+        return sample(cfg, &mut XorShift64::new(fnv1a(req.id.bytes())));
+    }
+    let mut rng = RNG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    sample(cfg, &mut rng)
+}
+
+/// Delay requested by `ext.mocktioneer.delay_ms`, honored only when the request
+/// has a `tmax` and capped just below it so the response still beats the timeout,
+/// and never past [`MAX_DELAY`]. A missing delay counts as zero.
+pub fn request_delay(req: &OpenRTBRequest) -> Option<Duration> {
+    let tmax = u64::try_from(req.tmax?).ok()?;
+    let delay_ms = req
+        .ext
+        .as_ref()
+        .and_then(|ext| ext["mocktioneer"]["delay_ms"].as_u64())
+        .unwrap_or(0);
+    let ms = delay_ms
+        .min(tmax.saturating_sub(1))
+        .min(MAX_DELAY.as_millis() as u64);
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    fn cfg(dist: LatencyDist) -> AuctionConfig {
+        AuctionConfig {
+            latency_dist: dist,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_distribution_means_no_delay() {
+        let mut rng = XorShift64::new(1);
+        assert!(sample(&AuctionConfig::default(), &mut rng).is_none());
+    }
+
+    #[test]
+    fn zero_variance_yields_fixed_delay() {
+        let mut rng = XorShift64::new(7);
+        let normal = AuctionConfig {
+            latency_mean_ms: 120.0,
+            latency_stddev_ms: 0.0,
+            ..cfg(LatencyDist::Normal)
+        };
+        let uniform = AuctionConfig {
+            latency_min_ms: 80,
+            latency_max_ms: 80,
+            ..cfg(LatencyDist::Uniform)
+        };
+        for _ in 0..100 {
+            assert_eq!(sample(&normal, &mut rng), Some(Duration::from_millis(120)));
+            assert_eq!(sample(&uniform, &mut rng), Some(Duration::from_millis(80)));
+        }
+    }
+
+    #[test]
+    fn uniform_stays_within_bounds() {
+        let mut rng = XorShift64::new(42);
+        let uniform = AuctionConfig {
+            latency_min_ms: 10,
+            latency_max_ms: 50,
+            ..cfg(LatencyDist::Uniform)
+        };
+        for _ in 0..1000 {
+            let d = sample(&uniform, &mut rng).unwrap();
+            assert!(d >= Duration::from_millis(10) && d < Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn normal_never_goes_negative() {
+        let mut rng = XorShift64::new(3);
+        let normal = AuctionConfig {
+            latency_mean_ms: 0.0,
+            latency_stddev_ms: 100.0,
+            ..cfg(LatencyDist::Normal)
+        };
+        let zeros = (0..1000)
+            .filter(|_| sample(&normal, &mut rng) == Some(Duration::ZERO))
+            .count();
+        assert!(zeros > 0, "about half the samples should clamp to zero");
+    }
+
+    #[test]
+    fn huge_and_infinite_samples_are_capped() {
+        let mut rng = XorShift64::new(5);
+        for mean in [1e300, f64::INFINITY] {
+            let normal = AuctionConfig {
+                latency_mean_ms: mean,
+                latency_stddev_ms: 0.0,
+                ..cfg(LatencyDist::Normal)
+            };
+            assert_eq!(sample(&normal, &mut rng), Some(MAX_DELAY));
+        }
+        let nan = AuctionConfig {
+            latency_mean_ms: f64::NAN,
+            ..cfg(LatencyDist::Normal)
+        };
+        assert_eq!(sample(&nan, &mut rng), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn same_seed_replays_same_samples() {
+        let normal = AuctionConfig {
+            latency_mean_ms: 50.0,
+            latency_stddev_ms: 20.0,
+            ..cfg(LatencyDist::Normal)
+        };
+        let (mut a, mut b) = (XorShift64::new(99), XorShift64::new(99));
+        for _ in 0..10 {
+            assert_eq!(sample(&normal, &mut a), sample(&normal, &mut b));
+        }
+    }
+
+    fn request(tmax: Option<i64>, delay_ms: Option<u64>) -> OpenRTBRequest {
+        OpenRTBRequest {
+            tmax,
+            ext: delay_ms.map(|ms| serde_json::json!({ "mocktioneer": { "delay_ms": ms } })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn request_delay_needs_tmax_and_stays_below_it() {
+        assert_eq!(
+            request_delay(&request(Some(500), Some(40))),
+            Some(Duration::from_millis(40))
+        );
+        assert_eq!(
+            request_delay(&request(Some(100), Some(5000))),
+            Some(Duration::from_millis(99))
+        );
+        assert_eq!(request_delay(&request(None, Some(40))), None);
+        assert_eq!(request_delay(&request(Some(500), None)), None);
+        assert_eq!(request_delay(&request(Some(0), Some(40))), None);
+        assert_eq!(
+            request_delay(&request(Some(i64::MAX), Some(u64::MAX))),
+            Some(MAX_DELAY)
+        );
+    }
+
+    #[test]
+    fn unseeded_delay_follows_request_id() {
+        let uniform = AuctionConfig {
+            latency_min_ms: 0,
+            latency_max_ms: 1000,
+            ..cfg(LatencyDist::Uniform)
+        };
+        let req = |id: &str| OpenRTBRequest {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            next_delay(&uniform, &req("a")),
+            next_delay(&uniform, &req("a"))
+        );
+        assert_ne!(
+            next_delay(&uniform, &req("a")),
+            next_delay(&uniform, &req("b"))
+        );
+    }
+
+    #[test]
+    fn blocking_timer_sleeps_only_when_polled() {
+        let start = SystemClock.now();
+        let sleep = BlockingTimer.sleep(Duration::from_millis(20));
+        assert!(SystemClock.now() - start < Duration::from_millis(20));
+        futures::executor::block_on(sleep);
+        assert!(SystemClock.now() - start >= Duration::from_millis(20));
+    }
+}
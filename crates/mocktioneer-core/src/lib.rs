@@ -1,9 +1,17 @@
 pub mod aps;
 pub mod auction;
+pub mod clock;
+pub mod config;
+pub mod debug;
+pub mod latency;
 pub mod mediation;
 pub mod openrtb;
+pub mod openrtb_proto;
+pub mod prebid;
+pub mod rate_limit;
 pub mod render;
 pub mod routes;
+pub mod usersync;
 pub mod verification;
 
 edgezero_core::app!("../../edgezero.toml", MocktioneerApp);
@@ -1,18 +1,22 @@
 //! Mock Ad Server Mediation
 //!
 //! Provides a simple mediation endpoint that accepts bids from multiple bidders
-//! and selects winners based on price (highest price wins).
+//! and selects winners based on price (highest price wins, or a seeded random
+//! pick among near-top bids when `top_bid_delta` is set). The [`WinnerStrategy`]
+//! chosen by `strategy` decides what the winner pays.
 
+use crate::auction::fnv1a;
+use crate::clock;
+use crate::latency::XorShift64;
 use crate::openrtb::{Bid as OpenRTBBid, Imp, MediaType, OpenRTBRequest, OpenRTBResponse, SeatBid};
 use crate::render::{CreativeMetadata, SignatureStatus};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use uuid::Uuid;
+use std::collections::{BTreeMap, HashMap};
 use validator::Validate;
 
 fn new_id() -> String {
-    Uuid::now_v7().simple().to_string()
+    clock::uuid_v7(clock::global()).simple().to_string()
 }
 
 /// Mediation request containing impression definitions and bidder responses
@@ -91,13 +95,157 @@ pub struct MediationBid {
 }
 
 /// Mediation configuration
-#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
 pub struct MediationConfig {
     /// Minimum acceptable bid price (CPM)
     /// Bids below this floor will be rejected
     #[serde(skip_serializing_if = "Option::is_none")]
     #[validate(range(min = 0.0))]
     pub price_floor: Option<f64>,
+
+    /// Bids within this CPM delta of the top bid compete equally; the winner
+    /// among them is picked at random instead of strictly by price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 0.0))]
+    pub top_bid_delta: Option<f64>,
+
+    /// Seed for the `top_bid_delta` pick, for reproducible winners.
+    /// Defaults to a hash of the request id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+
+    /// How winners are priced. Defaults to first price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<StrategyKind>,
+}
+
+/// Built-in [`WinnerStrategy`] implementations, selected by `config.strategy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    /// [`FirstPrice`]
+    #[default]
+    FirstPrice,
+    /// [`SecondPrice`]
+    SecondPrice,
+}
+
+/// A bid competing for one impression.
+#[derive(Debug, Clone)]
+pub struct CandidateBid {
+    pub bidder: String,
+    pub bid: MediationBid,
+}
+
+/// A bid that won its impression, with the price it clears at.
+#[derive(Debug, Clone)]
+pub struct WinningBid {
+    pub bidder: String,
+    pub bid: MediationBid,
+    pub clearing_price: f64,
+}
+
+/// Picks the winners among one impression's candidates (all above the floor)
+/// and prices them.
+pub trait WinnerStrategy {
+    fn select(&mut self, bids: &[CandidateBid]) -> Vec<WinningBid>;
+}
+
+/// How the winning candidate is chosen, shared by the built-in strategies:
+/// highest price (first candidate on a tie), or with `top_bid_delta` a seeded
+/// random pick among the candidates within the delta of the top price.
+pub struct WinnerPick {
+    pub top_bid_delta: Option<f64>,
+    pub rng: XorShift64,
+}
+
+impl WinnerPick {
+    /// Index of the winning candidate; `None` only when `bids` is empty.
+    fn pick(&mut self, bids: &[CandidateBid]) -> Option<usize> {
+        if bids.is_empty() {
+            return None;
+        }
+        Some(match self.top_bid_delta {
+            Some(delta) => pick_near_top(bids, delta, &mut self.rng),
+            None => highest_bid(bids),
+        })
+    }
+}
+
+/// The winner pays its own bid.
+pub struct FirstPrice(pub WinnerPick);
+
+impl WinnerStrategy for FirstPrice {
+    fn select(&mut self, bids: &[CandidateBid]) -> Vec<WinningBid> {
+        self.0
+            .pick(bids)
+            .map(|index| {
+                let CandidateBid { bidder, bid } = bids[index].clone();
+                WinningBid {
+                    clearing_price: bid.price,
+                    bidder,
+                    bid,
+                }
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+/// The winner pays one cent over the best other bid, or the price floor when
+/// it is the only bid, never more than its own bid. A lone bid with no floor
+/// pays its own price.
+pub struct SecondPrice {
+    pub pick: WinnerPick,
+    pub price_floor: f64,
+}
+
+/// [`SecondPrice`] clearing price for a `winner` bid, given the best other bid
+/// `runner_up` and the price floor (0 for none). The auction endpoint prices
+/// `at = 2` requests with it too.
+pub fn second_price(winner: f64, runner_up: Option<f64>, price_floor: f64) -> f64 {
+    match runner_up {
+        Some(price) => ((price + 0.01) * 100.0).round() / 100.0,
+        None if price_floor > 0.0 => price_floor,
+        None => winner,
+    }
+    .min(winner)
+}
+
+impl WinnerStrategy for SecondPrice {
+    fn select(&mut self, bids: &[CandidateBid]) -> Vec<WinningBid> {
+        let Some(index) = self.pick.pick(bids) else {
+            return Vec::new();
+        };
+        let CandidateBid { bidder, bid } = bids[index].clone();
+        let runner_up = bids
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, other)| other.bid.price)
+            .reduce(f64::max);
+        let clearing_price = second_price(bid.price, runner_up, self.price_floor);
+        vec![WinningBid {
+            bidder,
+            bid,
+            clearing_price,
+        }]
+    }
+}
+
+/// Strategy for `config`, picking near-top winners with `rng`.
+pub fn winner_strategy(config: &MediationConfig, rng: XorShift64) -> Box<dyn WinnerStrategy> {
+    let pick = WinnerPick {
+        top_bid_delta: config.top_bid_delta,
+        rng,
+    };
+    match config.strategy.unwrap_or_default() {
+        StrategyKind::FirstPrice => Box::new(FirstPrice(pick)),
+        StrategyKind::SecondPrice => Box::new(SecondPrice {
+            pick,
+            price_floor: config.price_floor.unwrap_or(0.0),
+        }),
+    }
 }
 
 /// Run mediation algorithm and return winning bids
@@ -105,9 +253,11 @@ pub struct MediationConfig {
 /// Algorithm:
 /// 1. Collect all bids grouped by impression ID
 /// 2. For each impression, select highest price bid (above floor if set)
-/// 3. On price tie, first bidder in array wins
-/// 4. Generate creatives for winning bids that don't have adm
-/// 5. Return OpenRTB response with winning bids grouped by seat
+/// 3. On price tie, first bidder in array wins; with `top_bid_delta`, a seeded
+///    random bid among those within the delta of the top price wins instead
+/// 4. Price winners with the configured [`WinnerStrategy`]
+/// 5. Generate creatives for winning bids that don't have adm
+/// 6. Return OpenRTB response with winning bids grouped by seat
 pub fn mediate_auction(request: MediationRequest, base_host: &str) -> OpenRTBResponse {
     log::info!(
         "Mediation: processing {} impressions with {} bidder responses",
@@ -115,15 +265,19 @@ pub fn mediate_auction(request: MediationRequest, base_host: &str) -> OpenRTBRes
         request.ext.bidder_responses.len()
     );
 
-    // Step 1: Collect all bids grouped by impression ID
-    let mut bids_by_imp: HashMap<String, Vec<(String, MediationBid)>> = HashMap::new();
+    // Step 1: Collect all bids grouped by impression ID (ordered, so a seeded
+    // pick visits impressions in the same order every time)
+    let mut bids_by_imp: BTreeMap<String, Vec<CandidateBid>> = BTreeMap::new();
 
     for bidder_response in request.ext.bidder_responses {
         for bid in bidder_response.bids {
             bids_by_imp
                 .entry(bid.imp_id.clone())
                 .or_default()
-                .push((bidder_response.bidder.clone(), bid));
+                .push(CandidateBid {
+                    bidder: bidder_response.bidder.clone(),
+                    bid,
+                });
         }
     }
 
@@ -132,13 +286,12 @@ pub fn mediate_auction(request: MediationRequest, base_host: &str) -> OpenRTBRes
         bids_by_imp.len()
     );
 
-    // Step 2: Select winner per impression (highest price)
-    let mut winning_bids: HashMap<String, (String, MediationBid)> = HashMap::new();
-    let price_floor = request
-        .ext
-        .config
-        .and_then(|c| c.price_floor)
-        .unwrap_or(0.0);
+    // Step 2: Select and price winners per impression
+    let mut winning_bids: Vec<WinningBid> = Vec::new();
+    let config = request.ext.config.unwrap_or_default();
+    let price_floor = config.price_floor.unwrap_or(0.0);
+    let rng = XorShift64::new(config.seed.unwrap_or_else(|| fnv1a(request.id.bytes())));
+    let mut strategy = winner_strategy(&config, rng);
 
     for (imp_id, mut bids) in bids_by_imp {
         log::debug!(
@@ -148,7 +301,7 @@ pub fn mediate_auction(request: MediationRequest, base_host: &str) -> OpenRTBRes
         );
 
         // Filter by price floor
-        bids.retain(|(_, bid)| bid.price >= price_floor);
+        bids.retain(|candidate| candidate.bid.price >= price_floor);
 
         if bids.is_empty() {
             log::debug!(
@@ -159,38 +312,62 @@ pub fn mediate_auction(request: MediationRequest, base_host: &str) -> OpenRTBRes
             continue;
         }
 
-        // Select highest price (first bidder wins on tie)
-        // Use fold to ensure first bidder wins on price tie
-        let winner = bids
-            .into_iter()
-            .reduce(|acc, current| {
-                match current.1.price.partial_cmp(&acc.1.price) {
-                    Some(Ordering::Greater) => current,
-                    _ => acc, // Keep first on tie or equal
-                }
-            })
-            .unwrap(); // Safe: we checked bids is not empty
-
-        log::info!(
-            "Mediation: '{}' wins impression '{}' at ${:.2}",
-            winner.0,
-            imp_id,
-            winner.1.price
-        );
-
-        winning_bids.insert(imp_id, winner);
+        for winner in strategy.select(&bids) {
+            log::info!(
+                "Mediation: '{}' wins impression '{}' at ${:.2} (bid ${:.2})",
+                winner.bidder,
+                imp_id,
+                winner.clearing_price,
+                winner.bid.price
+            );
+            winning_bids.push(winner);
+        }
     }
 
     // Step 3: Build OpenRTB response grouped by seat (bidder)
-    build_openrtb_response(request.id, request.imp, winning_bids, base_host)
+    build_openrtb_response(
+        request.id,
+        request.imp,
+        winning_bids,
+        base_host,
+        crate::config::AppConfig::global(),
+    )
+}
+
+/// Index of the highest price; the first bidder wins on a tie. `bids` must not
+/// be empty.
+fn highest_bid(bids: &[CandidateBid]) -> usize {
+    (0..bids.len())
+        .reduce(|acc, current| {
+            match bids[current].bid.price.partial_cmp(&bids[acc].bid.price) {
+                Some(Ordering::Greater) => current,
+                _ => acc, // Keep first on tie or equal
+            }
+        })
+        .expect("bids is not empty")
+}
+
+/// Index of a random pick among the bids priced within `delta` of the top bid.
+/// `bids` must not be empty.
+fn pick_near_top(bids: &[CandidateBid], delta: f64, rng: &mut XorShift64) -> usize {
+    let top = bids
+        .iter()
+        .map(|candidate| candidate.bid.price)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let near_top: Vec<usize> = (0..bids.len())
+        .filter(|&i| bids[i].bid.price >= top - delta)
+        .collect();
+    near_top[(rng.next_u64() % near_top.len() as u64) as usize]
 }
 
-/// Build OpenRTB response from winning bids
+/// Build OpenRTB response from winning bids; generated creatives follow the
+/// `[creative]` and `[templates]` settings of `cfg`.
 fn build_openrtb_response(
     id: String,
     imps: Vec<Imp>,
-    winning_bids: HashMap<String, (String, MediationBid)>,
+    winning_bids: Vec<WinningBid>,
     base_host: &str,
+    cfg: &crate::config::AppConfig,
 ) -> OpenRTBResponse {
     // Build a minimal OpenRTBRequest for metadata
     let ortb_request = OpenRTBRequest {
@@ -208,24 +385,36 @@ fn build_openrtb_response(
         response: None,
     };
 
+    let hints = crate::render::AdmHints {
+        sandbox: cfg.creative.sandbox.clone(),
+        iframe_template: cfg.templates.iframe.clone(),
+        ..Default::default()
+    };
+
     // Group winning bids by seat/bidder
     let mut seats: HashMap<String, Vec<OpenRTBBid>> = HashMap::new();
 
-    for (imp_id, (bidder, bid)) in winning_bids {
+    for WinningBid {
+        bidder,
+        bid,
+        clearing_price,
+    } in winning_bids
+    {
+        let imp_id = bid.imp_id;
         // Generate creative if missing (e.g., for APS bids)
         let adm = if let Some(existing_adm) = bid.adm {
             existing_adm
         } else {
             // Generate iframe creative using same logic as OpenRTB endpoint
             let crid = bid.crid.as_deref().unwrap_or(&imp_id);
-            let bid_price = Some(bid.price);
-            crate::render::iframe_html(base_host, crid, bid.w, bid.h, bid_price, &metadata)
+            let bid_price = Some(clearing_price);
+            crate::render::iframe_html(base_host, crid, bid.w, bid.h, bid_price, &metadata, &hints)
         };
 
         let ortb_bid = OpenRTBBid {
             id: new_id(),
             impid: imp_id,
-            price: bid.price,
+            price: clearing_price,
             adm: Some(adm),
             w: Some(bid.w),
             h: Some(bid.h),
@@ -305,6 +494,50 @@ mod tests {
         assert_eq!(bid.h, Some(250));
     }
 
+    #[test]
+    fn test_generated_creatives_use_iframe_template() {
+        let winner = |adm: Option<&str>| WinningBid {
+            bidder: "bidder-a".to_string(),
+            bid: MediationBid {
+                imp_id: "imp1".to_string(),
+                price: 2.50,
+                adm: adm.map(str::to_string),
+                w: 300,
+                h: 250,
+                crid: Some("creative-a".to_string()),
+                adomain: None,
+            },
+            clearing_price: 2.0,
+        };
+        let mut cfg = crate::config::AppConfig::default();
+        cfg.templates.iframe = Some(r#"<div class="custom">{{W}}x{{H}} {{CRID}}</div>"#.into());
+        let imps = vec![Imp {
+            id: "imp1".to_string(),
+            ..Default::default()
+        }];
+        let response = build_openrtb_response(
+            "m-1".to_string(),
+            imps.clone(),
+            vec![winner(None)],
+            "test.host",
+            &cfg,
+        );
+        assert_eq!(
+            response.seatbid[0].bid[0].adm.as_deref(),
+            Some(r#"<div class="custom">300x250 creative-a</div>"#)
+        );
+
+        // Bidder markup is passed through untouched
+        let response = build_openrtb_response(
+            "m-1".to_string(),
+            imps,
+            vec![winner(Some("<b/>"))],
+            "test.host",
+            &cfg,
+        );
+        assert_eq!(response.seatbid[0].bid[0].adm.as_deref(), Some("<b/>"));
+    }
+
     #[test]
     fn test_mediate_multiple_bidders_highest_price_wins() {
         let request = MediationRequest {
@@ -434,6 +667,7 @@ mod tests {
                 ],
                 config: Some(MediationConfig {
                     price_floor: Some(1.00),
+                    ..Default::default()
                 }),
             },
         };
@@ -469,6 +703,7 @@ mod tests {
                 }],
                 config: Some(MediationConfig {
                     price_floor: Some(1.00),
+                    ..Default::default()
                 }),
             },
         };
@@ -479,6 +714,158 @@ mod tests {
         assert_eq!(response.seatbid.len(), 0);
     }
 
+    fn near_top_request(prices: &[(&str, f64)], delta: f64, seed: u64) -> MediationRequest {
+        MediationRequest {
+            id: "test-auction-delta".to_string(),
+            imp: vec![Imp {
+                id: "imp1".to_string(),
+                ..Default::default()
+            }],
+            ext: MediationExt {
+                bidder_responses: prices
+                    .iter()
+                    .map(|(bidder, price)| BidderResponse {
+                        bidder: bidder.to_string(),
+                        bids: vec![MediationBid {
+                            imp_id: "imp1".to_string(),
+                            price: *price,
+                            adm: Some("<div>Ad</div>".to_string()),
+                            w: 300,
+                            h: 250,
+                            crid: None,
+                            adomain: None,
+                        }],
+                    })
+                    .collect(),
+                config: Some(MediationConfig {
+                    top_bid_delta: Some(delta),
+                    seed: Some(seed),
+                    ..Default::default()
+                }),
+            },
+        }
+    }
+
+    fn winning_seat(response: &OpenRTBResponse) -> String {
+        response.seatbid[0].seat.clone().unwrap()
+    }
+
+    #[test]
+    fn test_mediate_top_bid_delta_picks_any_near_top_bid() {
+        let prices = [("bidder-a", 2.00), ("bidder-b", 1.95), ("bidder-c", 1.00)];
+        let winners: std::collections::HashSet<String> = (0..64)
+            .map(|seed| winning_seat(&mediate_auction(near_top_request(&prices, 0.10, seed), "h")))
+            .collect();
+
+        assert!(winners.contains("bidder-a"));
+        assert!(winners.contains("bidder-b"));
+        assert!(!winners.contains("bidder-c"));
+    }
+
+    #[test]
+    fn test_mediate_top_bid_delta_same_seed_same_winner() {
+        let prices = [("bidder-a", 2.00), ("bidder-b", 1.99)];
+        for seed in 0..16 {
+            let first = mediate_auction(near_top_request(&prices, 0.05, seed), "h");
+            let second = mediate_auction(near_top_request(&prices, 0.05, seed), "h");
+            assert_eq!(winning_seat(&first), winning_seat(&second));
+        }
+    }
+
+    #[test]
+    fn test_mediate_top_bid_delta_without_seed_follows_request_id() {
+        let prices = [("bidder-a", 2.00), ("bidder-b", 1.99)];
+        let unseeded = |id: String| {
+            let mut request = near_top_request(&prices, 0.05, 0);
+            request.id = id;
+            if let Some(config) = request.ext.config.as_mut() {
+                config.seed = None;
+            }
+            winning_seat(&mediate_auction(request, "h"))
+        };
+        let winners: std::collections::HashSet<String> = (0..64)
+            .map(|i| {
+                let id = format!("auction-{}", i);
+                let winner = unseeded(id.clone());
+                assert_eq!(winner, unseeded(id));
+                winner
+            })
+            .collect();
+        assert_eq!(winners.len(), 2);
+    }
+
+    fn strategy_request(
+        prices: &[(&str, f64)],
+        strategy: StrategyKind,
+        price_floor: Option<f64>,
+    ) -> MediationRequest {
+        let mut request = near_top_request(prices, 0.0, 0);
+        for response in &mut request.ext.bidder_responses {
+            response.bids[0].crid = Some(format!("{}-creative", response.bidder));
+        }
+        request.ext.config = Some(MediationConfig {
+            price_floor,
+            strategy: Some(strategy),
+            ..Default::default()
+        });
+        request
+    }
+
+    #[test]
+    fn test_mediate_second_price_changes_price_not_winner() {
+        let prices = [("bidder-a", 2.00), ("bidder-b", 3.00), ("bidder-c", 1.00)];
+        let first = mediate_auction(
+            strategy_request(&prices, StrategyKind::FirstPrice, None),
+            "h",
+        );
+        let second = mediate_auction(
+            strategy_request(&prices, StrategyKind::SecondPrice, None),
+            "h",
+        );
+
+        assert_eq!(winning_seat(&first), "bidder-b");
+        assert_eq!(winning_seat(&second), "bidder-b");
+        let (first, second) = (&first.seatbid[0].bid[0], &second.seatbid[0].bid[0]);
+        assert_eq!(first.crid, second.crid);
+        assert_eq!(first.adm, second.adm);
+        assert_eq!(first.price, 3.00);
+        assert_eq!(second.price, 2.01);
+    }
+
+    #[test]
+    fn test_mediate_second_price_lone_bid_pays_floor() {
+        let prices = [("bidder-a", 3.00)];
+        let response = mediate_auction(
+            strategy_request(&prices, StrategyKind::SecondPrice, Some(1.50)),
+            "h",
+        );
+        assert_eq!(response.seatbid[0].bid[0].price, 1.50);
+
+        let response = mediate_auction(
+            strategy_request(&prices, StrategyKind::SecondPrice, None),
+            "h",
+        );
+        assert_eq!(response.seatbid[0].bid[0].price, 3.00);
+
+        // A runner-up within a cent never makes the winner pay over its bid
+        let prices = [("bidder-a", 3.00), ("bidder-b", 3.00)];
+        let response = mediate_auction(
+            strategy_request(&prices, StrategyKind::SecondPrice, None),
+            "h",
+        );
+        assert_eq!(winning_seat(&response), "bidder-a");
+        assert_eq!(response.seatbid[0].bid[0].price, 3.00);
+    }
+
+    #[test]
+    fn test_mediate_top_bid_delta_clear_winner_always_wins() {
+        let prices = [("bidder-a", 1.00), ("bidder-b", 3.00)];
+        for seed in 0..16 {
+            let response = mediate_auction(near_top_request(&prices, 0.50, seed), "h");
+            assert_eq!(winning_seat(&response), "bidder-b");
+        }
+    }
+
     #[test]
     fn test_mediate_multiple_impressions() {
         let request = MediationRequest {
@@ -859,6 +1246,7 @@ mod tests {
                 }],
                 config: Some(MediationConfig {
                     price_floor: Some(-1.0), // Negative floor should fail
+                    ..Default::default()
                 }),
             },
         };
@@ -917,6 +1305,7 @@ mod tests {
                 }],
                 config: Some(MediationConfig {
                     price_floor: Some(1.0),
+                    ..Default::default()
                 }),
             },
         };
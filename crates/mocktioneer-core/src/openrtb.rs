@@ -1,7 +1,10 @@
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use validator::{Validate, ValidationError, ValidationErrors};
 
+use crate::config::{AppConfig, DefaultMedia};
+
 // OpenRTB 2.x MarkupType for Bid.mtype (aka media/markup type)
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
@@ -12,6 +15,18 @@ pub enum MediaType {
     Native = 4,
 }
 
+impl MediaType {
+    /// Lowercase name, as used in seat suffixes and logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaType::Banner => "banner",
+            MediaType::Video => "video",
+            MediaType::Audio => "audio",
+            MediaType::Native => "native",
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Validate)]
 pub struct OpenRTBRequest {
     #[validate(length(min = 1))]
@@ -27,6 +42,7 @@ pub struct OpenRTBRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub at: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_currencies"))]
     pub cur: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bcat: Option<Vec<String>>,
@@ -70,6 +86,11 @@ pub struct Imp {
     pub native: Option<Native>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pmp: Option<Pmp>,
+    /// Rendering SDK name, e.g. `GoogleMobileAds`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub displaymanager: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub displaymanagerver: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tagid: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -78,6 +99,9 @@ pub struct Imp {
     pub bidfloor: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bidfloorcur: Option<String>,
+    /// Browser for click-throughs: 0 = embedded (in-app), 1 = native.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clickbrowser: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub secure: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -96,6 +120,28 @@ pub struct ImpExt {
 pub struct ExtMocktioneer {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bid: Option<f64>,
+    /// Creative id to echo back instead of the generated `mocktioneer-{impid}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crid: Option<String>,
+    /// Ad id to echo back instead of the generated `ad-{crid}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adid: Option<String>,
+    /// Number of bids to return for this imp, each from its own seat (capped at 10).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_bids: Option<i64>,
+    /// Skip this imp entirely, for testing empty and partially filled auctions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_bid: Option<bool>,
+    /// Bid without `adm` or `nurl`, for testing bids that carry no markup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_creative: Option<bool>,
+    /// `[min, max]` CPM range for a pseudo-random price, reproducible for the same
+    /// request id and imp id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price_range: Option<[f64; 2]>,
+    /// Serve a creative that registers with the host SafeFrame API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safeframe: Option<bool>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -122,8 +168,11 @@ pub struct Banner {
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Validate)]
 pub struct Format {
+    /// Absent (0) on flexible formats that only give a ratio or minimum size.
+    #[serde(default)]
     #[validate(range(min = 1))]
     pub w: i64,
+    #[serde(default)]
     #[validate(range(min = 1))]
     pub h: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -146,18 +195,38 @@ impl Validate for Imp {
             errors.add("id", error);
         }
 
-        let has_media = self.banner.is_some()
-            || self.video.is_some()
-            || self.audio.is_some()
-            || self.native.is_some();
-        if !has_media {
-            let mut error = ValidationError::new("missing_media");
-            error.message = Some(
-                "imp requires at least one creative object (banner/video/audio/native)".into(),
-            );
+        if let Err(error) = validate_imp_media(self, AppConfig::global().parsing.default_media) {
             errors.add("media", error);
         }
 
+        if let Some(exp) = self.exp {
+            if let Err(error) = validate_imp_exp(exp, AppConfig::global().auction.max_exp) {
+                errors.add("exp", error);
+            }
+        }
+
+        let formats = self.banner.as_ref().and_then(|b| b.format.as_deref());
+        if let Err(error) = validate_format_ratios(formats.unwrap_or_default()) {
+            errors.add("banner", error);
+        }
+
+        if let Some(video) = &self.video {
+            if let Err(error) = validate_video_size(video) {
+                errors.add("video", error);
+            }
+        }
+
+        let price_range = self
+            .ext
+            .as_ref()
+            .and_then(|ext| ext.mocktioneer.as_ref())
+            .and_then(|m| m.price_range);
+        if let Some(range) = price_range {
+            if let Err(error) = validate_price_range(range) {
+                errors.add("price_range", error);
+            }
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -166,6 +235,223 @@ impl Validate for Imp {
     }
 }
 
+impl Imp {
+    /// Whether the imp carries a banner, video, audio or native object.
+    pub fn has_media(&self) -> bool {
+        self.banner.is_some()
+            || self.video.is_some()
+            || self.audio.is_some()
+            || self.native.is_some()
+    }
+}
+
+/// An imp needs a media object unless lenient parsing supplies `default_media`.
+pub fn validate_imp_media(
+    imp: &Imp,
+    default_media: Option<DefaultMedia>,
+) -> Result<(), ValidationError> {
+    if imp.has_media() || default_media.is_some() {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("missing_media");
+    error.message =
+        Some("imp requires at least one creative object (banner/video/audio/native)".into());
+    Err(error)
+}
+
+/// Give every media-less imp the `[parsing] default_media` object: a 300x250
+/// banner, or a video with the default player size.
+pub fn apply_default_media(req: &mut OpenRTBRequest, media: DefaultMedia) {
+    for imp in req.imp.iter_mut().filter(|imp| !imp.has_media()) {
+        match media {
+            DefaultMedia::Banner => {
+                imp.banner = Some(Banner {
+                    w: Some(300),
+                    h: Some(250),
+                    ..Default::default()
+                })
+            }
+            DefaultMedia::Video => imp.video = Some(Video::default()),
+        }
+    }
+}
+
+/// Top-level keys of a JSON request that `OpenRTBRequest` has no field for.
+/// Non-objects have none.
+pub fn unknown_request_fields(json: &serde_json::Value) -> Vec<&str> {
+    let known = struct_fields::<OpenRTBRequest>();
+    json.as_object()
+        .into_iter()
+        .flat_map(|object| object.keys())
+        .map(String::as_str)
+        .filter(|key| !known.contains(key))
+        .collect()
+}
+
+/// Field names `T`'s derived `Deserialize` accepts, so the strict check can't
+/// drift from the struct definition.
+fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    // The capture always errors out after recording the names
+    let _ = T::deserialize(FieldCapture(&mut fields));
+    fields
+}
+
+/// Deserializer that records the field list handed to `deserialize_struct`.
+struct FieldCapture<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldCapture<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("fields captured"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+        enum identifier ignored_any
+    }
+}
+
+/// A non-fatal problem with the request shape, reported in the response's
+/// `ext.warnings` the way Prebid Server does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RequestWarning {
+    /// Dotted path of the offending field.
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+/// Deprecated or misspelled fields in `req`. None of them fail the request.
+pub fn request_warnings(req: &OpenRTBRequest) -> Vec<RequestWarning> {
+    let ext_has =
+        |ext: Option<&serde_json::Value>, key: &str| ext.and_then(|ext| ext.get(key)).is_some();
+    let site = req.site.as_ref();
+    let user = req.user.as_ref();
+    let checks = [
+        (
+            site.is_some_and(|s| s.ref_.is_some()),
+            "site.ref_",
+            "not an OpenRTB field and ignored; use site.ref",
+        ),
+        (
+            user.is_some_and(|u| u.yob.is_some()),
+            "user.yob",
+            "deprecated in OpenRTB 2.6",
+        ),
+        (
+            user.is_some_and(|u| u.gender.is_some()),
+            "user.gender",
+            "deprecated in OpenRTB 2.6",
+        ),
+        (
+            ext_has(user.and_then(|u| u.ext.as_ref()), "consent"),
+            "user.ext.consent",
+            "moved to user.consent in OpenRTB 2.6",
+        ),
+        (
+            ext_has(req.regs.as_ref().and_then(|r| r.ext.as_ref()), "gdpr"),
+            "regs.ext.gdpr",
+            "moved to regs.gdpr in OpenRTB 2.6",
+        ),
+        (
+            ext_has(req.source.as_ref().and_then(|s| s.ext.as_ref()), "schain"),
+            "source.ext.schain",
+            "moved to source.schain in OpenRTB 2.6",
+        ),
+    ];
+    checks
+        .into_iter()
+        .filter(|(found, _, _)| *found)
+        .map(|(_, field, message)| RequestWarning { field, message })
+        .collect()
+}
+
+/// Every `cur` entry must be shaped like an ISO-4217 code: three uppercase
+/// ASCII letters. Whether we can convert the currency is decided later.
+pub fn validate_currencies(cur: &[String]) -> Result<(), ValidationError> {
+    let iso_shaped =
+        |code: &String| code.len() == 3 && code.bytes().all(|b| b.is_ascii_uppercase());
+    if cur.iter().all(iso_shaped) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("currency");
+    error.message = Some("cur entries must be 3-letter uppercase ISO-4217 codes".into());
+    Err(error)
+}
+
+/// `imp.exp` must be a positive number of seconds no larger than `max`.
+pub fn validate_imp_exp(exp: i64, max: i64) -> Result<(), ValidationError> {
+    if (1..=max).contains(&exp) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("range");
+    error.message = Some(format!("imp.exp must be between 1 and {} seconds", max).into());
+    Err(error)
+}
+
+/// Largest ad dimension accepted from a request, in pixels.
+pub const MAX_AD_DIMENSION: i64 = 4096;
+
+/// `video.w`/`video.h`, when given, must be between 1 and [`MAX_AD_DIMENSION`].
+pub fn validate_video_size(video: &Video) -> Result<(), ValidationError> {
+    let in_range = |d: Option<i64>| d.is_none_or(|d| (1..=MAX_AD_DIMENSION).contains(&d));
+    if in_range(video.w) && in_range(video.h) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("range");
+    error.message = Some(
+        format!(
+            "video.w and video.h must be between 1 and {}",
+            MAX_AD_DIMENSION
+        )
+        .into(),
+    );
+    Err(error)
+}
+
+/// `banner.format[].wratio`/`hratio`, when given, must be between 1 and
+/// [`MAX_AD_DIMENSION`].
+pub fn validate_format_ratios(formats: &[Format]) -> Result<(), ValidationError> {
+    let in_range = |r: Option<i64>| r.is_none_or(|r| (1..=MAX_AD_DIMENSION).contains(&r));
+    if formats
+        .iter()
+        .all(|f| in_range(f.wratio) && in_range(f.hratio))
+    {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("range");
+    error.message = Some(
+        format!(
+            "format.wratio and format.hratio must be between 1 and {}",
+            MAX_AD_DIMENSION
+        )
+        .into(),
+    );
+    Err(error)
+}
+
+/// `imp.ext.mocktioneer.price_range` must be `[min, max]` with `0 <= min <= max`.
+pub fn validate_price_range([min, max]: [f64; 2]) -> Result<(), ValidationError> {
+    if min.is_finite() && max.is_finite() && 0.0 <= min && min <= max {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("range");
+    error.message = Some("price_range must be [min, max] with 0 <= min <= max".into());
+    Err(error)
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct OpenRTBResponse {
     pub id: String,
@@ -372,6 +658,9 @@ pub struct Device {
     pub pxratio: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub js: Option<i64>,
+    /// Network connection type (OpenRTB list 5.22), e.g. 2 = WiFi, 4 = 2G.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connectiontype: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub geofetch: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -440,10 +729,53 @@ pub struct User {
 pub struct Regs {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub coppa: Option<i64>,
+    /// 1 when GDPR applies (OpenRTB 2.6; older requests use `regs.ext.gdpr`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gdpr: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ext: Option<serde_json::Value>,
 }
 
+impl Regs {
+    /// `regs.ext.dsa`, if present and well-formed.
+    pub fn dsa(&self) -> Option<Dsa> {
+        let dsa = self.ext.as_ref()?.get("dsa")?;
+        serde_json::from_value(dsa.clone()).ok()
+    }
+}
+
+/// Digital Services Act signals from `regs.ext.dsa` (IAB DSA Transparency
+/// extension).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Dsa {
+    /// 0 not required, 1 supported, 2 required, 3 required and the publisher
+    /// is an online platform.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dsarequired: Option<i64>,
+    /// 0 publisher can't render, 1 might render, 2 will render.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubrender: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datatopub: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transparency: Option<Vec<DsaTransparency>>,
+}
+
+impl Dsa {
+    /// Whether bids must carry DSA transparency info (`dsarequired` 2 or 3).
+    pub fn required(&self) -> bool {
+        matches!(self.dsarequired, Some(2 | 3))
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DsaTransparency {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dsaparams: Option<Vec<i64>>,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Source {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -569,3 +901,144 @@ pub struct Native {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ext: Option<serde_json::Value>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn media_less_request() -> OpenRTBRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": "r1",
+            "imp": [{"id": "1"}, {"id": "2", "banner": {"w": 728, "h": 90}}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn media_less_imp_is_rejected_only_in_strict_mode() {
+        let req = media_less_request();
+        let err = validate_imp_media(&req.imp[0], None).unwrap_err();
+        assert_eq!(err.code, "missing_media");
+        assert!(validate_imp_media(&req.imp[0], Some(DefaultMedia::Banner)).is_ok());
+        assert!(validate_imp_media(&req.imp[1], None).is_ok());
+    }
+
+    #[test]
+    fn price_range_must_be_ordered_and_non_negative() {
+        assert!(validate_price_range([0.5, 2.0]).is_ok());
+        assert!(validate_price_range([1.0, 1.0]).is_ok());
+        assert!(validate_price_range([2.0, 0.5]).is_err());
+        assert!(validate_price_range([-1.0, 2.0]).is_err());
+        assert!(validate_price_range([0.0, f64::INFINITY]).is_err());
+    }
+
+    #[test]
+    fn video_size_must_be_in_range() {
+        let video = |w, h| Video {
+            w,
+            h,
+            ..Default::default()
+        };
+        assert!(validate_video_size(&video(None, None)).is_ok());
+        assert!(validate_video_size(&video(Some(640), Some(480))).is_ok());
+        assert!(validate_video_size(&video(Some(0), Some(480))).is_err());
+        assert!(validate_video_size(&video(Some(4_000_000_000), Some(4_000_000_000))).is_err());
+    }
+
+    #[test]
+    fn format_ratios_must_be_in_range() {
+        let format = |wratio, hratio| Format {
+            wratio,
+            hratio,
+            ..Default::default()
+        };
+        assert!(validate_format_ratios(&[]).is_ok());
+        assert!(validate_format_ratios(&[format(None, None)]).is_ok());
+        assert!(validate_format_ratios(&[format(Some(16), Some(9))]).is_ok());
+        assert!(validate_format_ratios(&[format(Some(0), Some(9))]).is_err());
+        assert!(validate_format_ratios(&[format(Some(1), Some(100_000_000_000_000_000))]).is_err());
+    }
+
+    #[test]
+    fn apply_default_media_fills_only_media_less_imps() {
+        let mut req = media_less_request();
+        apply_default_media(&mut req, DefaultMedia::Banner);
+        let banner = req.imp[0].banner.as_ref().unwrap();
+        assert_eq!((banner.w, banner.h), (Some(300), Some(250)));
+        assert_eq!(req.imp[1].banner.as_ref().unwrap().w, Some(728));
+
+        let mut req = media_less_request();
+        apply_default_media(&mut req, DefaultMedia::Video);
+        assert!(req.imp[0].video.is_some());
+        assert!(req.imp[1].video.is_none());
+    }
+
+    #[test]
+    fn currencies_must_be_iso_shaped() {
+        let cur = |codes: &[&str]| codes.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert!(validate_currencies(&cur(&[])).is_ok());
+        assert!(validate_currencies(&cur(&["USD", "EUR"])).is_ok());
+        assert!(validate_currencies(&cur(&["US"])).is_err());
+        assert!(validate_currencies(&cur(&["USDD"])).is_err());
+        assert!(validate_currencies(&cur(&["usd"])).is_err());
+    }
+
+    #[test]
+    fn unknown_request_fields_lists_typos_only() {
+        let json = serde_json::json!({
+            "id": "r1",
+            "imp": [],
+            "tmax": 100,
+            "ext": {"anything": true},
+            "sitee": {},
+            "curr": ["USD"]
+        });
+        assert_eq!(unknown_request_fields(&json), vec!["curr", "sitee"]);
+        assert!(unknown_request_fields(&serde_json::json!([])).is_empty());
+    }
+
+    #[test]
+    fn request_warnings_flag_deprecated_fields() {
+        assert!(request_warnings(&media_less_request()).is_empty());
+
+        let req: OpenRTBRequest = serde_json::from_value(serde_json::json!({
+            "id": "r1",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+            "site": {"ref_": "https://example.com/"},
+            "user": {"yob": 1990, "ext": {"consent": "CO..."}},
+            "regs": {"ext": {"gdpr": 1}}
+        }))
+        .unwrap();
+        let fields: Vec<&str> = request_warnings(&req).iter().map(|w| w.field).collect();
+        assert_eq!(
+            fields,
+            vec!["site.ref_", "user.yob", "user.ext.consent", "regs.ext.gdpr"]
+        );
+    }
+
+    #[test]
+    fn regs_dsa_parses_from_ext() {
+        let regs: Regs = serde_json::from_value(serde_json::json!({
+            "ext": {"dsa": {
+                "dsarequired": 3,
+                "pubrender": 0,
+                "transparency": [{"domain": "pub.test", "dsaparams": [1]}]
+            }}
+        }))
+        .unwrap();
+        let dsa = regs.dsa().unwrap();
+        assert!(dsa.required());
+        assert_eq!(dsa.pubrender, Some(0));
+        assert_eq!(
+            dsa.transparency.unwrap()[0].domain.as_deref(),
+            Some("pub.test")
+        );
+
+        assert!(!Regs::default().dsa().is_some_and(|dsa| dsa.required()));
+        let supported = Dsa {
+            dsarequired: Some(1),
+            ..Default::default()
+        };
+        assert!(!supported.required());
+    }
+}
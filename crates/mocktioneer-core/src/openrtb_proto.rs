@@ -0,0 +1,165 @@
+//! Protobuf encoding of OpenRTB bid responses.
+//!
+//! Served by `/openrtb2/auction` when the request sends
+//! `Accept: application/x-protobuf`. The messages are a proto2 subset of the
+//! IAB `openrtb.proto` binding, keeping its field numbers. JSON `ext` objects
+//! have no counterpart there and are dropped.
+
+use prost::Message;
+
+use crate::openrtb::{Bid as OpenrtbBid, OpenRTBResponse, SeatBid as OpenrtbSeatBid};
+
+/// Content type of protobuf auction responses.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BidResponse {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(message, repeated, tag = "2")]
+    pub seatbid: Vec<SeatBid>,
+    #[prost(string, optional, tag = "3")]
+    pub bidid: Option<String>,
+    #[prost(string, optional, tag = "4")]
+    pub cur: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub customdata: Option<String>,
+    #[prost(int32, optional, tag = "6")]
+    pub nbr: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SeatBid {
+    #[prost(message, repeated, tag = "1")]
+    pub bid: Vec<Bid>,
+    #[prost(string, optional, tag = "2")]
+    pub seat: Option<String>,
+    #[prost(bool, optional, tag = "3")]
+    pub group: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Bid {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(string, required, tag = "2")]
+    pub impid: String,
+    #[prost(double, required, tag = "3")]
+    pub price: f64,
+    #[prost(string, optional, tag = "4")]
+    pub adid: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub nurl: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub adm: Option<String>,
+    #[prost(string, repeated, tag = "7")]
+    pub adomain: Vec<String>,
+    #[prost(string, optional, tag = "10")]
+    pub crid: Option<String>,
+    #[prost(string, optional, tag = "13")]
+    pub dealid: Option<String>,
+    #[prost(int32, optional, tag = "16")]
+    pub w: Option<i32>,
+    #[prost(int32, optional, tag = "17")]
+    pub h: Option<i32>,
+    #[prost(int32, optional, tag = "21")]
+    pub exp: Option<i32>,
+    #[prost(string, optional, tag = "22")]
+    pub burl: Option<String>,
+    #[prost(string, optional, tag = "23")]
+    pub lurl: Option<String>,
+}
+
+/// Saturating narrowing for the binding's `int32` fields.
+fn int32(value: i64) -> i32 {
+    value.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+}
+
+impl From<&OpenrtbBid> for Bid {
+    fn from(bid: &OpenrtbBid) -> Self {
+        Bid {
+            id: bid.id.clone(),
+            impid: bid.impid.clone(),
+            price: bid.price,
+            adid: bid.adid.clone(),
+            nurl: bid.nurl.clone(),
+            adm: bid.adm.clone(),
+            adomain: bid.adomain.clone().unwrap_or_default(),
+            crid: bid.crid.clone(),
+            dealid: bid.dealid.clone(),
+            w: bid.w.map(int32),
+            h: bid.h.map(int32),
+            exp: bid.exp.map(int32),
+            burl: bid.burl.clone(),
+            lurl: bid.lurl.clone(),
+        }
+    }
+}
+
+impl From<&OpenrtbSeatBid> for SeatBid {
+    fn from(seatbid: &OpenrtbSeatBid) -> Self {
+        SeatBid {
+            bid: seatbid.bid.iter().map(Bid::from).collect(),
+            seat: seatbid.seat.clone(),
+            group: seatbid.group.map(|group| group != 0),
+        }
+    }
+}
+
+impl From<&OpenRTBResponse> for BidResponse {
+    fn from(resp: &OpenRTBResponse) -> Self {
+        BidResponse {
+            id: resp.id.clone(),
+            seatbid: resp.seatbid.iter().map(SeatBid::from).collect(),
+            bidid: resp.bidid.clone(),
+            cur: resp.cur.clone(),
+            customdata: resp.customdata.clone(),
+            nbr: resp.nbr.map(int32),
+        }
+    }
+}
+
+/// `resp` encoded as a protobuf `BidResponse`.
+pub fn to_proto(resp: &OpenRTBResponse) -> Vec<u8> {
+    BidResponse::from(resp).encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_proto_round_trips_bids() {
+        let resp = OpenRTBResponse {
+            id: "r1".to_string(),
+            cur: Some("USD".to_string()),
+            seatbid: vec![OpenrtbSeatBid {
+                seat: Some("mocktioneer".to_string()),
+                bid: vec![OpenrtbBid {
+                    id: "b1".to_string(),
+                    impid: "1".to_string(),
+                    price: 2.5,
+                    crid: Some("c1".to_string()),
+                    adomain: Some(vec!["example.com".to_string()]),
+                    w: Some(300),
+                    h: Some(250),
+                    ext: Some(serde_json::json!({"dropped": true})),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let decoded = BidResponse::decode(to_proto(&resp).as_slice()).unwrap();
+        assert_eq!(decoded.id, "r1");
+        assert_eq!(decoded.cur.as_deref(), Some("USD"));
+        assert_eq!(decoded.seatbid[0].seat.as_deref(), Some("mocktioneer"));
+        let bid = &decoded.seatbid[0].bid[0];
+        assert_eq!(bid.impid, "1");
+        assert_eq!(bid.price, 2.5);
+        assert_eq!(bid.crid.as_deref(), Some("c1"));
+        assert_eq!(bid.adomain, vec!["example.com"]);
+        assert_eq!((bid.w, bid.h), (Some(300), Some(250)));
+        assert_eq!(decoded.nbr, None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Prebid Server response shape for `[prebid] server_mode`.
+//!
+//! PBS fronts several bidders and reports on each of them in the response
+//! `ext`: `responsetimemillis` maps every bidder called to its latency, and
+//! `debug.resolvedrequest` echoes the request after PBS filled in its defaults
+//! (only with `[debug] enabled`, since it repeats user and device data).
+//! Here the bidders are the configured seats the request's `wseat`/`bseat`
+//! let bid, all answering after the same simulated delay.
+//!
+//! Independently of that mode, every bid carries the `ext.prebid.meta` block
+//! Prebid.js shows in its bid details.
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::auction::{active_seats, seat_allowed};
+use crate::config::AppConfig;
+use crate::openrtb::{Bid, MediaType, OpenRTBRequest, OpenRTBResponse};
+
+/// Add the PBS `ext.responsetimemillis` block to `resp`, listing the seats that
+/// bid, and `ext.debug` when `[debug] enabled`. `response_time` is the delay
+/// every seat took to answer.
+pub fn attach_server_ext(
+    req: &OpenRTBRequest,
+    resp: &mut OpenRTBResponse,
+    response_time: Duration,
+    cfg: &AppConfig,
+) {
+    let millis = response_time.as_millis() as u64;
+    let times: serde_json::Map<String, serde_json::Value> = active_seats(cfg)
+        .iter()
+        .filter(|seat| seat_allowed(req, seat))
+        .map(|seat| (seat.name.clone(), json!(millis)))
+        .collect();
+    let ext = resp.ext.get_or_insert_with(|| json!({}));
+    ext["responsetimemillis"] = json!(times);
+    if cfg.debug.enabled {
+        ext["debug"]["resolvedrequest"] = json!(req);
+    }
+}
+
+/// Add `ext.prebid.meta` to `bid`: its `adomain` as `advertiserDomains`, its
+/// `mtype` as `mediaType` (banner when unset) and `network_name`.
+pub fn attach_bid_meta(bid: &mut Bid, network_name: &str) {
+    let meta = json!({
+        "advertiserDomains": bid.adomain.clone().unwrap_or_default(),
+        "mediaType": bid.mtype.unwrap_or(MediaType::Banner).as_str(),
+        "networkName": network_name,
+    });
+    let ext = bid.ext.get_or_insert_with(|| json!({}));
+    ext["prebid"]["meta"] = meta;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openrtb::{Banner, Imp};
+
+    #[test]
+    fn server_ext_lists_seats_and_echoes_request() {
+        let req = OpenRTBRequest {
+            id: "r-pbs".to_string(),
+            imp: vec![Imp {
+                id: "1".to_string(),
+                banner: Some(Banner::default()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut resp = OpenRTBResponse {
+            id: "r-pbs".to_string(),
+            ext: Some(json!({"mocktioneer": {"features": []}})),
+            ..Default::default()
+        };
+        let mut cfg = AppConfig::default();
+        let mut quiet = resp.clone();
+        attach_server_ext(&req, &mut quiet, Duration::from_millis(42), &cfg);
+        let ext = quiet.ext.unwrap();
+        assert_eq!(ext["responsetimemillis"], json!({"mocktioneer": 42}));
+        // The request echo is debug-only
+        assert!(ext.get("debug").is_none());
+
+        cfg.debug.enabled = true;
+        attach_server_ext(&req, &mut resp, Duration::from_millis(42), &cfg);
+
+        let ext = resp.ext.unwrap();
+        assert_eq!(ext["responsetimemillis"], json!({"mocktioneer": 42}));
+        assert_eq!(ext["debug"]["resolvedrequest"]["id"], "r-pbs");
+        assert_eq!(ext["debug"]["resolvedrequest"]["imp"][0]["id"], "1");
+        assert!(ext["mocktioneer"].get("features").is_some());
+    }
+
+    #[test]
+    fn server_ext_skips_seats_excluded_by_wseat_and_bseat() {
+        let mut cfg = AppConfig::default();
+        let mut other = cfg.seats[0].clone();
+        other.name = "other".to_string();
+        cfg.seats.push(other);
+        let times = |wseat: Option<&str>, bseat: Option<&str>| {
+            let req = OpenRTBRequest {
+                id: "r-seats".to_string(),
+                wseat: wseat.map(|s| vec![s.to_string()]),
+                bseat: bseat.map(|s| vec![s.to_string()]),
+                ..Default::default()
+            };
+            let mut resp = OpenRTBResponse::default();
+            attach_server_ext(&req, &mut resp, Duration::from_millis(7), &cfg);
+            resp.ext.unwrap()["responsetimemillis"].clone()
+        };
+        assert_eq!(times(None, None), json!({"mocktioneer": 7, "other": 7}));
+        assert_eq!(times(Some("other"), None), json!({"other": 7}));
+        assert_eq!(times(None, Some("other")), json!({"mocktioneer": 7}));
+    }
+
+    #[test]
+    fn bid_meta_describes_advertiser_and_media_type() {
+        let mut bid = Bid {
+            adomain: Some(vec!["brand.test".to_string()]),
+            mtype: Some(MediaType::Video),
+            ext: Some(json!({"mocktioneer": {"bid": 2.5}})),
+            ..Default::default()
+        };
+        attach_bid_meta(&mut bid, "Mock Network");
+
+        let ext = bid.ext.unwrap();
+        assert_eq!(
+            ext["prebid"]["meta"],
+            json!({
+                "advertiserDomains": ["brand.test"],
+                "mediaType": "video",
+                "networkName": "Mock Network",
+            })
+        );
+        assert_eq!(ext["mocktioneer"]["bid"], 2.5);
+    }
+}
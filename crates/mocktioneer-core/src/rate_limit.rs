@@ -0,0 +1,268 @@
+//! Request limits: per route for the auction and pixel endpoints, and per client.
+//!
+//! `[rate_limit.auction]` and `[rate_limit.pixel]` each allow `requests` per
+//! `window_secs` fixed window, counted across all clients. Routes without a
+//! section are unlimited. `[rate_limit.client]` gives every client its own
+//! token bucket across all routes, keeping at most 10,000 buckets. Clients are
+//! keyed on the peer address, or on forwarded headers with `trust_forwarded`;
+//! requests with neither share one bucket. Both run on the real clock even when
+//! `[clock]` is frozen, so a fixed clock can't lock anyone out for good.
+//!
+//! Counters live in process memory, so limits are only enforced on the axum
+//! adapter. Fastly and Cloudflare spread requests over many short-lived
+//! instances, each starting from empty counters: there the limits are
+//! effectively off.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::config::{ClientLimit, RateLimitConfig, RouteLimit};
+
+static LIMITER: LazyLock<RateLimiter> = LazyLock::new(RateLimiter::default);
+
+static CLIENT_LIMITER: LazyLock<ClientLimiter> = LazyLock::new(ClientLimiter::default);
+
+/// Most client buckets kept; a new client beyond this evicts the bucket used
+/// longest ago.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Routes that can carry their own limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+    Auction,
+    Pixel,
+}
+
+impl LimitedRoute {
+    /// Route served at `path`, if it is rate limited.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/openrtb2/auction" => Some(Self::Auction),
+            "/pixel" => Some(Self::Pixel),
+            _ => None,
+        }
+    }
+
+    /// Limit configured for this route, if any.
+    pub fn limit(self, cfg: &RateLimitConfig) -> Option<&RouteLimit> {
+        match self {
+            Self::Auction => cfg.auction.as_ref(),
+            Self::Pixel => cfg.pixel.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Duration,
+    count: u32,
+}
+
+/// Fixed-window request counters, one per route.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<LimitedRoute, Window>>,
+}
+
+impl RateLimiter {
+    /// Process-wide limiter used by the rate limit middleware.
+    pub fn global() -> &'static RateLimiter {
+        &LIMITER
+    }
+
+    /// Count one request to `route` at `now`. Returns the time until the
+    /// current window ends when `limit` is already used up.
+    pub fn check(
+        &self,
+        route: LimitedRoute,
+        limit: &RouteLimit,
+        now: Duration,
+    ) -> Result<(), Duration> {
+        let window_len = Duration::from_secs(limit.window_secs.max(1));
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let window = windows.entry(route).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.saturating_sub(window.started) >= window_len {
+            *window = Window {
+                started: now,
+                count: 0,
+            };
+        }
+        if window.count >= limit.requests {
+            return Err((window.started + window_len).saturating_sub(now));
+        }
+        window.count += 1;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_client: HashMap<String, Bucket>,
+    /// `(updated, client)` of every bucket, oldest first, for eviction.
+    by_age: BTreeSet<(Duration, String)>,
+}
+
+/// Token buckets for `[rate_limit.client]`, one per client key.
+#[derive(Debug, Default)]
+pub struct ClientLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl ClientLimiter {
+    /// Process-wide limiter used by the rate limit middleware.
+    pub fn global() -> &'static ClientLimiter {
+        &CLIENT_LIMITER
+    }
+
+    /// Take one token from `client`'s bucket at `now`. Returns the time until a
+    /// token is available when the bucket is empty.
+    pub fn check(&self, client: &str, limit: &ClientLimit, now: Duration) -> Result<(), Duration> {
+        let rps = limit.rps.max(0.001);
+        let burst = f64::from(limit.burst.max(1));
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_sub(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * rps).min(burst)
+        };
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let Buckets { by_client, by_age } = &mut *buckets;
+        let key = client.to_string();
+        if let Some(bucket) = by_client.get(client) {
+            by_age.remove(&(bucket.updated, key.clone()));
+        } else if by_client.len() >= MAX_CLIENTS {
+            if let Some((_, oldest)) = by_age.pop_first() {
+                by_client.remove(&oldest);
+            }
+        }
+        by_age.insert((now, key.clone()));
+        let bucket = by_client.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rps));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(requests: u32, window_secs: u64) -> RouteLimit {
+        RouteLimit {
+            requests,
+            window_secs,
+        }
+    }
+
+    #[test]
+    fn routes_from_path() {
+        assert_eq!(
+            LimitedRoute::from_path("/openrtb2/auction"),
+            Some(LimitedRoute::Auction)
+        );
+        assert_eq!(LimitedRoute::from_path("/pixel"), Some(LimitedRoute::Pixel));
+        assert_eq!(LimitedRoute::from_path("/e/dtb/bid"), None);
+    }
+
+    #[test]
+    fn auction_and_pixel_limits_are_independent() {
+        let limiter = RateLimiter::default();
+        let auction = limit(3, 1);
+        let pixel = limit(1, 1);
+        let now = Duration::from_secs(100);
+
+        assert!(limiter.check(LimitedRoute::Pixel, &pixel, now).is_ok());
+        assert!(limiter.check(LimitedRoute::Pixel, &pixel, now).is_err());
+
+        // The exhausted pixel limit doesn't touch the auction's higher one
+        for _ in 0..3 {
+            assert!(limiter.check(LimitedRoute::Auction, &auction, now).is_ok());
+        }
+        assert!(limiter.check(LimitedRoute::Auction, &auction, now).is_err());
+    }
+
+    #[test]
+    fn client_bucket_throttles_after_burst_and_refills() {
+        let limiter = ClientLimiter::default();
+        let limit = ClientLimit {
+            rps: 2.0,
+            burst: 3,
+            ..Default::default()
+        };
+        let start = Duration::from_secs(100);
+
+        for _ in 0..3 {
+            assert!(limiter.check("10.0.0.1", &limit, start).is_ok());
+        }
+        let retry = limiter.check("10.0.0.1", &limit, start).unwrap_err();
+        assert_eq!(retry, Duration::from_millis(500));
+        // Other clients have their own bucket
+        assert!(limiter.check("10.0.0.2", &limit, start).is_ok());
+
+        // Half a second refills one token, not more
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check("10.0.0.1", &limit, later).is_ok());
+        assert!(limiter.check("10.0.0.1", &limit, later).is_err());
+
+        // A long pause refills up to burst only
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check("10.0.0.1", &limit, much_later).is_ok());
+        }
+        assert!(limiter.check("10.0.0.1", &limit, much_later).is_err());
+    }
+
+    #[test]
+    fn client_buckets_are_capped_by_evicting_the_oldest() {
+        let limiter = ClientLimiter::default();
+        let limit = ClientLimit {
+            rps: 0.001,
+            burst: 1,
+            ..Default::default()
+        };
+        let at = Duration::from_secs;
+
+        assert!(limiter.check("first", &limit, at(1)).is_ok());
+        assert!(limiter.check("first", &limit, at(1)).is_err());
+        for i in 1..MAX_CLIENTS {
+            assert!(limiter.check(&format!("c{}", i), &limit, at(2)).is_ok());
+        }
+        // Full: a new client pushes out the stalest bucket, so `first` starts over
+        assert!(limiter.check("new", &limit, at(3)).is_ok());
+        assert!(limiter.check("first", &limit, at(3)).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.by_client.len(), MAX_CLIENTS);
+        assert_eq!(buckets.by_age.len(), MAX_CLIENTS);
+    }
+
+    #[test]
+    fn window_resets_after_window_secs() {
+        let limiter = RateLimiter::default();
+        let pixel = limit(1, 10);
+        let start = Duration::from_secs(100);
+
+        assert!(limiter.check(LimitedRoute::Pixel, &pixel, start).is_ok());
+        let retry = limiter
+            .check(LimitedRoute::Pixel, &pixel, start + Duration::from_secs(4))
+            .unwrap_err();
+        assert_eq!(retry, Duration::from_secs(6));
+        assert!(limiter
+            .check(LimitedRoute::Pixel, &pixel, start + Duration::from_secs(10))
+            .is_ok());
+    }
+}
@@ -1,9 +1,8 @@
+use crate::clock;
+use crate::openrtb::{Imp, OpenRTBRequest};
 use handlebars::Handlebars;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use uuid::Uuid;
-
-use crate::openrtb::OpenRTBRequest;
 
 /// Signature verification status for creative metadata
 #[derive(Debug, Clone, Serialize)]
@@ -45,22 +44,115 @@ pub fn render_template_str(tmpl: &str, data: &JsonValue) -> String {
     reg.render("t", data).unwrap_or_default()
 }
 
+/// Test-render `tmpl` against sample creative data, reporting why it fails to
+/// parse or render.
+pub fn check_template(tmpl: &str) -> Result<(), String> {
+    let mut reg = Handlebars::new();
+    reg.register_template_string("t", tmpl)
+        .map_err(|e| e.to_string())?;
+    let sample = serde_json::json!({"W": 300, "H": 250, "HOST": "host.test", "CRID": "crid"});
+    reg.render("t", &sample)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
 const IFRAME_HTML_TMPL: &str = include_str!("../static/templates/iframe.html.hbs");
 
-/// Render iframe HTML with embedded metadata as an HTML comment.
-///
-/// The metadata is serialized as pretty-printed JSON and wrapped in an HTML comment.
-/// Any `--` sequences in the JSON are escaped to prevent breaking the HTML comment
-/// syntax. The iframe is wrapped in a positioned container. The signature verification
-/// badge is rendered inside the creative template (not in the wrapper).
-pub fn iframe_html(
+/// Interaction hints for the `adm` wrappers: per-imp values taken from the
+/// OpenRTB imp, plus the `[creative]` iframe sandbox.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdmHints {
+    /// `banner.expdir`: directions the creative may expand in (OpenRTB list 5.5).
+    pub expdir: Vec<i64>,
+    /// `imp.clickbrowser`: 0 = embedded (in-app) browser, 1 = native browser.
+    pub clickbrowser: Option<i64>,
+    /// `imp.ext.mocktioneer.safeframe`: the creative registers with `$sf.ext`.
+    pub safeframe: bool,
+    /// `[creative] sandbox`: tokens for the creative iframe's `sandbox`
+    /// attribute. `None` leaves the attribute off; an empty string sandboxes fully.
+    pub sandbox: Option<String>,
+    /// `[creative] mraid_resize_ms`: delay before the MRAID creative calls
+    /// `mraid.resize()`. `None` never resizes.
+    pub mraid_resize_ms: Option<u64>,
+    /// `[templates] iframe`: replaces the embedded iframe wrapper template.
+    pub iframe_template: Option<String>,
+}
+
+impl AdmHints {
+    pub fn from_imp(imp: &Imp) -> Self {
+        AdmHints {
+            expdir: imp
+                .banner
+                .as_ref()
+                .and_then(|b| b.expdir.clone())
+                .unwrap_or_default(),
+            clickbrowser: imp.clickbrowser,
+            safeframe: imp
+                .ext
+                .as_ref()
+                .and_then(|e| e.mocktioneer.as_ref())
+                .and_then(|m| m.safeframe)
+                .unwrap_or(false),
+            sandbox: None,
+            mraid_resize_ms: None,
+            iframe_template: None,
+        }
+    }
+}
+
+/// `sandbox` tokens reduced to well-formed ones (`allow-scripts` etc.), joined
+/// by single spaces, so they are safe inside the attribute and the script tag.
+fn sandbox_tokens(sandbox: &str) -> String {
+    sandbox
+        .split_whitespace()
+        .filter(|token| token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Names of the supported `expdir` values; full screen (5) wins over the rest.
+fn expand_dir_names(expdir: &[i64]) -> Vec<&'static str> {
+    if expdir.contains(&5) {
+        return vec!["fullscreen"];
+    }
+    let mut names = Vec::new();
+    for (code, name) in [(1, "left"), (2, "right"), (3, "up"), (4, "down")] {
+        if expdir.contains(&code) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Inline CSS applied to the creative iframe while expanded: the ad grows by its
+/// own size in each direction, or covers the viewport for full screen.
+fn expand_css(w: i64, h: i64, dirs: &[&str]) -> String {
+    if dirs.contains(&"fullscreen") {
+        return "position:fixed;left:0;top:0;width:100vw;height:100vh;z-index:2147483647"
+            .to_string();
+    }
+    let has = |d: &str| i64::from(dirs.contains(&d));
+    let (left, right, up, down) = (has("left"), has("right"), has("up"), has("down"));
+    format!(
+        "position:absolute;left:-{}px;top:-{}px;width:{}px;height:{}px;z-index:2147483647",
+        left * w,
+        up * h,
+        w * (1 + left + right),
+        h * (1 + up + down)
+    )
+}
+
+/// Template data shared by the `adm` wrappers: creative URL parts plus the
+/// metadata JSON, with `--` escaped so it cannot close the HTML comment.
+fn adm_template_data(
     base_host: &str,
     crid: &str,
     w: i64,
     h: i64,
     bid: Option<f64>,
     metadata: &CreativeMetadata,
-) -> String {
+    hints: &AdmHints,
+) -> JsonValue {
     // Get signature status URL param for the creative to render the badge
     let sig_param = metadata.signature.url_param();
 
@@ -73,50 +165,408 @@ pub fn iframe_html(
 
     let bid_str = bid.map(|b| format!("{:.2}", b)).unwrap_or_default();
 
-    let data = serde_json::json!({
+    let dirs = expand_dir_names(&hints.expdir);
+    let expand = (!dirs.is_empty()).then(|| expand_css(w, h, &dirs));
+
+    serde_json::json!({
         "BID": bid_str,
+        "CLICKBROWSER": hints.clickbrowser.map(|cb| cb.to_string()).unwrap_or_default(),
         "CRID": crid,
+        "EXPAND_CSS": expand.unwrap_or_default(),
+        "EXPDIR": dirs.join(","),
         "H": h,
+        "HAS_SANDBOX": hints.sandbox.is_some(),
         "HOST": base_host,
         "METADATA_JSON": safe_json,
+        "PID_RESIZE": clock::uuid_v7(clock::global()).as_simple().to_string(),
+        "RESIZE": hints.mraid_resize_ms.is_some(),
+        "RESIZE_H": h * 2,
+        "RESIZE_MS": hints.mraid_resize_ms.unwrap_or(0),
+        "SAFEFRAME": hints.safeframe,
+        "SANDBOX": hints.sandbox.as_deref().map(sandbox_tokens).unwrap_or_default(),
         "SIG": sig_param,
         "W": w,
+    })
+}
+
+/// Render iframe HTML with embedded metadata as an HTML comment.
+///
+/// The metadata is serialized as pretty-printed JSON and wrapped in an HTML comment.
+/// Any `--` sequences in the JSON are escaped to prevent breaking the HTML comment
+/// syntax. The iframe is wrapped in a positioned container. The signature verification
+/// badge is rendered inside the creative template (not in the wrapper). `hints`
+/// make the container expand on hover per `banner.expdir` and forward
+/// `imp.clickbrowser` to the creative, which picks the click-through target,
+/// and `safeframe` when the imp asks for a SafeFrame creative.
+pub fn iframe_html(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    bid: Option<f64>,
+    metadata: &CreativeMetadata,
+    hints: &AdmHints,
+) -> String {
+    let data = adm_template_data(base_host, crid, w, h, bid, metadata, hints);
+    let tmpl = hints.iframe_template.as_deref().unwrap_or(IFRAME_HTML_TMPL);
+    render_template_str(tmpl, &data)
+}
+
+const LIGHT_HTML_TMPL: &str = include_str!("../static/templates/light.html.hbs");
+
+/// Render lightweight markup for slow connections: the SVG placeholder in a
+/// click-through link, without the HTML creative and its beacons.
+pub fn light_adm(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    bid: Option<f64>,
+    metadata: &CreativeMetadata,
+    hints: &AdmHints,
+) -> String {
+    let mut data = adm_template_data(base_host, crid, w, h, bid, metadata, hints);
+    data["CLICK_URL"] = click_url(base_host, crid, w, h).into();
+    render_template_str(LIGHT_HTML_TMPL, &data)
+}
+
+const SCRIPT_HTML_TMPL: &str = include_str!("../static/templates/script.html.hbs");
+
+/// Render a `<script>` tag that `document.write`s the creative iframe, for ad
+/// servers that expect script-delivered markup. Carries the same metadata comment
+/// and creative URL as [`iframe_html`].
+pub fn script_adm(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    bid: Option<f64>,
+    metadata: &CreativeMetadata,
+    hints: &AdmHints,
+) -> String {
+    let data = adm_template_data(base_host, crid, w, h, bid, metadata, hints);
+    render_template_str(SCRIPT_HTML_TMPL, &data)
+}
+
+const VAST_XML_TMPL: &str = include_str!("../static/templates/vast.xml.hbs");
+
+/// VAST version emitted when `video.protocols` names none we can produce.
+pub const DEFAULT_VAST_VERSION: &str = "3.0";
+
+/// Every VAST version we produce.
+pub const VAST_VERSIONS: [&str; 5] = ["2.0", "3.0", "4.0", "4.1", "4.2"];
+
+/// VAST version for an OpenRTB `video.protocols` code (inline or wrapper).
+/// VAST 1.0 and DAAST are not produced and map to `None`.
+fn protocol_vast_version(protocol: i64) -> Option<&'static str> {
+    match protocol {
+        2 | 5 => Some("2.0"),
+        3 | 6 => Some("3.0"),
+        7 | 8 => Some("4.0"),
+        11 | 12 => Some("4.1"),
+        13 | 14 => Some("4.2"),
+        _ => None,
+    }
+}
+
+/// Highest VAST version listed in `video.protocols`, or
+/// [`DEFAULT_VAST_VERSION`] when it lists none we can produce.
+pub fn vast_version(protocols: Option<&[i64]>) -> &'static str {
+    protocols
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|p| protocol_vast_version(*p))
+        .max()
+        .unwrap_or(DEFAULT_VAST_VERSION)
+}
+
+/// `video.protocols` code the bid's VAST answers to: the listed code for the
+/// version [`vast_version`] picks, preferring the inline one over the wrapper.
+/// `None` when the list names no version we produce.
+pub fn vast_protocol(protocols: Option<&[i64]>) -> Option<i64> {
+    let wrapper = |p: i64| matches!(p, 5 | 6 | 8 | 12 | 14);
+    protocols
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|&p| Some((protocol_vast_version(p)?, !wrapper(p), p)))
+        .max()
+        .map(|(_, _, p)| p)
+}
+
+/// Render a minimal inline VAST document of `version` for a linear video ad of
+/// `duration_secs`, with an impression pixel, click-through and a single MP4
+/// MediaFile at `/static/video/{W}x{H}.mp4`.
+pub fn vast_xml(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    duration_secs: i64,
+    version: &str,
+) -> String {
+    let pid = clock::uuid_v7(clock::global()).as_simple().to_string();
+    let secs = duration_secs.max(0);
+    let data = serde_json::json!({
+        "CLICK_URL": click_url(base_host, crid, w, h),
+        "CRID": crid,
+        "DURATION": format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+        "H": h,
+        "IMPRESSION_URL": format!("//{}/pixel?pid={}", base_host, pid),
+        "MEDIA_URL": format!("//{}/static/video/{}x{}.mp4", base_host, w, h),
+        "VERSION": version,
+        "W": w,
     });
-    render_template_str(IFRAME_HTML_TMPL, &data)
+    render_template_str(VAST_XML_TMPL, &data)
+}
+
+/// Render an OpenRTB Native 1.2 response for a native imp: a title and a main
+/// image asset pointing at the static SVG, an impression tracker, and a `link`
+/// whose destination is the creative's [`click_url`].
+pub fn native_adm(base_host: &str, crid: &str, w: i64, h: i64) -> String {
+    let pid = clock::uuid_v7(clock::global()).as_simple().to_string();
+    serde_json::json!({
+        "native": {
+            "ver": "1.2",
+            "assets": [
+                {"id": 1, "title": {"text": "Mocktioneer"}},
+                {"id": 2, "img": {
+                    "type": 3,
+                    "url": format!("//{}/static/img/{}x{}.svg", base_host, w, h),
+                    "w": w,
+                    "h": h,
+                }},
+            ],
+            "link": {"url": click_url(base_host, crid, w, h)},
+            "imptrackers": [format!("//{}/pixel?pid={}", base_host, pid)],
+        }
+    })
+    .to_string()
+}
+
+/// `/vast` URL serving the same document as [`vast_xml`] with these arguments.
+pub fn vast_url(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    duration_secs: i64,
+    version: &str,
+) -> String {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("crid", crid)
+        .append_pair("w", &w.to_string())
+        .append_pair("h", &h.to_string())
+        .append_pair("dur", &duration_secs.to_string())
+        .append_pair("ver", version)
+        .finish();
+    format!("//{}/vast?{}", base_host, query)
+}
+
+const MRAID_HTML_TMPL: &str = include_str!("../static/templates/mraid.html.hbs");
+
+/// Render MRAID-aware markup for in-app placements: loads `mraid.js` and shows the
+/// creative iframe once the container fires `ready` (immediately outside MRAID).
+/// With `hints.mraid_resize_ms`, the creative then resizes to twice its height
+/// after that delay and beacons `/pixel?event=mraid_resize`.
+pub fn mraid_adm(
+    base_host: &str,
+    crid: &str,
+    w: i64,
+    h: i64,
+    bid: Option<f64>,
+    metadata: &CreativeMetadata,
+    hints: &AdmHints,
+) -> String {
+    let data = adm_template_data(base_host, crid, w, h, bid, metadata, hints);
+    render_template_str(MRAID_HTML_TMPL, &data)
 }
 
-pub fn render_svg(w: i64, h: i64, bid: Option<f64>) -> String {
+/// Largest width or height [`render_svg`] draws; bigger requests are clamped.
+pub const MAX_SVG_DIM: i64 = 2000;
+
+/// Color scheme of the HTML creative and SVG placeholder, picked with `?theme=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Default,
+    Dark,
+    /// Loud colors and a red border, to spot the slot on a busy page.
+    Highlight,
+}
+
+/// Colors a [`Theme`] feeds into the templates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThemeColors {
+    /// Background gradient, top-left to bottom-right.
+    pub background: [&'static str; 2],
+    pub text: &'static str,
+    pub caption: &'static str,
+    pub border: Option<&'static str>,
+}
+
+impl Theme {
+    /// `?theme=` value, `None` for the default theme.
+    pub fn param(self) -> Option<&'static str> {
+        match self {
+            Theme::Default => None,
+            Theme::Dark => Some("dark"),
+            Theme::Highlight => Some("highlight"),
+        }
+    }
+
+    pub fn colors(self) -> ThemeColors {
+        match self {
+            Theme::Default => ThemeColors {
+                background: ["#f7f7ff", "#eaf0ff"],
+                text: "#0f172a",
+                caption: "#334155",
+                border: None,
+            },
+            Theme::Dark => ThemeColors {
+                background: ["#0f172a", "#1e293b"],
+                text: "#f8fafc",
+                caption: "#cbd5e1",
+                border: Some("#475569"),
+            },
+            Theme::Highlight => ThemeColors {
+                background: ["#fff7cc", "#ffe066"],
+                text: "#7a1f00",
+                caption: "#9a3412",
+                border: Some("#ff3b30"),
+            },
+        }
+    }
+}
+
+/// SVG placeholder creative. `w` and `h` are clamped into `1..=MAX_SVG_DIM`, so
+/// any input gives a small, well-formed image.
+pub fn render_svg(w: i64, h: i64, bid: Option<f64>, theme: Theme) -> String {
     const SVG_TMPL: &str = include_str!("../static/templates/image.svg.hbs");
+    let w = w.clamp(1, MAX_SVG_DIM);
+    let h = h.clamp(1, MAX_SVG_DIM);
     // Font size: fit "WxH" text (~7 chars) within width, also limit by height
     let font = (w as f64 / 5.0).min(h as f64 / 2.0).round().max(12.0) as i64;
     // Caption positioned below main title
     let cap_y = h / 2 + (font as f64 * 0.7).round() as i64;
     let bid_label = bid.map(|b| format!(" — ${:.2}", b)).unwrap_or_default();
+    let colors = theme.colors();
     let data = serde_json::json!({
+        "BG_FROM": colors.background[0],
+        "BG_TO": colors.background[1],
         "BIDLBL": bid_label,
+        "BORDER": colors.border,
+        "CAPCOLOR": colors.caption,
         "CAPFONT": ((w.min(h) as f64) * 0.06).clamp(10.0, 16.0).round() as i64,
         "CAPY": cap_y,
         "FONT": font,
+        "TEXTCOLOR": colors.text,
         "H": h,
         "W": w,
     });
     render_template_str(SVG_TMPL, &data)
 }
 
+/// Click-through URL for a creative. Carries the creative id and size so `/click`
+/// can echo them and analytics can segment clicks by format; every markup type
+/// should build its click destination through here.
+pub fn click_url(host: &str, crid: &str, w: i64, h: i64) -> String {
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("crid", crid)
+        .append_pair("w", &w.to_string())
+        .append_pair("h", &h.to_string())
+        .finish();
+    format!("//{}/click?{}", host, query)
+}
+
+/// Longest prefix of `s` that fits in `max_bytes` without splitting a UTF-8 char.
+/// Use this instead of byte-slicing whenever client-supplied text is shortened.
+pub fn truncate_str(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
 const CREATIVE_HTML_TMPL: &str = include_str!("../static/templates/creative.html.hbs");
-pub fn creative_html(w: i64, h: i64, pixel_html: bool, pixel_js: bool, host: &str) -> String {
-    let html_pid = Uuid::now_v7().as_simple().to_string();
-    let js_pid = Uuid::now_v7().as_simple().to_string();
+
+/// Ad-refresh simulation for [`creative_html`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreativeRefresh {
+    /// Seconds before the `/pixel?event=refresh` beacon fires.
+    pub interval_secs: u64,
+    /// Reload the creative document after the beacon, like a refreshing slot.
+    pub reload: bool,
+}
+
+/// Optional behavior of the HTML creative.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CreativeOptions {
+    /// Fire the `<img>` impression pixel.
+    pub pixel_html: bool,
+    /// Fire a second, JS-created pixel.
+    pub pixel_js: bool,
+    /// Hide the visible content for this many milliseconds, to simulate
+    /// slow-loading assets.
+    pub slow_ms: Option<u64>,
+    /// Ad-refresh beacon (and optional reload).
+    pub refresh: Option<CreativeRefresh>,
+    /// Burn CPU and hold memory to trip browsers' heavy ad intervention.
+    pub heavy: bool,
+    /// Register with the SafeFrame host API and beacon what it reports.
+    pub safeframe: bool,
+    /// Color scheme, also passed on to the placeholder image.
+    pub theme: Theme,
+    /// `[templates] creative`: replaces the embedded creative template.
+    pub template: Option<&'static str>,
+}
+
+/// Main-thread time the heavy creative burns, in milliseconds. Chrome unloads
+/// ads using 15 s of CPU in any 30 s window.
+pub const HEAVY_CPU_MS: u64 = 20_000;
+
+/// Memory the heavy creative holds on to, in bytes.
+pub const HEAVY_ALLOC_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Impression pixel id for the `slot` pixel of a `w`x`h` creative. Derived from
+/// the size and theme instead of minted per render, so a creative URL always
+/// renders the same body and can be cached.
+fn creative_pixel_id(w: i64, h: i64, theme: Theme, slot: &str) -> String {
+    let key = format!("{}x{} {:?} {}", w, h, theme, slot);
+    format!("{:016x}", crate::auction::fnv1a(key.bytes()))
+}
+
+/// Render the HTML creative with the behavior selected in `opts`. The output
+/// depends only on the arguments.
+pub fn creative_html(w: i64, h: i64, host: &str, opts: &CreativeOptions) -> String {
+    let pid = |slot| creative_pixel_id(w, h, opts.theme, slot);
+    let colors = opts.theme.colors();
     let data = serde_json::json!({
+        "BG": colors.background[0],
+        "BORDER": colors.border,
         "H": h,
+        "HEAVY": opts.heavy,
+        "HEAVY_CPU_MS": HEAVY_CPU_MS,
+        "HEAVY_F64S": HEAVY_ALLOC_BYTES / 8,
         "HOST": host,
-        "PID_HTML": html_pid,
-        "PID_JS": js_pid,
-        "PIXEL_HTML": pixel_html,
-        "PIXEL_JS": pixel_js,
+        "PID_HTML": pid("html"),
+        "PID_JS": pid("js"),
+        "PID_REFRESH": pid("refresh"),
+        "PID_SAFEFRAME": pid("safeframe"),
+        "PIXEL_HTML": opts.pixel_html,
+        "PIXEL_JS": opts.pixel_js,
+        "REFRESH_RELOAD": opts.refresh.is_some_and(|r| r.reload),
+        "REFRESH_SECS": opts.refresh.map_or(0, |r| r.interval_secs),
+        "SAFEFRAME": opts.safeframe,
+        "SLOW_MS": opts.slow_ms.unwrap_or(0),
+        "THEME": opts.theme.param(),
         "W": w,
     });
-    render_template_str(CREATIVE_HTML_TMPL, &data)
+    render_template_str(opts.template.unwrap_or(CREATIVE_HTML_TMPL), &data)
 }
 
 const INFO_TMPL: &str = include_str!("../static/templates/info.html.hbs");
@@ -139,10 +589,20 @@ pub fn info_html(host: &str) -> String {
     render_template_str(INFO_TMPL, &data)
 }
 
+const AUCTION_FORM_TMPL: &str = include_str!("../static/templates/auction_form.html.hbs");
+
+/// Browser form that posts a one-imp banner auction for one of `sizes`.
+pub fn auction_form_html(sizes: impl IntoIterator<Item = (i64, i64)>) -> String {
+    let sizes: Vec<serde_json::Value> = sizes
+        .into_iter()
+        .map(|(w, h)| serde_json::json!({ "w": w, "h": h }))
+        .collect();
+    render_template_str(AUCTION_FORM_TMPL, &serde_json::json!({ "SIZES": sizes }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::openrtb::OpenRTBRequest;
 
     fn test_metadata(signature: SignatureStatus) -> (OpenRTBRequest, CreativeMetadata<'static>) {
         // Use a leaked request to get a 'static lifetime for tests
@@ -167,26 +627,404 @@ mod tests {
         let (_, metadata) = test_metadata(SignatureStatus::NotPresent {
             reason: "test".to_string(),
         });
-        let adm = iframe_html("host.test", "abc&def\"", 300, 250, None, &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "abc&def\"",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
         assert!(adm.contains("//host.test/static/creatives/300x250.html?crid=abc&amp;def&quot;"));
         assert!(adm.contains("width=\"300\""));
         assert!(adm.contains("height=\"250\""));
     }
 
+    #[test]
+    fn test_sandbox_attribute_only_when_configured() {
+        let (_, metadata) = test_metadata(SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        });
+        let adm = iframe_html(
+            "host.test",
+            "c1",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
+        assert!(!adm.contains("sandbox"));
+
+        let hints = AdmHints {
+            sandbox: Some("allow-scripts  allow-popups bad\"token".to_string()),
+            ..Default::default()
+        };
+        for adm in [
+            iframe_html("host.test", "c1", 300, 250, None, &metadata, &hints),
+            script_adm("host.test", "c1", 300, 250, None, &metadata, &hints),
+            mraid_adm("host.test", "c1", 300, 250, None, &metadata, &hints),
+        ] {
+            assert!(
+                adm.contains(r#"sandbox="allow-scripts allow-popups""#),
+                "{}",
+                adm
+            );
+        }
+
+        let hints = AdmHints {
+            sandbox: Some(String::new()),
+            ..Default::default()
+        };
+        let adm = iframe_html("host.test", "c1", 300, 250, None, &metadata, &hints);
+        assert!(adm.contains(r#"sandbox="""#));
+    }
+
+    #[test]
+    fn test_adm_hints_expand_container_and_forward_clickbrowser() {
+        let (_, metadata) = test_metadata(SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        });
+        let hints = AdmHints {
+            expdir: vec![4, 1, 6],
+            clickbrowser: Some(0),
+            ..Default::default()
+        };
+        let adm = iframe_html("host.test", "c1", 300, 250, None, &metadata, &hints);
+        assert!(adm.contains(r#"data-expdir="left,down""#));
+        assert!(adm.contains("left:-300px;top:-0px;width:600px;height:500px"));
+        assert!(adm.contains("&clickbrowser=0"));
+
+        let hints = AdmHints {
+            expdir: vec![3, 5],
+            clickbrowser: Some(1),
+            ..Default::default()
+        };
+        let adm = script_adm("host.test", "c1", 300, 250, None, &metadata, &hints);
+        assert!(adm.contains(r#"data-expdir="fullscreen""#));
+        assert!(adm.contains("position:fixed"));
+        assert!(adm.contains("&clickbrowser=1"));
+
+        let adm = iframe_html(
+            "host.test",
+            "c1",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
+        assert!(!adm.contains("data-expdir"));
+        assert!(!adm.contains("clickbrowser"));
+    }
+
+    #[test]
+    fn test_script_adm_wraps_creative_in_script_tag() {
+        let (_, metadata) = test_metadata(SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        });
+        let adm = script_adm(
+            "host.test",
+            "crid'</script>",
+            300,
+            250,
+            Some(2.5),
+            &metadata,
+            &AdmHints::default(),
+        );
+        assert!(adm.contains("<script type=\"text/javascript\">"));
+        assert!(adm.contains("document.write('"));
+        assert!(adm.contains("//host.test/static/creatives/300x250.html?crid=crid"));
+        assert!(adm.contains("bid=2.50"));
+        assert!(adm.contains("MOCKTIONEER_METADATA"));
+        // Escaped values cannot terminate the JS string or the script element
+        assert_eq!(adm.matches("</script>").count(), 1);
+        assert!(!adm.contains("crid'"));
+    }
+
+    #[test]
+    fn test_click_url_carries_crid_and_size() {
+        assert_eq!(
+            click_url("host.test", "mocktioneer-1", 728, 90),
+            "//host.test/click?crid=mocktioneer-1&w=728&h=90"
+        );
+        assert_eq!(
+            click_url("host.test", "a&b c", 300, 250),
+            "//host.test/click?crid=a%26b+c&w=300&h=250"
+        );
+    }
+
+    #[test]
+    fn test_creative_html_click_href_includes_size() {
+        let html = creative_html(320, 50, "host.test", &CreativeOptions::default());
+        // Static fallback before JS adds the crid
+        assert!(html.contains("href=\"//host.test/click?w=320&h=50\""));
+        assert!(html.contains("\"&w=320&h=50\""));
+    }
+
+    #[test]
+    fn test_creative_html_embedded_clickbrowser_targets_top() {
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(html.contains(r#"target="_blank""#));
+        assert!(html.contains(r#"p.get("clickbrowser") === "0""#));
+        assert!(html.contains(r#"clk.target = "_top""#));
+    }
+
+    #[test]
+    fn test_creative_html_slow_delays_reveal() {
+        let html = creative_html(
+            300,
+            250,
+            "host.test",
+            &CreativeOptions {
+                slow_ms: Some(1500),
+                ..Default::default()
+            },
+        );
+        assert!(html.contains("var slowMs = 1500;"));
+        assert!(html.contains("visibility: hidden"));
+
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(!html.contains("slowMs"));
+        assert!(!html.contains("visibility: hidden"));
+    }
+
+    #[test]
+    fn test_creative_html_heavy_burns_cpu_and_memory() {
+        let heavy = CreativeOptions {
+            heavy: true,
+            ..Default::default()
+        };
+        let html = creative_html(300, 250, "host.test", &heavy);
+        assert!(html.contains("while (Date.now() < sliceEnd)"));
+        assert!(html.contains(&format!("new Float64Array({})", HEAVY_ALLOC_BYTES / 8)));
+        assert!(html.contains(&format!("Date.now() + {}", HEAVY_CPU_MS)));
+
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(!html.contains("sliceEnd"));
+        assert!(!html.contains("Float64Array"));
+    }
+
+    #[test]
+    fn test_creative_html_safeframe_registers_and_beacons() {
+        let safeframe = CreativeOptions {
+            safeframe: true,
+            ..Default::default()
+        };
+        let html = creative_html(300, 250, "host.test", &safeframe);
+        assert!(html.contains("$sf.ext.register(300, 250,"));
+        assert!(html.contains("$sf.ext.geom()"));
+        assert!(html.contains("$sf.ext.inViewPercentage()"));
+        assert!(html.contains("//host.test/pixel?pid="));
+        assert!(html.contains("&event=safeframe"));
+
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(!html.contains("$sf"));
+        assert!(!html.contains("event=safeframe"));
+    }
+
+    #[test]
+    fn test_creative_html_is_deterministic() {
+        let opts = CreativeOptions {
+            pixel_html: true,
+            pixel_js: true,
+            ..Default::default()
+        };
+        let html = creative_html(300, 250, "host.test", &opts);
+        assert_eq!(html, creative_html(300, 250, "host.test", &opts));
+        let pid = creative_pixel_id(300, 250, Theme::default(), "html");
+        assert!(html.contains(&format!("//host.test/pixel?pid={}", pid)));
+        assert_ne!(pid, creative_pixel_id(300, 250, Theme::default(), "js"));
+        assert_ne!(pid, creative_pixel_id(728, 90, Theme::default(), "html"));
+    }
+
+    #[test]
+    fn test_auction_form_html_lists_sizes() {
+        let html = auction_form_html([(300, 250), (728, 90)]);
+        assert!(html.contains(r#"<form id="auction" method="post""#));
+        assert!(html.contains(r#"<option value="300x250">300x250</option>"#));
+        assert!(html.contains(r#"<option value="728x90">728x90</option>"#));
+        assert!(html.contains(r#"name="bid""#));
+    }
+
+    #[test]
+    fn test_vast_xml_inline_linear() {
+        let xml = vast_xml("host.test", "crid-v", 640, 360, 75, DEFAULT_VAST_VERSION);
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.contains(r#"<VAST version="3.0">"#));
+        assert!(xml.contains("<Duration>00:01:15</Duration>"));
+        assert!(xml.contains("<MediaFile delivery=\"progressive\" type=\"video/mp4\""));
+        assert!(xml.contains("//host.test/static/video/640x360.mp4"));
+        assert!(xml.contains("//host.test/click?crid=crid-v&w=640&h=360"));
+        assert!(xml.contains("//host.test/pixel?pid="));
+    }
+
+    #[test]
+    fn test_vast_version_from_protocols() {
+        assert_eq!(vast_version(None), "3.0");
+        assert_eq!(vast_version(Some(&[])), "3.0");
+        assert_eq!(vast_version(Some(&[2, 5])), "2.0");
+        assert_eq!(vast_version(Some(&[2, 3, 7, 13, 6])), "4.2");
+        assert_eq!(vast_version(Some(&[12, 8])), "4.1");
+        // VAST 1.0 and DAAST are not produced
+        assert_eq!(vast_version(Some(&[1, 4, 9, 10])), "3.0");
+        assert_eq!(vast_version(Some(&[1, 2])), "2.0");
+
+        assert_eq!(vast_protocol(None), None);
+        assert_eq!(vast_protocol(Some(&[1, 10])), None);
+        assert_eq!(vast_protocol(Some(&[6, 3, 2])), Some(3));
+        assert_eq!(vast_protocol(Some(&[2, 8])), Some(8));
+
+        let xml = vast_xml("host.test", "crid-v", 640, 360, 15, "4.2");
+        assert!(xml.contains(r#"<VAST version="4.2">"#));
+    }
+
+    #[test]
+    fn test_creative_html_refresh_beacon_and_reload() {
+        let refresh = CreativeRefresh {
+            interval_secs: 30,
+            reload: false,
+        };
+        let html = creative_html(
+            300,
+            250,
+            "host.test",
+            &CreativeOptions {
+                refresh: Some(refresh),
+                ..Default::default()
+            },
+        );
+        assert!(html.contains("var refreshSecs = 30;"));
+        assert!(html.contains("//host.test/pixel?pid="));
+        assert!(html.contains("&event=refresh"));
+        assert!(!html.contains("location.reload()"));
+
+        let refresh = CreativeRefresh {
+            reload: true,
+            ..refresh
+        };
+        let html = creative_html(
+            300,
+            250,
+            "host.test",
+            &CreativeOptions {
+                refresh: Some(refresh),
+                ..Default::default()
+            },
+        );
+        assert!(html.contains("location.reload()"));
+
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(!html.contains("refreshSecs"));
+    }
+
     #[test]
     fn test_render_svg_includes_bid_label_when_present() {
-        let svg = render_svg(300, 250, Some(2.5));
+        let svg = render_svg(300, 250, Some(2.5), Theme::Default);
         assert!(svg.contains("$2.50"));
-        let svg2 = render_svg(300, 250, None);
+        let svg2 = render_svg(300, 250, None, Theme::Default);
         assert!(!svg2.contains("$"));
     }
 
+    #[test]
+    fn test_render_svg_theme_colors() {
+        let light = render_svg(300, 250, None, Theme::Default);
+        assert!(light.contains(r##"stop-color="#f7f7ff""##));
+        assert!(light.contains(r##"fill="#0f172a""##));
+        assert!(!light.contains("stroke="));
+
+        let dark = render_svg(300, 250, None, Theme::Dark);
+        assert!(dark.contains(r##"stop-color="#0f172a""##));
+        assert!(dark.contains(r##"fill="#f8fafc""##));
+        assert!(!dark.contains(r##"fill="#0f172a""##));
+
+        let highlight = render_svg(300, 250, None, Theme::Highlight);
+        assert!(highlight.contains(r##"stroke="#ff3b30""##));
+    }
+
+    #[test]
+    fn test_creative_html_template_override() {
+        let opts = CreativeOptions {
+            template: Some("<p>{{W}}x{{H}} on {{HOST}}</p>"),
+            ..Default::default()
+        };
+        let html = creative_html(728, 90, "host.test", &opts);
+        assert_eq!(html, "<p>728x90 on host.test</p>");
+        assert!(check_template("{{#each}}").is_err());
+        assert!(check_template(CREATIVE_HTML_TMPL).is_ok());
+    }
+
+    #[test]
+    fn test_creative_html_theme() {
+        let opts = CreativeOptions {
+            theme: Theme::Dark,
+            ..Default::default()
+        };
+        let html = creative_html(300, 250, "host.test", &opts);
+        assert!(html.contains("background: #0f172a;"));
+        assert!(html.contains("border: 4px solid #475569;"));
+        assert!(html.contains("/static/img/300x250.svg?theme=dark"));
+
+        let html = creative_html(300, 250, "host.test", &CreativeOptions::default());
+        assert!(html.contains(r#"/static/img/300x250.svg""#));
+        assert!(!html.contains("border: 4px"));
+    }
+
+    #[test]
+    fn test_render_svg_clamps_extreme_dimensions() {
+        let normal_len = render_svg(300, 250, None, Theme::Default).len();
+        for (w, h, shown) in [
+            (1, 1, "1\u{d7}1"),
+            (0, 0, "1\u{d7}1"),
+            (-50, 90, "1\u{d7}90"),
+            (i64::MAX, 100_000, "2000\u{d7}2000"),
+        ] {
+            let svg = render_svg(w, h, None, Theme::Default);
+            assert!(svg.starts_with("<svg"), "{}x{}", w, h);
+            assert!(svg.trim_end().ends_with("</svg>"), "{}x{}", w, h);
+            assert!(svg.contains(shown), "{}x{}", w, h);
+            assert!(!svg.contains("bold -") && !svg.contains("font: -"));
+            let cap_y = svg
+                .split(r#"<text x="50%" y=""#)
+                .nth(2)
+                .and_then(|rest| rest.split('"').next())
+                .and_then(|y| y.parse::<i64>().ok())
+                .unwrap();
+            assert!(cap_y > 0, "caption y {} for {}x{}", cap_y, w, h);
+            assert!(svg.len() < normal_len + 64, "{}x{}", w, h);
+        }
+        let svg = render_svg(i64::MAX, i64::MAX, None, Theme::Default);
+        assert!(svg.contains(r#"width="2000" height="2000""#));
+    }
+
+    #[test]
+    fn test_render_svg_labels_are_not_mojibake() {
+        let svg = render_svg(300, 250, Some(2.5), Theme::Default);
+        assert!(svg.contains("$2.50"));
+        assert!(svg.contains("\u{2014} $2.50"));
+        assert!(svg.contains("300\u{d7}250"));
+        // UTF-8 em-dash / multiplication sign mis-decoded as Latin-1 / CP-1252
+        assert!(!svg.contains("\u{e2}\u{20ac}"));
+        assert!(!svg.contains("\u{c3}\u{2014}"));
+        // Handlebars must not entity-escape the non-ASCII characters either
+        assert!(!svg.contains("&#"));
+    }
+
     #[test]
     fn test_banner_adm_iframe_includes_bid_param_when_present() {
         let (_, metadata) = test_metadata(SignatureStatus::NotPresent {
             reason: "test".to_string(),
         });
-        let adm = iframe_html("host.test", "crid123", 320, 50, Some(3.75), &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "crid123",
+            320,
+            50,
+            Some(3.75),
+            &metadata,
+            &AdmHints::default(),
+        );
         assert!(adm.contains("//host.test/static/creatives/320x50.html"));
         assert!(adm.contains("bid=3.75"));
     }
@@ -207,7 +1045,15 @@ mod tests {
             response: None,
         };
 
-        let adm = iframe_html("host.test", "crid123", 300, 250, Some(1.23), &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "crid123",
+            300,
+            250,
+            Some(1.23),
+            &metadata,
+            &AdmHints::default(),
+        );
 
         // Check the comment structure
         assert!(adm.starts_with("<!-- MOCKTIONEER_METADATA"));
@@ -245,7 +1091,15 @@ mod tests {
             response: None,
         };
 
-        let adm = iframe_html("host.test", "crid123", 300, 250, None, &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "crid123",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
 
         // The -- sequences should be escaped to "- -" to not break HTML comments
         // "test--with--dashes" becomes "test- -with- -dashes"
@@ -285,7 +1139,15 @@ mod tests {
             response: None,
         };
 
-        let adm = iframe_html("host.test", "crid123", 300, 250, None, &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "crid123",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
 
         assert!(adm.contains("\"status\": \"NotPresent\""));
         assert!(adm.contains("No site.domain present"));
@@ -326,7 +1188,15 @@ mod tests {
             response: Some(response),
         };
 
-        let adm = iframe_html("host.test", "crid123", 300, 250, None, &metadata);
+        let adm = iframe_html(
+            "host.test",
+            "crid123",
+            300,
+            250,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
 
         // Check response is included
         assert!(adm.contains("\"response\":"));
@@ -334,4 +1204,58 @@ mod tests {
         assert!(adm.contains("\"seat\": \"mocktioneer\""));
         assert!(adm.contains("\"price\": 1.23"));
     }
+
+    #[test]
+    fn truncate_str_respects_char_boundaries() {
+        assert_eq!(truncate_str("short", 10), "short");
+        assert_eq!(truncate_str("abcdef", 3), "abc");
+        // "é" is two bytes; a limit landing inside it backs off to the previous boundary
+        let s = "caf\u{e9}!";
+        assert_eq!(truncate_str(s, 4), "caf");
+        assert_eq!(truncate_str(s, 5), "caf\u{e9}");
+        // Four-byte emoji: every cut inside it yields valid UTF-8
+        for max in 0..=4 {
+            let out = truncate_str("\u{1f600}", max);
+            assert!(std::str::from_utf8(out.as_bytes()).is_ok());
+            assert!(out.is_empty() || max == 4);
+        }
+    }
+    #[test]
+    fn mraid_adm_waits_for_ready() {
+        let (_req, metadata) = test_metadata(SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        });
+        let adm = mraid_adm(
+            "host.test",
+            "crid123",
+            320,
+            50,
+            None,
+            &metadata,
+            &AdmHints::default(),
+        );
+        assert!(adm.contains(r#"<script src="mraid.js"></script>"#));
+        assert!(adm.contains("mraid.addEventListener('ready', show)"));
+        assert!(adm.contains("//host.test/static/creatives/320x50.html?crid=crid123"));
+        assert!(adm.contains("MOCKTIONEER_METADATA"));
+        assert!(!adm.contains("mraid.resize()"));
+        assert!(!adm.contains("mraid_resize"));
+    }
+
+    #[test]
+    fn mraid_adm_resizes_after_configured_delay() {
+        let (_req, metadata) = test_metadata(SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        });
+        let hints = AdmHints {
+            mraid_resize_ms: Some(1500),
+            ..Default::default()
+        };
+        let adm = mraid_adm("host.test", "crid123", 320, 50, None, &metadata, &hints);
+        assert!(adm.contains("setTimeout(resize, 1500)"));
+        assert!(adm.contains("width: 320, height: 100"));
+        assert!(adm.contains("mraid.resize()"));
+        assert!(adm.contains("//host.test/pixel?pid="));
+        assert!(adm.contains("&event=mraid_resize"));
+    }
 }
@@ -1,32 +1,59 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Read;
 use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use edgezero_core::action;
 use edgezero_core::context::RequestContext;
-use edgezero_core::extractor::{
-    ForwardedHost, FromRequest, Headers, ValidatedJson, ValidatedQuery,
-};
+use edgezero_core::extractor::{FromRequest, Headers, ValidatedJson, ValidatedQuery};
 use edgezero_core::http::{
-    header, response_builder, HeaderMap, HeaderValue, Method, Response, StatusCode,
+    header, response_builder, HeaderMap, HeaderName, HeaderValue, Method, Request, Response,
+    StatusCode, Uri,
 };
-use edgezero_core::middleware::{Middleware, Next};
+use edgezero_core::middleware::{Middleware, Next, RequestLogger};
+use edgezero_core::response::IntoResponse;
 use edgezero_core::{body::Body, error::EdgeError};
+use flate2::read::GzDecoder;
 use serde::Deserialize;
-use uuid::Uuid;
-use validator::{Validate, ValidationError};
+use validator::{Validate, ValidationError, ValidationErrors};
 
 use crate::aps::ApsBidRequest;
 use crate::auction::{
-    build_aps_response, build_openrtb_response, is_standard_size, standard_sizes,
+    build_aps_response, build_openrtb_response_with_config, decode_aps_price, fnv1a, is_bid_size,
+    nearest_standard_size, serialized_len, standard_sizes, truncate_to_fit, vast_redirect_url,
+};
+use crate::clock::Clock;
+use crate::config::{
+    AccessLogFormat, AppConfig, AuctionConfig, ServerConfig, VerificationConfig, VideoResponse,
+};
+use crate::debug::{require_debug, RequestRecorder};
+use crate::openrtb::{
+    apply_default_media, unknown_request_fields, OpenRTBRequest, MAX_AD_DIMENSION,
+};
+use crate::openrtb_proto::{to_proto, PROTOBUF_CONTENT_TYPE};
+use crate::rate_limit::{ClientLimiter, LimitedRoute, RateLimiter};
+use crate::render::{
+    auction_form_html, creative_html, info_html, render_svg, render_template_str, truncate_str,
+    vast_xml, CreativeOptions, CreativeRefresh, SignatureStatus, Theme, DEFAULT_VAST_VERSION,
+    VAST_VERSIONS,
 };
-use crate::openrtb::OpenRTBRequest;
-use crate::render::{creative_html, info_html, render_svg, render_template_str, SignatureStatus};
+use crate::usersync::{
+    apply_synced_uid, build_cookie_sync_response, set_uid, CookieSyncRequest, UIDS_COOKIE,
+};
+
+/// Cap on client-supplied identifiers echoed into log lines.
+const LOG_FIELD_MAX_BYTES: usize = 64;
 
 #[derive(Deserialize, Validate)]
 struct StaticImgQuery {
     #[validate(range(min = 0.0))]
     bid: Option<f64>,
+    /// Color scheme; unknown names are rejected with 400.
+    #[serde(default)]
+    theme: Theme,
 }
 
 #[derive(Deserialize, Validate)]
@@ -35,12 +62,61 @@ struct StaticCreativeQuery {
     pixel_html: Option<bool>,
     #[serde(default)]
     pixel_js: Option<bool>,
+    /// Delay in ms before the creative becomes visible (capped by `[creative] max_slow_ms`).
+    #[serde(default)]
+    slow: Option<u64>,
+    /// Seconds until the creative fires a `/pixel?event=refresh` beacon.
+    #[serde(default)]
+    #[validate(range(min = 1, max = 3600))]
+    refresh: Option<u64>,
+    /// Reload the creative after the refresh beacon.
+    #[serde(default)]
+    reload: Option<bool>,
+    /// Burn CPU and memory like a heavy ad. Honored only with `[debug] enabled`.
+    #[serde(default)]
+    heavy: Option<bool>,
+    /// Register with the SafeFrame API and fire a `/pixel?event=safeframe` beacon.
+    #[serde(default)]
+    safeframe: Option<bool>,
+    /// Color scheme; unknown names are rejected with 400.
+    #[serde(default)]
+    theme: Theme,
+}
+
+#[derive(Deserialize, Validate)]
+struct VastQuery {
+    #[validate(length(min = 1, max = 128))]
+    crid: String,
+    #[validate(range(min = 1, max = 4096))]
+    w: i64,
+    #[validate(range(min = 1, max = 4096))]
+    h: i64,
+    /// Clip length in seconds.
+    #[validate(range(min = 1, max = 3600))]
+    dur: i64,
+    /// VAST version; defaults to `DEFAULT_VAST_VERSION`.
+    #[serde(default)]
+    #[validate(custom(function = "validate_vast_version"))]
+    ver: Option<String>,
+}
+
+fn validate_vast_version(version: &str) -> Result<(), ValidationError> {
+    if VAST_VERSIONS.contains(&version) {
+        return Ok(());
+    }
+    let mut err = ValidationError::new("vast_version");
+    err.message = Some(format!("expected one of {}", VAST_VERSIONS.join(", ")).into());
+    Err(err)
 }
 
 #[derive(Deserialize, Validate)]
 struct PixelQueryParams {
     #[validate(length(min = 1, max = 128))]
     pid: String,
+    /// Beacon type sent by creatives, e.g. `refresh`.
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    event: Option<String>,
 }
 
 #[derive(Deserialize, Validate)]
@@ -67,6 +143,7 @@ struct StaticAssetPath {
 enum AssetFormat {
     Svg,
     Html,
+    Mp4,
 }
 
 impl AssetFormat {
@@ -74,6 +151,7 @@ impl AssetFormat {
         match self {
             AssetFormat::Svg => ".svg",
             AssetFormat::Html => ".html",
+            AssetFormat::Mp4 => ".mp4",
         }
     }
 }
@@ -81,11 +159,17 @@ impl AssetFormat {
 trait AssetFormatMarker {
     const FORMAT: AssetFormat;
 
+    /// Whether the asset is served at this size; banner assets need a bid size.
+    fn accepts(width: i64, height: i64, cfg: &AuctionConfig) -> bool {
+        is_bid_size(width, height, cfg)
+    }
+
     fn handle_invalid(path: &str, width: i64, height: i64) -> EdgeError;
 }
 
 struct SvgSize;
 struct HtmlSize;
+struct Mp4Size;
 
 impl AssetFormatMarker for SvgSize {
     const FORMAT: AssetFormat = AssetFormat::Svg;
@@ -105,6 +189,19 @@ impl AssetFormatMarker for HtmlSize {
     }
 }
 
+impl AssetFormatMarker for Mp4Size {
+    const FORMAT: AssetFormat = AssetFormat::Mp4;
+
+    /// Video players come in any size, so any positive one is served.
+    fn accepts(width: i64, height: i64, _cfg: &AuctionConfig) -> bool {
+        width > 0 && height > 0
+    }
+
+    fn handle_invalid(_path: &str, width: i64, height: i64) -> EdgeError {
+        EdgeError::validation(format!("invalid video size {}x{}", width, height))
+    }
+}
+
 #[derive(Clone, Copy)]
 struct SizeDimensions {
     width: i64,
@@ -115,7 +212,10 @@ impl SizeDimensions {}
 
 struct ValidatedSize<F>(SizeDimensions, PhantomData<F>);
 
-async fn extract_size<F>(ctx: &RequestContext) -> Result<ValidatedSize<F>, EdgeError>
+async fn extract_size<F>(
+    ctx: &RequestContext,
+    cfg: &AuctionConfig,
+) -> Result<ValidatedSize<F>, EdgeError>
 where
     F: AssetFormatMarker,
 {
@@ -125,7 +225,7 @@ where
         .map_err(|err| EdgeError::validation(err.to_string()))?;
 
     if let Some((width, height)) = parse_size_param(&params.size, F::FORMAT.suffix()) {
-        if !is_standard_size(width, height) {
+        if !F::accepts(width, height, cfg) {
             return Err(F::handle_invalid(ctx.request().uri().path(), width, height));
         }
 
@@ -141,7 +241,39 @@ where
     F: AssetFormatMarker + Send + Sync + 'static,
 {
     async fn from_request(ctx: &RequestContext) -> Result<Self, EdgeError> {
-        extract_size::<F>(ctx).await
+        extract_size::<F>(ctx, &AppConfig::global().auction).await
+    }
+}
+
+/// Public host for URLs embedded in responses: `X-Forwarded-Host`, then `Host`,
+/// then `[server] default_host`, followed by `[server] base_path` so generated
+/// `//{host}/...` URLs land under the mount point.
+struct RequestHost(String);
+
+fn request_host(headers: &HeaderMap, default_host: &str) -> String {
+    ["x-forwarded-host", header::HOST.as_str()]
+        .into_iter()
+        .filter_map(|name| headers.get(name)?.to_str().ok())
+        // X-Forwarded-Host may list every proxy hop; the first is the client-facing host
+        .filter_map(|value| value.split(',').next().map(str::trim))
+        .find(|host| !host.is_empty())
+        .unwrap_or(default_host)
+        .to_string()
+}
+
+#[async_trait(?Send)]
+impl FromRequest for RequestHost {
+    async fn from_request(ctx: &RequestContext) -> Result<Self, EdgeError> {
+        let server = &AppConfig::global().server;
+        let host = request_host(ctx.request().headers(), &server.default_host);
+        Ok(RequestHost(with_base_path(host, server)))
+    }
+}
+
+fn with_base_path(host: String, server: &ServerConfig) -> String {
+    match server.base_path() {
+        Some(prefix) => host + &prefix,
+        None => host,
     }
 }
 
@@ -159,12 +291,15 @@ fn parse_size_param(size: &str, suffix: &str) -> Option<(i64, i64)> {
 }
 
 fn validate_static_asset_size(value: &str) -> Result<(), ValidationError> {
-    if parse_size_param(value, ".svg").is_some() || parse_size_param(value, ".html").is_some() {
+    if [".svg", ".html", ".mp4"]
+        .iter()
+        .any(|suffix| parse_size_param(value, suffix).is_some())
+    {
         return Ok(());
     }
 
     let mut err = ValidationError::new("invalid_size");
-    err.message = Some("expected format <width>x<height>.(svg|html)".into());
+    err.message = Some("expected format <width>x<height>.(svg|html|mp4)".into());
     Err(err)
 }
 
@@ -180,50 +315,325 @@ fn build_response(status: StatusCode, body: Body) -> Response {
         .expect("static response builder should not fail")
 }
 
-fn apply_cors(headers: &mut HeaderMap) {
-    headers.insert("Access-Control-Allow-Origin", HeaderValue::from_static("*"));
+/// How long (seconds) browsers may cache a preflight answer.
+const CORS_MAX_AGE_SECS: &str = "7200";
+
+/// Add CORS headers answering a request with `request_headers`. With no
+/// `allowed` origins every origin gets `*`; otherwise a listed `Origin` is echoed
+/// with `Access-Control-Allow-Credentials`, and any other origin gets no CORS
+/// grant at all. For allowed origins a preflight's
+/// `Access-Control-Request-Method`/`-Headers` are reflected as the allowed
+/// methods and headers, which otherwise keep their static defaults.
+fn apply_cors(headers: &mut HeaderMap, request_headers: &HeaderMap, allowed: &[String]) {
+    let origin = request_headers.get(header::ORIGIN);
+    if allowed.is_empty() {
+        headers.insert("Access-Control-Allow-Origin", HeaderValue::from_static("*"));
+    } else {
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        let listed = origin.filter(|origin| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| allowed.iter().any(|allowed| allowed == origin))
+        });
+        let Some(origin) = listed else {
+            return;
+        };
+        headers.insert("Access-Control-Allow-Origin", origin.clone());
+        headers.insert(
+            "Access-Control-Allow-Credentials",
+            HeaderValue::from_static("true"),
+        );
+    }
+    let requested = |name| request_headers.get(name).filter(|value| !value.is_empty());
     headers.insert(
         "Access-Control-Allow-Methods",
-        HeaderValue::from_static("GET, POST, OPTIONS"),
+        requested(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .cloned()
+            .unwrap_or(HeaderValue::from_static("GET, POST, OPTIONS")),
     );
     headers.insert(
         "Access-Control-Allow-Headers",
-        HeaderValue::from_static("*, content-type"),
+        requested(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .cloned()
+            .unwrap_or(HeaderValue::from_static("content-type")),
+    );
+    headers.insert(
+        "Access-Control-Max-Age",
+        HeaderValue::from_static(CORS_MAX_AGE_SECS),
     );
 }
 
+/// Adds CORS headers to every response and answers every preflight itself with
+/// a 204, whatever the path, so preflights never depend on routing.
 pub struct Cors;
 
 #[async_trait(?Send)]
 impl Middleware for Cors {
     async fn handle(&self, ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
         let method = ctx.request().method().clone();
+        let request_headers = ctx.request().headers().clone();
         let mut response = if method == Method::OPTIONS {
             Ok(options_response())
         } else {
             next.run(ctx).await
         }?;
-        apply_cors(response.headers_mut());
+        apply_cors(
+            response.headers_mut(),
+            &request_headers,
+            &AppConfig::global().server.cors_allowed_origins,
+        );
+        Ok(response)
+    }
+}
+
+/// Header correlating a request with its response and log lines.
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming `X-Request-ID` kept as-is; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Tags every request with an `X-Request-ID`: the incoming one when present
+/// (and at most [`MAX_REQUEST_ID_LEN`] printable characters), otherwise a fresh
+/// UUIDv7. Handlers see it on the request; every response, errors included,
+/// echoes it.
+pub struct RequestId;
+
+#[async_trait(?Send)]
+impl Middleware for RequestId {
+    async fn handle(&self, mut ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
+        let id = incoming_request_id(ctx.request().headers()).unwrap_or_else(|| {
+            let id = crate::clock::uuid_v7(crate::clock::global()).to_string();
+            HeaderValue::from_str(&id).expect("uuid is a valid header")
+        });
+        ctx.request_mut()
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, id.clone());
+        let mut response = next
+            .run(ctx)
+            .await
+            .unwrap_or_else(IntoResponse::into_response);
+        response.headers_mut().insert(REQUEST_ID_HEADER, id);
         Ok(response)
     }
 }
 
+fn incoming_request_id(headers: &HeaderMap) -> Option<HeaderValue> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .filter(|id| {
+            id.to_str().is_ok_and(|id| {
+                (1..=MAX_REQUEST_ID_LEN).contains(&id.len())
+                    && id.chars().all(|c| c.is_ascii_graphic())
+            })
+        })
+        .cloned()
+}
+
+/// Access logging in the `[server] access_log_format`: `text` hands the request
+/// to EdgeZero's [`RequestLogger`], `json` instead logs one JSON record per
+/// request (method, path, status, duration and request id) under the `access`
+/// target. JSON records are timed on [`SystemClock`] like [`ServerTiming`];
+/// either way the response passes through untouched.
+///
+/// [`SystemClock`]: crate::clock::SystemClock
+pub struct AccessLog;
+
+#[async_trait(?Send)]
+impl Middleware for AccessLog {
+    async fn handle(&self, ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
+        match AppConfig::global().server.access_log_format {
+            AccessLogFormat::Text => RequestLogger.handle(ctx, next).await,
+            AccessLogFormat::Json => {
+                let (result, record) = run_logged(ctx, |ctx| next.run(ctx)).await;
+                log::info!(target: "access", "{}", record);
+                result
+            }
+        }
+    }
+}
+
+/// Run `run` on `ctx`, returning its result unchanged along with the JSON
+/// access record.
+async fn run_logged<F, Fut>(
+    ctx: RequestContext,
+    run: F,
+) -> (Result<Response, EdgeError>, serde_json::Value)
+where
+    F: FnOnce(RequestContext) -> Fut,
+    Fut: std::future::Future<Output = Result<Response, EdgeError>>,
+{
+    let clock = crate::clock::SystemClock;
+    let started = clock.now();
+    let request = ctx.request();
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let request_id = request.headers().get(REQUEST_ID_HEADER).cloned();
+    let result = run(ctx).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(err) => err.status(),
+    };
+    let record = access_record(
+        &method,
+        &path,
+        status,
+        clock.now().saturating_sub(started),
+        request_id.as_ref(),
+    );
+    (result, record)
+}
+
+/// One access log record as JSON.
+fn access_record(
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+    elapsed: Duration,
+    request_id: Option<&HeaderValue>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "method": method.as_str(),
+        "path": path,
+        "status": status.as_u16(),
+        "duration_ms": (elapsed.as_secs_f64() * 10_000.0).round() / 10.0,
+        "request_id": request_id.and_then(|id| id.to_str().ok()),
+    })
+}
+
+/// Adds `Server-Timing: app;dur=<ms>` with the time spent in the rest of the
+/// middleware chain and the handler, plus `Timing-Allow-Origin: *` so
+/// cross-origin pages can read it. Timed on [`SystemClock`], which also works
+/// on wasm targets.
+///
+/// [`SystemClock`]: crate::clock::SystemClock
+pub struct ServerTiming;
+
+#[async_trait(?Send)]
+impl Middleware for ServerTiming {
+    async fn handle(&self, ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
+        let clock = crate::clock::SystemClock;
+        let started = clock.now();
+        let mut response = next.run(ctx).await?;
+        let elapsed = clock.now().saturating_sub(started);
+        let headers = response.headers_mut();
+        headers.insert(
+            HeaderName::from_static("server-timing"),
+            HeaderValue::from_str(&server_timing(elapsed)).expect("metric is a valid header"),
+        );
+        headers.insert("Timing-Allow-Origin", HeaderValue::from_static("*"));
+        Ok(response)
+    }
+}
+
+/// `Server-Timing` value for a handler that took `elapsed`.
+fn server_timing(elapsed: Duration) -> String {
+    format!("app;dur={:.1}", elapsed.as_secs_f64() * 1000.0)
+}
+
+/// Answers 429 with `Retry-After` once the client's `[rate_limit.client]` bucket
+/// is empty, or a route's `[rate_limit.*]` budget for the current window is
+/// spent. Preflight requests are never counted. Clients are told apart by
+/// [`client_key`].
+pub struct RateLimit;
+
+#[async_trait(?Send)]
+impl Middleware for RateLimit {
+    async fn handle(&self, ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
+        let request = ctx.request();
+        let route = LimitedRoute::from_path(request.uri().path())
+            .filter(|_| request.method() != Method::OPTIONS);
+        let cfg = &AppConfig::global().rate_limit;
+        let now = crate::clock::SystemClock.now();
+        if let Some(limit) = cfg
+            .client
+            .as_ref()
+            .filter(|_| request.method() != Method::OPTIONS)
+        {
+            let client = client_key(request, limit.trust_forwarded);
+            if let Err(retry_after) = ClientLimiter::global().check(&client, limit, now) {
+                return Ok(rate_limited_response(retry_after));
+            }
+        }
+        if let Some((route, limit)) = route.and_then(|r| Some((r, r.limit(cfg)?))) {
+            if let Err(retry_after) = RateLimiter::global().check(route, limit, now) {
+                return Ok(rate_limited_response(retry_after));
+            }
+        }
+        next.run(ctx).await
+    }
+}
+
+/// Client key shared by every request without a usable address.
+const SHARED_CLIENT: &str = "shared";
+
+/// Client key for `[rate_limit.client]`. With `trust_forwarded` the forwarded
+/// address comes first (see [`forwarded_ip`]). Otherwise, or without one, it is
+/// the peer address the adapter records on the request; requests with neither
+/// share a single bucket rather than going unlimited.
+fn client_key(request: &Request, trust_forwarded: bool) -> Cow<'_, str> {
+    let forwarded = trust_forwarded
+        .then(|| forwarded_ip(request.headers()))
+        .flatten();
+    if let Some(ip) = forwarded {
+        return Cow::Borrowed(ip);
+    }
+    match request.extensions().get::<SocketAddr>() {
+        Some(peer) => Cow::Owned(peer.ip().to_string()),
+        None => Cow::Borrowed(SHARED_CLIENT),
+    }
+}
+
+/// Address forwarded by a proxy or edge platform: `CF-Connecting-IP`,
+/// `Fastly-Client-IP`, the first `X-Forwarded-For` hop, then `X-Real-IP`. Any
+/// client can send these, so they only identify clients behind a proxy that
+/// overwrites them.
+fn forwarded_ip(headers: &HeaderMap) -> Option<&str> {
+    [
+        "cf-connecting-ip",
+        "fastly-client-ip",
+        "x-forwarded-for",
+        "x-real-ip",
+    ]
+    .into_iter()
+    .filter_map(|name| headers.get(name)?.to_str().ok())
+    .filter_map(|value| value.split(',').next().map(str::trim))
+    .find(|ip| !ip.is_empty())
+}
+
+fn rate_limited_response(retry_after: Duration) -> Response {
+    let mut response = build_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        Body::text("rate limit exceeded"),
+    );
+    // Round up so clients never retry inside the same window
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from_str(&secs.max(1).to_string()).expect("digits are a valid header"),
+    );
+    response
+}
+
 #[action]
 pub async fn handle_options() -> Response {
     options_response()
 }
 
+/// Empty preflight answer. `Content-Length: 0` is explicit because some strict
+/// proxies reject a 204 preflight without it.
 fn options_response() -> Response {
     let mut response = build_response(StatusCode::NO_CONTENT, Body::empty());
-    response.headers_mut().insert(
+    let headers = response.headers_mut();
+    headers.insert(
         header::ALLOW,
         HeaderValue::from_static("GET, POST, OPTIONS"),
     );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("0"));
     response
 }
 
 #[action]
-pub async fn handle_root(ForwardedHost(host): ForwardedHost) -> Response {
+pub async fn handle_root(RequestHost(host): RequestHost) -> Response {
     let html = info_html(&host);
     let mut response = build_response(StatusCode::OK, Body::text(html));
     response.headers_mut().insert(
@@ -233,160 +643,735 @@ pub async fn handle_root(ForwardedHost(host): ForwardedHost) -> Response {
     response
 }
 
-#[action]
-pub async fn handle_openrtb_auction(
-    RequestContext(ctx): RequestContext,
-    ForwardedHost(host): ForwardedHost,
-    ValidatedJson(req): ValidatedJson<OpenRTBRequest>,
-) -> Result<Response, EdgeError> {
-    // Capture signature verification status for metadata
-    let signature_status = if let Some(domain) = req.site.as_ref().and_then(|s| s.domain.as_deref())
-    {
-        match crate::verification::verify_request_id_signature(
-            &ctx,
-            &req.id,
-            req.ext.as_ref(),
-            domain,
-        )
-        .await
-        {
-            Ok(kid) => {
-                log::info!("✅ Request signature verified with key: {}", kid);
-                SignatureStatus::Verified { kid }
-            }
-            Err(e) => {
-                log::error!("❌ Signature verification failed: {}", e);
-                SignatureStatus::Failed {
-                    reason: e.to_string(),
-                }
-            }
-        }
+/// OpenRTB auction body, inflated first when sent with `Content-Encoding: gzip`.
+struct AuctionJson(OpenRTBRequest);
+
+/// Inflate a gzip body, refusing to grow past `max_inflated` bytes so a small
+/// compressed payload can't exhaust memory.
+fn gunzip(body: &[u8], max_inflated: usize) -> Result<Vec<u8>, EdgeError> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(body)
+        .take(max_inflated as u64 + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|e| EdgeError::bad_request(format!("invalid gzip body: {}", e)))?;
+    if inflated.len() > max_inflated {
+        return Err(EdgeError::bad_request(format!(
+            "gzip body inflates past {} bytes",
+            max_inflated
+        )));
+    }
+    Ok(inflated)
+}
+
+fn parse_auction_body(
+    body: &[u8],
+    content_encoding: Option<&str>,
+    max_inflated: usize,
+    strict: bool,
+) -> Result<OpenRTBRequest, EdgeError> {
+    let gzipped = content_encoding.is_some_and(|enc| enc.trim().eq_ignore_ascii_case("gzip"));
+    let inflated;
+    let json = if gzipped {
+        inflated = gunzip(body, max_inflated)?;
+        &inflated[..]
     } else {
-        log::info!("⚠️ Signature verification skipped (no domain)");
-        SignatureStatus::NotPresent {
-            reason: "No site.domain present in request".to_string(),
+        body
+    };
+    let req: OpenRTBRequest = if strict {
+        let value: serde_json::Value =
+            serde_json::from_slice(json).map_err(|e| EdgeError::bad_request(e.to_string()))?;
+        let unknown = unknown_request_fields(&value);
+        if !unknown.is_empty() {
+            return Err(EdgeError::validation(format!(
+                "unknown request field(s): {}",
+                unknown.join(", ")
+            )));
         }
+        serde_json::from_value(value).map_err(|e| EdgeError::bad_request(e.to_string()))?
+    } else {
+        serde_json::from_slice(json).map_err(|e| EdgeError::bad_request(e.to_string()))?
     };
+    req.validate()
+        .map_err(|e| EdgeError::validation(e.to_string()))?;
+    Ok(req)
+}
 
-    log::info!("auction id={}, imps={}", req.id, req.imp.len());
-
-    // Build response with embedded metadata (signature status + request + response preview)
-    let resp = build_openrtb_response(&req, &host, signature_status);
-    let body = Body::json(&resp).map_err(|e| {
-        log::error!("Failed to serialize OpenRTB response: {}", e);
-        EdgeError::internal(e)
-    })?;
-    let mut response = build_response(StatusCode::OK, body);
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("application/json"),
-    );
-    Ok(response)
+#[async_trait(?Send)]
+impl FromRequest for AuctionJson {
+    async fn from_request(ctx: &RequestContext) -> Result<Self, EdgeError> {
+        let request = ctx.request();
+        let encoding = request
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok());
+        let cfg = AppConfig::global();
+        parse_auction_body(
+            request.body().as_bytes(),
+            encoding,
+            cfg.limits.max_inflated_bytes,
+            cfg.parsing.strict_request,
+        )
+        .map(AuctionJson)
+    }
 }
 
+/// `GET /openrtb2/auction`: a form for firing a test auction from a browser.
 #[action]
-pub async fn handle_static_img(
-    ValidatedSize(size, _): ValidatedSize<SvgSize>,
-    ValidatedQuery(query): ValidatedQuery<StaticImgQuery>,
-) -> Response {
-    let SizeDimensions {
-        width: w,
-        height: h,
-    } = size;
-    let svg = render_svg(w, h, query.bid);
-    let mut response = build_response(StatusCode::OK, Body::from(svg));
+pub async fn handle_auction_form() -> Response {
+    let html = auction_form_html(standard_sizes(&AppConfig::global().auction));
+    let mut response = build_response(StatusCode::OK, Body::text(html));
     response.headers_mut().insert(
         header::CONTENT_TYPE,
-        HeaderValue::from_static("image/svg+xml"),
+        HeaderValue::from_static("text/html; charset=utf-8"),
     );
     response
 }
 
 #[action]
-pub async fn handle_static_creatives(
-    ValidatedSize(size, _): ValidatedSize<HtmlSize>,
-    ValidatedQuery(query): ValidatedQuery<StaticCreativeQuery>,
-    ForwardedHost(host): ForwardedHost,
-) -> Response {
-    let SizeDimensions {
-        width: w,
-        height: h,
-    } = size;
-    let pixel_html = query.pixel_html.unwrap_or(true);
-    let pixel_js = query.pixel_js.unwrap_or(false);
-    let html = creative_html(w, h, pixel_html, pixel_js, &host);
-    let mut response = build_response(StatusCode::OK, Body::from(html));
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("text/html; charset=utf-8"),
-    );
-    response
+pub async fn handle_openrtb_auction(
+    RequestContext(ctx): RequestContext,
+    RequestHost(host): RequestHost,
+    AuctionJson(mut req): AuctionJson,
+) -> Result<Response, EdgeError> {
+    if let Some(media) = AppConfig::global().parsing.default_media {
+        apply_default_media(&mut req, media);
+    }
+    let uids = ctx
+        .request()
+        .headers()
+        .get(header::COOKIE)
+        .and_then(|c| c.to_str().ok())
+        .and_then(|c| parse_cookie(c, UIDS_COOKIE));
+    apply_synced_uid(&mut req, uids);
+    let debug = &AppConfig::global().debug;
+    if debug.enabled {
+        RequestRecorder::global().record(&req, debug.record_limit);
+    }
+    openrtb_auction_response(&ctx, &host, &req, AppConfig::global()).await
 }
 
-fn parse_cookie<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
-    for part in cookie_header.split(';') {
-        let trimmed = part.trim();
-        if let Some((k, v)) = trimmed.split_once('=') {
-            if k.trim() == name {
-                return Some(v.trim());
+/// Verify `ext.trusted_server` against the keys published by `site.domain`.
+async fn request_signature_status(ctx: &RequestContext, req: &OpenRTBRequest) -> SignatureStatus {
+    let Some(domain) = req.site.as_ref().and_then(|s| s.domain.as_deref()) else {
+        log::info!("⚠️ Signature verification skipped (no domain)");
+        return SignatureStatus::NotPresent {
+            reason: "No site.domain present in request".to_string(),
+        };
+    };
+    match crate::verification::verify_request_id_signature(ctx, &req.id, req.ext.as_ref(), domain)
+        .await
+    {
+        Ok(kid) => {
+            log::info!("✅ Request signature verified with key: {}", kid);
+            SignatureStatus::Verified { kid }
+        }
+        Err(e) => {
+            log::error!("❌ Signature verification failed: {}", e);
+            SignatureStatus::Failed {
+                reason: e.to_string(),
             }
         }
     }
-    None
 }
 
-const PIXEL_GIF: &[u8] = include_bytes!("../static/pixel.gif");
-
-#[action]
-pub async fn handle_pixel(
-    Headers(headers): Headers,
-    ValidatedQuery(params): ValidatedQuery<PixelQueryParams>,
-) -> Response {
-    let cookie_name = "mtkid";
-    let mut set_cookie = None;
+/// With `[verification] enforce`, a request carrying `ext.trusted_server` whose
+/// signature didn't verify is answered 401. Unsigned requests always pass.
+fn signature_rejection(
+    req: &OpenRTBRequest,
+    status: &SignatureStatus,
+    cfg: &VerificationConfig,
+) -> Option<Response> {
+    let signed = req
+        .ext
+        .as_ref()
+        .is_some_and(|ext| ext.get("trusted_server").is_some());
+    if !cfg.enforce || !signed {
+        return None;
+    }
+    let reason = match status {
+        SignatureStatus::Verified { .. } => return None,
+        SignatureStatus::Failed { reason } | SignatureStatus::NotPresent { reason } => reason,
+    };
+    let body = serde_json::json!({
+        "error": {
+            "code": "SIGNATURE_INVALID",
+            "message": reason,
+        }
+    });
+    let mut response = build_response(
+        StatusCode::UNAUTHORIZED,
+        Body::json(&body).expect("JSON value serializes"),
+    );
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Some(response)
+}
 
-    let PixelQueryParams { pid: _ } = params;
+/// Verify the request signature, run the auction and serialize the response.
+/// Shared by the live auction route and debug replay.
+async fn openrtb_auction_response(
+    ctx: &RequestContext,
+    host: &str,
+    req: &OpenRTBRequest,
+    cfg: &AppConfig,
+) -> Result<Response, EdgeError> {
+    let signature_status = request_signature_status(ctx, req).await;
+    if let Some(rejection) = signature_rejection(req, &signature_status, &cfg.verification) {
+        return Ok(rejection);
+    }
 
-    let existing = headers
-        .get(header::COOKIE)
-        .and_then(|c| c.to_str().ok())
-        .and_then(|c| parse_cookie(c, cookie_name));
+    let request_id = ctx
+        .request()
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or("-");
+    log::info!(
+        "auction id={}, imps={}, request_id={}",
+        truncate_str(&req.id, LOG_FIELD_MAX_BYTES),
+        req.imp.len(),
+        request_id
+    );
 
-    if existing.is_none() {
-        let id = Uuid::now_v7().as_simple().to_string();
-        let max_age = 60 * 60 * 24 * 365;
-        let cookie_val = format!(
-            "{}={}; Path=/; Max-Age={}; SameSite=None; Secure; HttpOnly",
-            cookie_name, id, max_age
-        );
-        set_cookie = Some(cookie_val);
+    // A delay asked for by the request (bounded by its tmax) replaces simulated
+    // latency; either way it sleeps on the timer the adapter installed
+    let latency = crate::latency::request_delay(req)
+        .or_else(|| crate::latency::next_delay(&cfg.auction, req));
+    if let Some(latency) = latency {
+        crate::latency::timer().sleep(latency).await;
     }
 
-    let mut response = build_response(StatusCode::OK, Body::from(PIXEL_GIF));
-    {
-        let headers = response.headers_mut();
-        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/gif"));
-        headers.insert(
-            header::CACHE_CONTROL,
-            HeaderValue::from_static("no-store, no-cache, must-revalidate, max-age=0"),
-        );
-        headers.insert("Pragma", HeaderValue::from_static("no-cache"));
-        headers.insert(
-            header::CONTENT_LENGTH,
-            HeaderValue::from_str(&PIXEL_GIF.len().to_string()).expect("length"),
-        );
+    // Build response with embedded metadata (signature status + request + response preview)
+    let mut resp = build_openrtb_response_with_config(req, host, signature_status, cfg);
+    if cfg.auction.video_response == VideoResponse::Redirect {
+        if let Some(location) = vast_redirect_url(req, &resp, host) {
+            let mut response = build_response(StatusCode::FOUND, Body::empty());
+            response.headers_mut().insert(
+                header::LOCATION,
+                HeaderValue::from_str(&location).map_err(EdgeError::internal)?,
+            );
+            return Ok(response);
+        }
     }
-
-    if let Some(cookie) = set_cookie {
-        if let Ok(value) = HeaderValue::from_str(&cookie) {
-            response.headers_mut().append("Set-Cookie", value);
+    if cfg.debug.enabled {
+        crate::debug::attach_self_check(req, &mut resp, &cfg.auction);
+    }
+    if cfg.prebid.server_mode {
+        crate::prebid::attach_server_ext(req, &mut resp, latency.unwrap_or_default(), cfg);
+    }
+    let limits = &cfg.limits;
+    if let Some(max_bytes) = limits.max_response_bytes {
+        if limits.strict_response_size {
+            let len = serialized_len(&resp);
+            if len > max_bytes {
+                return Err(EdgeError::internal(format!(
+                    "response of {} bytes exceeds max_response_bytes ({})",
+                    len, max_bytes
+                )));
+            }
+        } else {
+            let dropped = truncate_to_fit(&mut resp, max_bytes, cfg.debug.enabled);
+            if dropped > 0 {
+                log::warn!("Dropped {} bids to fit max_response_bytes", dropped);
+            }
         }
     }
-
+    let (body, content_type) = if accepts_protobuf(ctx.request().headers()) {
+        (Body::from(to_proto(&resp)), PROTOBUF_CONTENT_TYPE)
+    } else {
+        let body = Body::json(&resp).map_err(|e| {
+            log::error!("Failed to serialize OpenRTB response: {}", e);
+            EdgeError::internal(e)
+        })?;
+        (body, "application/json")
+    };
+    let mut response = build_response(StatusCode::OK, body);
     response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    insert_openrtb_version(response.headers_mut(), &cfg.auction.openrtb_version);
+    Ok(response)
 }
 
-#[derive(Deserialize, Validate)]
+/// Whether `Accept` lists the protobuf media type; JSON stays the default.
+fn accepts_protobuf(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            let essence = media.split(';').next().unwrap_or_default().trim();
+            essence.eq_ignore_ascii_case(PROTOBUF_CONTENT_TYPE)
+        })
+}
+
+const OPENRTB_VERSION_HEADER: &str = "x-openrtb-version";
+
+/// Advertise the OpenRTB version so clients can auto-detect the protocol.
+fn insert_openrtb_version(headers: &mut HeaderMap, version: &str) {
+    match HeaderValue::from_str(version) {
+        Ok(value) => {
+            headers.insert(OPENRTB_VERSION_HEADER, value);
+        }
+        Err(_) => log::warn!("Invalid openrtb_version {:?}, header omitted", version),
+    }
+}
+
+#[derive(Deserialize, Validate)]
+struct ReplayRequest {
+    /// Position in the recorder, 0 = oldest retained request.
+    index: usize,
+}
+
+/// Re-run a recorded auction request through the live auction (debug only).
+#[action]
+pub async fn handle_debug_replay(
+    RequestContext(ctx): RequestContext,
+    RequestHost(host): RequestHost,
+    ValidatedJson(body): ValidatedJson<ReplayRequest>,
+) -> Result<Response, EdgeError> {
+    require_debug(&AppConfig::global().debug, ctx.request().uri().path())?;
+    replay_recorded(RequestRecorder::global(), &ctx, &host, body.index).await
+}
+
+async fn replay_recorded(
+    recorder: &RequestRecorder,
+    ctx: &RequestContext,
+    host: &str,
+    index: usize,
+) -> Result<Response, EdgeError> {
+    let req = recorder.get(index).ok_or_else(|| {
+        EdgeError::not_found(format!(
+            "no recorded request at index {} ({} recorded)",
+            index,
+            recorder.len()
+        ))
+    })?;
+    log::info!(
+        "replaying recorded request #{} id={}",
+        index,
+        truncate_str(&req.id, LOG_FIELD_MAX_BYTES)
+    );
+    openrtb_auction_response(ctx, host, &req, AppConfig::global()).await
+}
+
+#[derive(Deserialize, Validate)]
+struct ApsDecodeQuery {
+    /// `amznbid` value from an APS response.
+    #[validate(length(min = 1, max = 128))]
+    token: String,
+}
+
+/// Decode a mocktioneer APS price token back to its price (debug only).
+#[action]
+pub async fn handle_debug_aps_decode(
+    RequestContext(ctx): RequestContext,
+    ValidatedQuery(query): ValidatedQuery<ApsDecodeQuery>,
+) -> Result<Response, EdgeError> {
+    require_debug(&AppConfig::global().debug, ctx.request().uri().path())?;
+    aps_decode_response(&query.token)
+}
+
+fn aps_decode_response(token: &str) -> Result<Response, EdgeError> {
+    let price = decode_aps_price(token).ok_or_else(|| {
+        EdgeError::validation(format!(
+            "not a mocktioneer APS price token: {:?}",
+            truncate_str(token, LOG_FIELD_MAX_BYTES)
+        ))
+    })?;
+    let body = serde_json::json!({ "token": token, "price": price });
+    let mut response = build_response(StatusCode::OK, Body::from(body.to_string()));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(response)
+}
+
+/// Most sizes one `/debug/validate-sizes` call may check.
+const MAX_VALIDATE_SIZES: usize = 100;
+
+/// Body of `/debug/validate-sizes`: a JSON array of `[w, h]` pairs, each
+/// dimension between 1 and [`MAX_AD_DIMENSION`].
+#[derive(Deserialize)]
+#[serde(transparent)]
+struct SizeList(Vec<[i64; 2]>);
+
+impl Validate for SizeList {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if !(1..=MAX_VALIDATE_SIZES).contains(&self.0.len()) {
+            let mut error = ValidationError::new("length");
+            error.message = Some(format!("expected 1 to {} sizes", MAX_VALIDATE_SIZES).into());
+            errors.add("sizes", error);
+        } else if !self
+            .0
+            .iter()
+            .flatten()
+            .all(|d| (1..=MAX_AD_DIMENSION).contains(d))
+        {
+            let mut error = ValidationError::new("range");
+            error.message =
+                Some(format!("sizes must be between 1 and {}", MAX_AD_DIMENSION).into());
+            errors.add("sizes", error);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Report which sizes are standard and the nearest standard size for the rest
+/// (debug only).
+#[action]
+pub async fn handle_debug_validate_sizes(
+    RequestContext(ctx): RequestContext,
+    ValidatedJson(sizes): ValidatedJson<SizeList>,
+) -> Result<Response, EdgeError> {
+    require_debug(&AppConfig::global().debug, ctx.request().uri().path())?;
+    Ok(validate_sizes_response(
+        &sizes.0,
+        &AppConfig::global().auction,
+    ))
+}
+
+fn validate_sizes_response(sizes: &[[i64; 2]], cfg: &AuctionConfig) -> Response {
+    let results: Vec<serde_json::Value> = sizes
+        .iter()
+        .map(|&[w, h]| {
+            if cfg.is_standard_size(w, h) {
+                serde_json::json!({ "w": w, "h": h, "standard": true })
+            } else {
+                let (nw, nh) = nearest_standard_size(w, h, cfg);
+                serde_json::json!({
+                    "w": w,
+                    "h": h,
+                    "standard": false,
+                    "nearest": format!("{}x{}", nw, nh),
+                })
+            }
+        })
+        .collect();
+    let body = serde_json::json!({ "sizes": results });
+    let mut response = build_response(StatusCode::OK, Body::from(body.to_string()));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// `Cache-Control` for static creatives, images and video, which are
+/// deterministic for their size, query and host.
+const STATIC_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// Strong ETag for a static asset. `key` must cover every input the body depends
+/// on; the crate version is mixed in so a deploy invalidates cached copies.
+fn asset_etag(key: &str) -> String {
+    let hash = fnv1a(
+        env!("CARGO_PKG_VERSION")
+            .bytes()
+            .chain([0])
+            .chain(key.bytes()),
+    );
+    format!("\"{:016x}\"", hash)
+}
+
+/// Whether `If-None-Match` lists `etag` (weak comparison) or is `*`.
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Cacheable static asset response, or a bodiless 304 when the client already
+/// holds `etag`. `render` only runs for a 200.
+fn static_asset_response(
+    headers: &HeaderMap,
+    etag: String,
+    content_type: &'static str,
+    render: impl FnOnce() -> Body,
+) -> Response {
+    let mut response = if etag_matches(headers, &etag) {
+        build_response(StatusCode::NOT_MODIFIED, Body::empty())
+    } else {
+        let mut response = build_response(StatusCode::OK, render());
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        response
+    };
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(STATIC_CACHE_CONTROL),
+    );
+    response_headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&etag).expect("hex etag"),
+    );
+    response
+}
+
+#[action]
+pub async fn handle_static_img(
+    ValidatedSize(size, _): ValidatedSize<SvgSize>,
+    ValidatedQuery(query): ValidatedQuery<StaticImgQuery>,
+    Headers(headers): Headers,
+) -> Response {
+    let SizeDimensions {
+        width: w,
+        height: h,
+    } = size;
+    let etag = asset_etag(&format!(
+        "svg {}x{} {:?} {:?}",
+        w, h, query.bid, query.theme
+    ));
+    static_asset_response(&headers, etag, "image/svg+xml", || {
+        Body::from(render_svg(w, h, query.bid, query.theme))
+    })
+}
+
+/// Placeholder clip behind VAST `MediaFile`s: a 15-second single-frame H.264
+/// MP4 at 16x16 that players scale to the requested size.
+const VIDEO_MP4: &[u8] = include_bytes!("../static/video.mp4");
+
+#[action]
+pub async fn handle_static_video(
+    ValidatedSize(_, _): ValidatedSize<Mp4Size>,
+    Headers(headers): Headers,
+) -> Response {
+    // Every size gets the same bytes, so they share one ETag
+    let etag = asset_etag("video mp4");
+    static_asset_response(&headers, etag, "video/mp4", || Body::from(VIDEO_MP4))
+}
+
+#[action]
+pub async fn handle_static_creatives(
+    ValidatedSize(size, _): ValidatedSize<HtmlSize>,
+    ValidatedQuery(query): ValidatedQuery<StaticCreativeQuery>,
+    RequestHost(host): RequestHost,
+    Headers(headers): Headers,
+) -> Response {
+    let SizeDimensions {
+        width: w,
+        height: h,
+    } = size;
+    let opts = CreativeOptions {
+        pixel_html: query.pixel_html.unwrap_or(true),
+        pixel_js: query.pixel_js.unwrap_or(false),
+        slow_ms: query
+            .slow
+            .map(|ms| ms.min(AppConfig::global().creative.max_slow_ms)),
+        refresh: query.refresh.map(|interval_secs| CreativeRefresh {
+            interval_secs,
+            reload: query.reload.unwrap_or(false),
+        }),
+        heavy: query.heavy.unwrap_or(false) && AppConfig::global().debug.enabled,
+        safeframe: query.safeframe.unwrap_or(false),
+        theme: query.theme,
+        template: AppConfig::global().templates.creative.as_deref(),
+    };
+    let etag = asset_etag(&format!("creative {}x{} {} {:?}", w, h, host, opts));
+    static_asset_response(&headers, etag, "text/html; charset=utf-8", || {
+        Body::from(creative_html(w, h, &host, &opts))
+    })
+}
+
+fn parse_cookie<'a>(cookie_header: &'a str, name: &str) -> Option<&'a str> {
+    for part in cookie_header.split(';') {
+        let trimmed = part.trim();
+        if let Some((k, v)) = trimmed.split_once('=') {
+            if k.trim() == name {
+                return Some(v.trim());
+            }
+        }
+    }
+    None
+}
+
+/// Inline VAST for a video bid; the target of redirecting video auctions.
+#[action]
+pub async fn handle_vast(
+    RequestHost(host): RequestHost,
+    ValidatedQuery(query): ValidatedQuery<VastQuery>,
+) -> Response {
+    let version = query.ver.as_deref().unwrap_or(DEFAULT_VAST_VERSION);
+    let xml = vast_xml(&host, &query.crid, query.w, query.h, query.dur, version);
+    let mut response = build_response(StatusCode::OK, Body::from(xml));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    response
+}
+
+const PIXEL_GIF: &[u8] = include_bytes!("../static/pixel.gif");
+
+#[action]
+pub async fn handle_pixel(
+    Headers(headers): Headers,
+    ValidatedQuery(params): ValidatedQuery<PixelQueryParams>,
+) -> Response {
+    let PixelQueryParams { pid: _, event } = params;
+    if let Some(event) = event {
+        log::info!("pixel event={}", event);
+    }
+    pixel_response(&headers, crate::clock::global())
+}
+
+const PIXEL_COOKIE: &str = "mtkid";
+const PIXEL_COOKIE_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+/// Tracking pixel, setting the `mtkid` cookie (id and expiry from `clock`) when absent.
+fn pixel_response(headers: &HeaderMap, clock: &dyn Clock) -> Response {
+    let mut set_cookie = None;
+
+    let existing = headers
+        .get(header::COOKIE)
+        .and_then(|c| c.to_str().ok())
+        .and_then(|c| parse_cookie(c, PIXEL_COOKIE));
+
+    if existing.is_none() {
+        let id = crate::clock::uuid_v7(clock).as_simple().to_string();
+        set_cookie = Some(persistent_cookie(PIXEL_COOKIE, &id, clock));
+    }
+
+    let mut response = gif_response();
+    if let Some(cookie) = set_cookie {
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append("Set-Cookie", value);
+        }
+    }
+
+    response
+}
+
+/// Cross-site `Set-Cookie` value for `name`, kept for a year from `clock`.
+fn persistent_cookie(name: &str, value: &str, clock: &dyn Clock) -> String {
+    format!(
+        "{}={}; Path=/; Max-Age={}; Expires={}; SameSite=None; Secure; HttpOnly",
+        name,
+        value,
+        PIXEL_COOKIE_MAX_AGE.as_secs(),
+        crate::clock::http_date_after(clock, PIXEL_COOKIE_MAX_AGE)
+    )
+}
+
+/// Uncacheable 1x1 transparent GIF.
+fn gif_response() -> Response {
+    let mut response = build_response(StatusCode::OK, Body::from(PIXEL_GIF));
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("image/gif"));
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("no-store, no-cache, must-revalidate, max-age=0"),
+    );
+    headers.insert("Pragma", HeaderValue::from_static("no-cache"));
+    headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from_str(&PIXEL_GIF.len().to_string()).expect("length"),
+    );
+    response
+}
+
+#[action]
+pub async fn handle_cookie_sync(
+    RequestHost(host): RequestHost,
+    ValidatedJson(req): ValidatedJson<CookieSyncRequest>,
+) -> Result<Response, EdgeError> {
+    log::info!("cookie sync for {} bidder(s)", req.bidders.len());
+    let resp = build_cookie_sync_response(&req, &host);
+    let body = Body::json(&resp).map_err(|e| {
+        log::error!("Failed to serialize cookie sync response: {}", e);
+        EdgeError::internal(e)
+    })?;
+    let mut response = build_response(StatusCode::OK, body);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(response)
+}
+
+#[derive(Deserialize, Validate)]
+struct SetUidParams {
+    #[validate(custom(function = "crate::usersync::validate_bidder"))]
+    bidder: String,
+    /// User id to store; a fresh UUIDv7 when absent.
+    #[serde(default)]
+    #[validate(length(min = 1, max = 128))]
+    uid: Option<String>,
+}
+
+/// Store the bidder's uid in the `uids` cookie, keeping other bidders' ids.
+#[action]
+pub async fn handle_setuid(
+    Headers(headers): Headers,
+    ValidatedQuery(params): ValidatedQuery<SetUidParams>,
+) -> Response {
+    setuid_response(&headers, params, crate::clock::global())
+}
+
+fn setuid_response(headers: &HeaderMap, params: SetUidParams, clock: &dyn Clock) -> Response {
+    let SetUidParams { bidder, uid } = params;
+    let uid = uid.unwrap_or_else(|| crate::clock::uuid_v7(clock).as_simple().to_string());
+    log::info!(
+        "setuid bidder={}, uid={}",
+        truncate_str(&bidder, LOG_FIELD_MAX_BYTES),
+        truncate_str(&uid, LOG_FIELD_MAX_BYTES)
+    );
+    let existing = headers
+        .get(header::COOKIE)
+        .and_then(|c| c.to_str().ok())
+        .and_then(|c| parse_cookie(c, UIDS_COOKIE));
+    let now_ms = clock.now().as_millis() as u64;
+    let uids = set_uid(existing, &bidder, &uid, now_ms);
+    let cookie = persistent_cookie(UIDS_COOKIE, &uids, clock);
+
+    let mut response = gif_response();
+    if let Ok(value) = HeaderValue::from_str(&cookie) {
+        response.headers_mut().append("Set-Cookie", value);
+    }
+    response
+}
+
+#[derive(Deserialize, Validate)]
+struct WinNoticeParams {
+    #[validate(length(min = 1, max = 128))]
+    imp: String,
+    /// Clearing price, or the literal `${AUCTION_PRICE}` if the exchange didn't
+    /// substitute the macro.
+    #[validate(length(min = 1, max = 32))]
+    price: String,
+    /// `win` for `nurl`, `billing` for `burl`.
+    #[serde(default)]
+    #[validate(length(max = 32))]
+    event: Option<String>,
+}
+
+/// Win and billing notices from the `nurl`/`burl` on auction bids.
+#[action]
+pub async fn handle_winnotice(ValidatedQuery(params): ValidatedQuery<WinNoticeParams>) -> Response {
+    log::info!(
+        "win notice event={}, imp={}, price={}",
+        params.event.as_deref().unwrap_or("win"),
+        params.imp,
+        params.price
+    );
+    gif_response()
+}
+
+#[derive(Deserialize, Validate)]
 struct ApsWinParams {
     #[validate(length(min = 1))]
     slot: String,
@@ -394,14 +1379,44 @@ struct ApsWinParams {
     price: f64,
 }
 
+/// APS bid request: a JSON body, falling back to the URL-encoded form `apstag`
+/// sends (form body, or the query string when the body is empty).
+struct ApsBid(ApsBidRequest);
+
+fn parse_aps_bid(body: &[u8], query: Option<&str>) -> Result<ApsBidRequest, EdgeError> {
+    let req = match serde_json::from_slice::<ApsBidRequest>(body) {
+        Ok(req) => req,
+        Err(json_err) => {
+            let encoded = if body.is_empty() {
+                query.unwrap_or_default().as_bytes()
+            } else {
+                body
+            };
+            ApsBidRequest::from_urlencoded(encoded)
+                .ok_or_else(|| EdgeError::bad_request(json_err.to_string()))?
+        }
+    };
+    req.validate()
+        .map_err(|e| EdgeError::validation(e.to_string()))?;
+    Ok(req)
+}
+
+#[async_trait(?Send)]
+impl FromRequest for ApsBid {
+    async fn from_request(ctx: &RequestContext) -> Result<Self, EdgeError> {
+        let request = ctx.request();
+        parse_aps_bid(request.body().as_bytes(), request.uri().query()).map(ApsBid)
+    }
+}
+
 #[action]
 pub async fn handle_aps_bid(
-    ForwardedHost(host): ForwardedHost,
-    ValidatedJson(req): ValidatedJson<ApsBidRequest>,
+    RequestHost(host): RequestHost,
+    ApsBid(req): ApsBid,
 ) -> Result<Response, EdgeError> {
     log::info!(
         "APS auction pubId={}, slots={}",
-        req.pub_id,
+        truncate_str(&req.pub_id, LOG_FIELD_MAX_BYTES),
         req.slots.len()
     );
 
@@ -430,12 +1445,12 @@ pub async fn handle_aps_win(ValidatedQuery(params): ValidatedQuery<ApsWinParams>
 
 #[action]
 pub async fn handle_adserver_mediate(
-    ForwardedHost(host): ForwardedHost,
+    RequestHost(host): RequestHost,
     ValidatedJson(req): ValidatedJson<crate::mediation::MediationRequest>,
 ) -> Result<Response, EdgeError> {
     log::info!(
         "Mediation request for auction '{}' with {} impressions and {} bidder responses",
-        req.id,
+        truncate_str(&req.id, LOG_FIELD_MAX_BYTES),
         req.imp.len(),
         req.ext.bidder_responses.len()
     );
@@ -444,7 +1459,7 @@ pub async fn handle_adserver_mediate(
 
     log::info!(
         "Mediation complete for auction '{}': {} seatbid(s)",
-        resp.id,
+        truncate_str(&resp.id, LOG_FIELD_MAX_BYTES),
         resp.seatbid.len()
     );
 
@@ -472,7 +1487,12 @@ pub async fn handle_click(ValidatedQuery(params): ValidatedQuery<ClickQueryParam
         .into_iter()
         .map(|(k, v)| serde_json::json!({ "KEY": k, "VALUE": v }))
         .collect();
-    log::info!("click crid={}, size={}x{}", crid, w, h);
+    log::info!(
+        "click crid={}, size={}x{}",
+        truncate_str(&crid, LOG_FIELD_MAX_BYTES),
+        w,
+        h
+    );
     const CLICK_TMPL: &str = include_str!("../static/templates/click.html.hbs");
     let html = render_template_str(
         CLICK_TMPL,
@@ -491,8 +1511,8 @@ pub async fn handle_click(ValidatedQuery(params): ValidatedQuery<ClickQueryParam
     response
 }
 
-/// Returns all standard ad sizes as JSON array.
-/// Useful for test fixtures and keeping external configs in sync with SIZE_MAP.
+/// Returns all standard ad sizes (`[auction] standard_sizes`) as JSON array.
+/// Useful for test fixtures and keeping external configs in sync.
 ///
 /// Response format:
 /// ```json
@@ -508,7 +1528,7 @@ pub async fn handle_click(ValidatedQuery(params): ValidatedQuery<ClickQueryParam
 pub async fn handle_sizes() -> Response {
     use crate::auction::get_cpm;
 
-    let sizes: Vec<serde_json::Value> = standard_sizes()
+    let sizes: Vec<serde_json::Value> = standard_sizes(&AppConfig::global().auction)
         .map(|(w, h)| {
             serde_json::json!({
                 "width": w,
@@ -527,6 +1547,58 @@ pub async fn handle_sizes() -> Response {
     response
 }
 
+/// Serves every route under `[server] base_path` too, by stripping the prefix
+/// from the request path before it is routed. Paths outside the prefix route
+/// unchanged.
+pub struct BasePath;
+
+#[async_trait(?Send)]
+impl Middleware for BasePath {
+    async fn handle(&self, mut ctx: RequestContext, next: Next<'_>) -> Result<Response, EdgeError> {
+        if let Some(prefix) = AppConfig::global().server.base_path() {
+            strip_request_base_path(ctx.request_mut(), &prefix);
+        }
+        next.run(ctx).await
+    }
+}
+
+/// Remove `prefix` from `request`'s path when it is under it.
+fn strip_request_base_path(request: &mut Request, prefix: &str) {
+    if let Some(uri) = strip_base_path(request.uri(), prefix) {
+        *request.uri_mut() = uri;
+    }
+}
+
+/// `uri` with `prefix` removed from the path (query kept), or `None` when the
+/// path is not under `prefix`.
+fn strip_base_path(uri: &Uri, prefix: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    let path = if rest.is_empty() { "/" } else { rest };
+    let stripped = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    stripped.parse().ok()
+}
+
+/// Liveness probe for load balancers: no Host lookup, config or rendering.
+#[action]
+pub async fn handle_health() -> Response {
+    let body = serde_json::json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    let mut response = build_response(StatusCode::OK, Body::from(body.to_string()));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -565,15 +1637,169 @@ mod tests {
     }
 
     #[test]
-    fn parse_cookie_extracts_value() {
-        let c = "a=1; mtkid=xyz; x=y";
-        assert_eq!(parse_cookie(c, "mtkid"), Some("xyz"));
-        assert_eq!(parse_cookie(c, "missing"), None);
+    fn request_host_prefers_forwarded_then_host_then_default() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_host(&headers, "qa.example"), "qa.example");
+        headers.insert(header::HOST, HeaderValue::from_static("origin.example"));
+        assert_eq!(request_host(&headers, "qa.example"), "origin.example");
+        headers.insert(
+            "x-forwarded-host",
+            HeaderValue::from_static("edge.example, origin.example"),
+        );
+        assert_eq!(request_host(&headers, "qa.example"), "edge.example");
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
     }
 
     #[test]
-    fn handle_pixel_sets_cookie_when_absent() {
-        let ctx = ctx(Method::GET, "/pixel?pid=test", Body::empty(), &[]);
+    fn gzipped_auction_body_gets_normal_response() {
+        let body = serde_json::json!({
+            "id": "r-gzip",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}]
+        });
+        let request = request_builder()
+            .method(Method::POST)
+            .uri("/openrtb2/auction")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(gzip(body.to_string().as_bytes())))
+            .unwrap();
+        let ctx = RequestContext::new(request, PathParams::default());
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        assert_eq!(json["id"], "r-gzip");
+        assert_eq!(json["seatbid"][0]["bid"][0]["impid"], "1");
+    }
+
+    #[test]
+    fn auction_answers_protobuf_when_accepted() {
+        use prost::Message;
+
+        let body = serde_json::json!({
+            "id": "r-proto",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}]
+        });
+        let request = request_builder()
+            .method(Method::POST)
+            .uri("/openrtb2/auction")
+            .header(
+                header::ACCEPT,
+                "application/x-protobuf;q=1, application/json;q=0.5",
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let ctx = RequestContext::new(request, PathParams::default());
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROTOBUF_CONTENT_TYPE
+        );
+        let bytes = response.into_body().into_bytes();
+        let decoded = crate::openrtb_proto::BidResponse::decode(bytes.as_ref()).unwrap();
+        assert_eq!(decoded.id, "r-proto");
+        let bid = &decoded.seatbid[0].bid[0];
+        assert_eq!(bid.impid, "1");
+        assert_eq!((bid.w, bid.h), (Some(300), Some(250)));
+        assert!(bid.price > 0.0);
+        assert!(bid.adm.as_deref().unwrap().contains("300x250"));
+    }
+
+    #[test]
+    fn accepts_protobuf_only_when_listed() {
+        let mut headers = HeaderMap::new();
+        assert!(!accepts_protobuf(&headers));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        assert!(!accepts_protobuf(&headers));
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("text/html, Application/X-Protobuf"),
+        );
+        assert!(accepts_protobuf(&headers));
+    }
+
+    #[test]
+    fn gzipped_auction_body_is_capped() {
+        let body = serde_json::json!({
+            "id": "r-bomb",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+            "ext": {"padding": "x".repeat(4096)}
+        })
+        .to_string();
+        let compressed = gzip(body.as_bytes());
+        assert!(parse_auction_body(&compressed, Some("gzip"), body.len(), false).is_ok());
+        let err = parse_auction_body(&compressed, Some("gzip"), 1024, false).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(err.message().contains("1024"));
+
+        let err = parse_auction_body(b"not gzip", Some("GZIP"), 1024, false).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        // Without the header the body is plain JSON
+        assert!(parse_auction_body(body.as_bytes(), None, 1024, false).is_ok());
+    }
+
+    #[test]
+    fn strict_request_rejects_unknown_top_level_fields() {
+        let body = serde_json::json!({
+            "id": "r-strict",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+            "tmaxx": 100
+        })
+        .to_string();
+        let err = parse_auction_body(body.as_bytes(), None, 1024, true).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(err.message().contains("tmaxx"));
+
+        // Lenient parsing ignores the typo
+        let req = parse_auction_body(body.as_bytes(), None, 1024, false).unwrap();
+        assert_eq!(req.tmax, None);
+    }
+
+    #[test]
+    fn auction_without_host_uses_default_host_in_creative_urls() {
+        let body = serde_json::json!({
+            "id": "r-nohost",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}]
+        });
+        let ctx = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::from(body.to_string()),
+            &[],
+        );
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        let adm = json["seatbid"][0]["bid"][0]["adm"].as_str().unwrap();
+        let expected = format!(
+            "//{}/static/creatives/300x250.html",
+            AppConfig::global().server.default_host
+        );
+        assert!(adm.contains(&expected), "adm: {}", adm);
+    }
+
+    #[test]
+    fn parse_cookie_extracts_value() {
+        let c = "a=1; mtkid=xyz; x=y";
+        assert_eq!(parse_cookie(c, "mtkid"), Some("xyz"));
+        assert_eq!(parse_cookie(c, "missing"), None);
+    }
+
+    #[test]
+    fn handle_pixel_sets_cookie_when_absent() {
+        let ctx = ctx(Method::GET, "/pixel?pid=test", Body::empty(), &[]);
         let response = response_from(block_on(handle_pixel(ctx)));
         assert_eq!(response.status(), StatusCode::OK);
         let ct = response
@@ -589,6 +1815,31 @@ mod tests {
             .any(|c| c.to_str().unwrap_or_default().starts_with("mtkid=")));
     }
 
+    #[test]
+    fn pixel_cookie_is_time_stable_under_fixed_clock() {
+        let clock = crate::clock::FixedClock::from_unix_ms(1_700_000_000_000);
+        let cookie = |response: Response| {
+            response
+                .headers()
+                .get("set-cookie")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        let first = cookie(pixel_response(&HeaderMap::new(), &clock));
+        let second = cookie(pixel_response(&HeaderMap::new(), &clock));
+
+        // Everything after the id is identical, including the computed expiry
+        let (first_id, first_attrs) = first.split_once(';').unwrap();
+        let (second_id, second_attrs) = second.split_once(';').unwrap();
+        assert_eq!(first_attrs, second_attrs);
+        assert!(first_attrs.contains("Max-Age=31536000"));
+        assert!(first_attrs.contains("Expires=Wed, 13 Nov 2024 22:13:20 GMT"));
+        // UUID v7 ids share the fixed 48-bit millisecond timestamp prefix
+        assert_eq!(first_id[..18], second_id[..18]);
+    }
+
     #[test]
     fn handle_pixel_requires_pid() {
         let ctx = ctx(Method::GET, "/pixel", Body::empty(), &[]);
@@ -663,6 +1914,327 @@ mod tests {
         assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
     }
 
+    #[test]
+    fn handle_openrtb_auction_rejects_invalid_exp_422() {
+        for exp in [-5, 0, 3601] {
+            let body = serde_json::json!({
+                "id": "req-exp",
+                "imp": [
+                    { "id": "imp-1", "banner": { "w": 300, "h": 250 }, "exp": exp }
+                ]
+            });
+            let ctx = ctx(
+                Method::POST,
+                "/openrtb2/auction",
+                Body::json(&body).expect("json body"),
+                &[],
+            );
+            let response = response_from(block_on(handle_openrtb_auction(ctx)));
+            assert_eq!(
+                response.status(),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "exp={} should be rejected",
+                exp
+            );
+        }
+    }
+
+    #[test]
+    fn handle_openrtb_auction_rejects_oversized_video_422() {
+        let body = serde_json::json!({
+            "id": "req-video",
+            "imp": [
+                { "id": "imp-1", "video": { "w": 4_000_000_000_i64, "h": 4_000_000_000_i64 } }
+            ]
+        });
+        let ctx = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::json(&body).expect("json body"),
+            &[],
+        );
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn handle_openrtb_auction_validates_cur() {
+        let status = |cur: serde_json::Value| {
+            let body = serde_json::json!({
+                "id": "req-cur",
+                "imp": [{ "id": "imp-1", "banner": { "w": 300, "h": 250 } }],
+                "cur": cur
+            });
+            let ctx = ctx(
+                Method::POST,
+                "/openrtb2/auction",
+                Body::json(&body).expect("json body"),
+                &[],
+            );
+            response_from(block_on(handle_openrtb_auction(ctx))).status()
+        };
+        assert_eq!(
+            status(serde_json::json!(["US"])),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(status(serde_json::json!(["USD", "EUR"])), StatusCode::OK);
+    }
+
+    #[test]
+    fn handle_openrtb_auction_valid_exp_flows_into_bid() {
+        let body = serde_json::json!({
+            "id": "req-exp",
+            "imp": [
+                { "id": "imp-1", "banner": { "w": 300, "h": 250 }, "exp": 300 }
+            ]
+        });
+        let ctx = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::json(&body).expect("json body"),
+            &[],
+        );
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).expect("valid json");
+        assert_eq!(json["seatbid"][0]["bid"][0]["exp"], 300);
+    }
+
+    #[test]
+    fn handle_openrtb_auction_honors_request_delay() {
+        let body = serde_json::json!({
+            "id": "req-delay",
+            "tmax": 200,
+            "imp": [{ "id": "imp-1", "banner": { "w": 300, "h": 250 } }],
+            "ext": { "mocktioneer": { "delay_ms": 10 } }
+        });
+        let delayed = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::json(&body).expect("json body"),
+            &[],
+        );
+        let start = std::time::Instant::now();
+        let response = response_from(block_on(handle_openrtb_auction(delayed)));
+        assert!(start.elapsed() >= std::time::Duration::from_millis(10));
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).expect("valid json");
+        assert_eq!(json["seatbid"][0]["bid"][0]["impid"], "imp-1");
+
+        // A delay past tmax is cut to just under it
+        let body = serde_json::json!({
+            "id": "req-delay-capped",
+            "tmax": 20,
+            "imp": [{ "id": "imp-1", "banner": { "w": 300, "h": 250 } }],
+            "ext": { "mocktioneer": { "delay_ms": 60_000 } }
+        });
+        let capped = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::json(&body).expect("json body"),
+            &[],
+        );
+        let start = std::time::Instant::now();
+        let response = response_from(block_on(handle_openrtb_auction(capped)));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn handle_openrtb_auction_advertises_openrtb_version() {
+        let body = serde_json::json!({
+            "id": "req-version",
+            "imp": [{ "id": "imp-1", "banner": { "w": 300, "h": 250 } }]
+        });
+        let ctx = ctx(
+            Method::POST,
+            "/openrtb2/auction",
+            Body::json(&body).expect("json body"),
+            &[],
+        );
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(OPENRTB_VERSION_HEADER).unwrap(),
+            AppConfig::global().auction.openrtb_version.as_str()
+        );
+    }
+
+    #[test]
+    fn insert_openrtb_version_uses_configured_value() {
+        let mut headers = HeaderMap::new();
+        insert_openrtb_version(&mut headers, "2.5");
+        assert_eq!(headers.get(OPENRTB_VERSION_HEADER).unwrap(), "2.5");
+
+        let mut headers = HeaderMap::new();
+        insert_openrtb_version(&mut headers, "bad\nvalue");
+        assert!(headers.get(OPENRTB_VERSION_HEADER).is_none());
+    }
+
+    #[test]
+    fn handle_debug_replay_404_when_debug_disabled() {
+        let ctx = ctx(
+            Method::POST,
+            "/debug/replay",
+            Body::json(&serde_json::json!({ "index": 0 })).expect("json body"),
+            &[],
+        );
+        let response = response_from(block_on(handle_debug_replay(ctx)));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn handle_debug_validate_sizes_404_when_debug_disabled() {
+        let ctx = ctx(
+            Method::POST,
+            "/debug/validate-sizes",
+            Body::json(&serde_json::json!([[300, 250]])).expect("json body"),
+            &[],
+        );
+        let response = response_from(block_on(handle_debug_validate_sizes(ctx)));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn validate_sizes_reports_standard_and_nearest() {
+        let response =
+            validate_sizes_response(&[[300, 250], [333, 222]], &AuctionConfig::default());
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        let sizes = json["sizes"].as_array().unwrap();
+        assert_eq!(sizes[0]["standard"], true);
+        assert!(sizes[0].get("nearest").is_none());
+        assert_eq!(sizes[1]["standard"], false);
+        assert_eq!(sizes[1]["nearest"], "300x250");
+    }
+
+    #[test]
+    fn size_list_rejects_empty_and_oversized_bodies() {
+        assert!(SizeList(vec![]).validate().is_err());
+        assert!(SizeList(vec![[300, 250]; MAX_VALIDATE_SIZES + 1])
+            .validate()
+            .is_err());
+        assert!(SizeList(vec![[300, 250]]).validate().is_ok());
+        assert!(SizeList(vec![[4_000_000_000, 1]]).validate().is_err());
+        assert!(SizeList(vec![[300, 0]]).validate().is_err());
+    }
+
+    #[test]
+    fn parse_aps_bid_falls_back_to_form_encoding() {
+        let form = br#"src=5555&slots=[{"sd":"a","s":["300x250"]}]"#;
+        let req = parse_aps_bid(form, Some("src=ignored")).expect("form body");
+        assert_eq!(req.pub_id, "5555");
+        let req = parse_aps_bid(b"", Some(std::str::from_utf8(form).unwrap())).expect("query");
+        assert_eq!(req.slots[0].sizes, vec![[300, 250]]);
+
+        let err = parse_aps_bid(b"not json", None).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        let err = parse_aps_bid(br#"{"pubId":"1","slots":[]}"#, None).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn handle_debug_aps_decode_404_when_debug_disabled() {
+        let ctx = ctx(
+            Method::GET,
+            "/debug/aps/decode?token=Mi41",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_debug_aps_decode(ctx)));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn aps_decode_response_decodes_known_token() {
+        let response = response_from(aps_decode_response("Mi41"));
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        assert_eq!(json["token"], "Mi41");
+        assert_eq!(json["price"], 2.5);
+
+        let response = response_from(aps_decode_response("pgafb4"));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    /// Strip per-run values (bid ids and the creative markup that embeds them).
+    fn stable_auction_json(response: Response) -> serde_json::Value {
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).expect("valid json");
+        for seatbid in json["seatbid"].as_array_mut().unwrap() {
+            for bid in seatbid["bid"].as_array_mut().unwrap() {
+                let bid = bid.as_object_mut().unwrap();
+                bid.remove("id");
+                bid.remove("adm");
+            }
+        }
+        json
+    }
+
+    #[test]
+    fn replay_recorded_matches_fresh_run() {
+        let req: OpenRTBRequest = serde_json::from_value(serde_json::json!({
+            "id": "replay-1",
+            "imp": [
+                { "id": "1", "banner": { "w": 728, "h": 90 } },
+                { "id": "2", "banner": { "format": [{ "w": 320, "h": 50 }] } }
+            ]
+        }))
+        .unwrap();
+        let recorder = RequestRecorder::default();
+        recorder.record(&req, 10);
+
+        let ctx = ctx(Method::POST, "/debug/replay", Body::empty(), &[]);
+        let replayed = block_on(replay_recorded(&recorder, &ctx, "host.test", 0)).unwrap();
+        let fresh = block_on(openrtb_auction_response(
+            &ctx,
+            "host.test",
+            &req,
+            AppConfig::global(),
+        ))
+        .unwrap();
+        assert_eq!(replayed.status(), StatusCode::OK);
+        let replayed = stable_auction_json(replayed);
+        assert_eq!(replayed["id"], "replay-1");
+        assert_eq!(replayed, stable_auction_json(fresh));
+
+        let missing = response_from(block_on(replay_recorded(&recorder, &ctx, "host.test", 1)));
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn video_auction_redirects_to_vast_when_configured() {
+        let req: OpenRTBRequest = serde_json::from_value(serde_json::json!({
+            "id": "video-1",
+            "imp": [{ "id": "1", "video": { "w": 640, "h": 360 } }]
+        }))
+        .unwrap();
+        let ctx = ctx(Method::POST, "/openrtb2/auction", Body::empty(), &[]);
+        let mut cfg = AppConfig::default();
+        cfg.auction.video_response = VideoResponse::Redirect;
+        let response = block_on(openrtb_auction_response(&ctx, "host.test", &req, &cfg)).unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(location.starts_with("//host.test/vast?"), "{}", location);
+        assert!(location.contains("w=640&h=360"));
+        assert!(response.into_body().into_bytes().is_empty());
+
+        // Inline, the default, answers with the bid JSON
+        let cfg = AppConfig::default();
+        let response = block_on(openrtb_auction_response(&ctx, "host.test", &req, &cfg)).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::LOCATION).is_none());
+    }
+
     #[test]
     fn handle_static_img_svg_ok_and_nonstandard_422() {
         let ctx_ok = ctx(
@@ -691,6 +2263,69 @@ mod tests {
         assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
     }
 
+    #[test]
+    fn static_sizes_follow_configured_standard_sizes() {
+        let custom = AuctionConfig {
+            standard_sizes: vec![[333, 222]],
+            ..Default::default()
+        };
+        let svg = ctx(
+            Method::GET,
+            "/static/img/333x222.svg",
+            Body::empty(),
+            &[("size", "333x222.svg")],
+        );
+        assert!(block_on(extract_size::<SvgSize>(&svg, &custom)).is_ok());
+        let err = block_on(extract_size::<SvgSize>(&svg, &AuctionConfig::default()))
+            .err()
+            .expect("not a default size");
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let html = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let err = block_on(extract_size::<HtmlSize>(&html, &custom))
+            .err()
+            .expect("dropped from the custom list");
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn handle_static_video_serves_vast_media_file() {
+        let vast = crate::render::vast_xml("host.test", "crid-v", 640, 360, 15, "4.0");
+        let path = vast
+            .split("//host.test")
+            .find_map(|rest| rest.split("]]>").next()?.strip_prefix("/static/video/"))
+            .expect("media file url");
+        assert_eq!(path, "640x360.mp4");
+        let uri = format!("/static/video/{}", path);
+        let response = response_from(block_on(handle_static_video(ctx(
+            Method::GET,
+            &uri,
+            Body::empty(),
+            &[("size", path)],
+        ))));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "video/mp4"
+        );
+        let body = response.into_body().into_bytes();
+        assert_eq!(body.as_ref(), VIDEO_MP4);
+        assert_eq!(&body[4..8], b"ftyp");
+
+        let zero = response_from(block_on(handle_static_video(ctx(
+            Method::GET,
+            "/static/video/0x360.mp4",
+            Body::empty(),
+            &[("size", "0x360.mp4")],
+        ))));
+        assert_eq!(zero.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
     #[test]
     fn handle_static_creatives_html_ok() {
         let mut builder = request_builder();
@@ -708,60 +2343,257 @@ mod tests {
         );
         let response = response_from(block_on(handle_static_creatives(ctx)));
         assert_eq!(response.status(), StatusCode::OK);
-        let ct = response
+        let ct = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(ct.starts_with("text/html"));
+        let body = response.into_body().into_bytes();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body_str.contains("data-static-pid=\""));
+        assert!(body_str.contains("//mocktioneer.edgecompute.app/pixel?pid="));
+        assert!(!body_str.contains("var jsPid = \""));
+    }
+
+    #[test]
+    fn handle_static_creatives_safeframe_adds_sf_api_and_beacon() {
+        let safeframe = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?safeframe=true",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(safeframe)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains("$sf.ext"));
+        assert!(body.contains("$sf.ext.register(300, 250,"));
+        assert!(body.contains("$sf.ext.geom()"));
+        assert!(body.contains("&event=safeframe"));
+
+        let plain = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(plain)));
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(!body.contains("event=safeframe"));
+    }
+
+    #[test]
+    fn handle_static_creatives_refresh_adds_beacon() {
+        let refresh = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?refresh=15&reload=true",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(refresh)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains("var refreshSecs = 15;"));
+        assert!(body.contains("&event=refresh"));
+        assert!(body.contains("location.reload()"));
+
+        let invalid = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?refresh=0",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(invalid)));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn handle_static_creatives_html_ok_with_js_pixel() {
+        let ctx = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?pixel_js=true",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains("data-static-pid=\""));
+        assert!(body.contains("var jsPid = \""));
+        let static_pid = body
+            .split("data-static-pid=\"")
+            .nth(1)
+            .and_then(|s| s.split('\"').next())
+            .expect("static pid");
+        let js_pid = body
+            .split("var jsPid = \"")
+            .nth(1)
+            .and_then(|s| s.split('\"').next())
+            .expect("js pid");
+        assert_ne!(static_pid, js_pid);
+    }
+
+    #[test]
+    fn handle_static_creatives_html_ok_without_pixel() {
+        let ctx = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?pixel_html=false",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().into_bytes();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!body.contains("/pixel"));
+        assert!(!body.contains("var jsPid = \""));
+    }
+
+    #[test]
+    fn handle_static_creatives_slow_is_capped() {
+        let ctx = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?slow=999999999",
+            Body::empty(),
+            &[("size", "300x250.html")],
+        );
+        let response = response_from(block_on(handle_static_creatives(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        let expected = format!("var slowMs = {};", AppConfig::global().creative.max_slow_ms);
+        assert!(body.contains(&expected));
+    }
+
+    #[test]
+    fn static_assets_are_cacheable_and_revalidate() {
+        let get = |uri: &str, size: &str, if_none_match: Option<&str>| {
+            let mut builder = request_builder().method(Method::GET).uri(uri);
+            if let Some(etag) = if_none_match {
+                builder = builder.header(header::IF_NONE_MATCH, etag);
+            }
+            let request = builder.body(Body::empty()).unwrap();
+            let params = HashMap::from([("size".to_string(), size.to_string())]);
+            RequestContext::new(request, PathParams::new(params))
+        };
+        let img = |uri, if_none_match| {
+            response_from(block_on(handle_static_img(get(
+                uri,
+                "300x250.svg",
+                if_none_match,
+            ))))
+        };
+        let uri = "/static/img/300x250.svg?bid=1.5";
+        let response = img(uri, None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+        let etag = response
             .headers()
-            .get(header::CONTENT_TYPE)
+            .get(header::ETAG)
             .unwrap()
             .to_str()
             .unwrap();
-        assert!(ct.starts_with("text/html"));
-        let body = response.into_body().into_bytes();
-        let body_str = String::from_utf8(body.to_vec()).unwrap();
-        assert!(body_str.contains("data-static-pid=\""));
-        assert!(body_str.contains("//mocktioneer.edgecompute.app/pixel?pid="));
-        assert!(!body_str.contains("var jsPid = \""));
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+        let revalidated = img(uri, Some(etag));
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(revalidated.headers().get(header::ETAG).unwrap(), etag);
+        assert!(revalidated.into_body().into_bytes().is_empty());
+        let weak = format!("\"other\", W/{}", etag);
+        assert_eq!(img(uri, Some(&weak)).status(), StatusCode::NOT_MODIFIED);
+
+        // Other params make another ETag, so the stale tag gets a full body
+        let other = img("/static/img/300x250.svg", Some(etag));
+        assert_eq!(other.status(), StatusCode::OK);
+
+        // Creatives derive their pixel ids from the size and theme, so the
+        // same URL renders the same body and revalidates too
+        let creative = |uri, if_none_match: Option<&str>| {
+            response_from(block_on(handle_static_creatives(get(
+                uri,
+                "300x250.html",
+                if_none_match,
+            ))))
+        };
+        let uri = "/static/creatives/300x250.html?pixel_js=true";
+        let first = creative(uri, None);
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(
+            first.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=3600"
+        );
+        let creative_etag = first
+            .headers()
+            .get(header::ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_ne!(creative_etag, etag);
+        let body = first.into_body().into_bytes();
+        assert_eq!(
+            creative(uri, None).into_body().into_bytes(),
+            body,
+            "same URL renders the same creative"
+        );
+        let revalidated = creative(uri, Some(&creative_etag));
+        assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+        assert!(revalidated.into_body().into_bytes().is_empty());
+        let other = creative("/static/creatives/300x250.html", Some(&creative_etag));
+        assert_eq!(other.status(), StatusCode::OK);
     }
 
     #[test]
-    fn handle_static_creatives_html_ok_with_js_pixel() {
-        let ctx = ctx(
+    fn static_assets_take_a_theme() {
+        let img = ctx(
             Method::GET,
-            "/static/creatives/300x250.html?pixel_js=true",
+            "/static/img/300x250.svg?theme=dark",
+            Body::empty(),
+            &[("size", "300x250.svg")],
+        );
+        let response = response_from(block_on(handle_static_img(img)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains(r##"stop-color="#0f172a""##));
+
+        let creative = ctx(
+            Method::GET,
+            "/static/creatives/300x250.html?theme=highlight",
             Body::empty(),
             &[("size", "300x250.html")],
         );
-        let response = response_from(block_on(handle_static_creatives(ctx)));
+        let response = response_from(block_on(handle_static_creatives(creative)));
         assert_eq!(response.status(), StatusCode::OK);
         let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
-        assert!(body.contains("data-static-pid=\""));
-        assert!(body.contains("var jsPid = \""));
-        let static_pid = body
-            .split("data-static-pid=\"")
-            .nth(1)
-            .and_then(|s| s.split('\"').next())
-            .expect("static pid");
-        let js_pid = body
-            .split("var jsPid = \"")
-            .nth(1)
-            .and_then(|s| s.split('\"').next())
-            .expect("js pid");
-        assert_ne!(static_pid, js_pid);
+        assert!(body.contains("/static/img/300x250.svg?theme=highlight"));
+
+        let unknown = ctx(
+            Method::GET,
+            "/static/img/300x250.svg?theme=neon",
+            Body::empty(),
+            &[("size", "300x250.svg")],
+        );
+        let response = response_from(block_on(handle_static_img(unknown)));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
     #[test]
-    fn handle_static_creatives_html_ok_without_pixel() {
+    fn handle_static_creatives_heavy_needs_debug() {
+        // The embedded config leaves debug off, so ?heavy=true is ignored
         let ctx = ctx(
             Method::GET,
-            "/static/creatives/300x250.html?pixel_html=false",
+            "/static/creatives/300x250.html?heavy=true",
             Body::empty(),
             &[("size", "300x250.html")],
         );
         let response = response_from(block_on(handle_static_creatives(ctx)));
         assert_eq!(response.status(), StatusCode::OK);
-        let body = response.into_body().into_bytes();
-        let body = String::from_utf8(body.to_vec()).unwrap();
-        assert!(!body.contains("/pixel"));
-        assert!(!body.contains("var jsPid = \""));
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(!body.contains("sliceEnd"));
     }
 
     #[test]
@@ -812,6 +2644,38 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn handle_vast_serves_inline_vast() {
+        let ctx = ctx(
+            Method::GET,
+            "/vast?crid=mocktioneer-v1&w=640&h=360&dur=75&ver=4.2",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_vast(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/xml"
+        );
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains(r#"<VAST version="4.2">"#));
+        assert!(body.contains("<Duration>00:01:15</Duration>"));
+        assert!(body.contains("/static/video/640x360.mp4"));
+    }
+
+    #[test]
+    fn handle_vast_rejects_unknown_version() {
+        let ctx = ctx(
+            Method::GET,
+            "/vast?crid=c&w=640&h=360&dur=30&ver=5.0",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_vast(ctx)));
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
     #[test]
     fn handle_click_echoes_params() {
         let ctx = ctx(
@@ -829,6 +2693,37 @@ mod tests {
         assert!(!body.contains("Additional Parameters"));
     }
 
+    #[test]
+    fn handle_click_renders_size_from_click_url() {
+        let url = crate::render::click_url("host.test", "mocktioneer-imp-9", 970, 250);
+        let uri = url.trim_start_matches("//host.test");
+        let ctx = ctx(Method::GET, uri, Body::empty(), &[]);
+        let response = response_from(block_on(handle_click(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains("mocktioneer-imp-9"));
+        assert!(body.contains("970"));
+        assert!(body.contains("250"));
+        assert!(!body.contains("Additional Parameters"));
+    }
+
+    #[test]
+    fn handle_auction_form_returns_html_form() {
+        let ctx = ctx(Method::GET, "/openrtb2/auction", Body::empty(), &[]);
+        let response = response_from(block_on(handle_auction_form(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let ct = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(ct.starts_with("text/html"));
+        let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+        assert!(body.contains("<form"));
+        assert!(body.contains(r#"<option value="300x250">300x250</option>"#));
+    }
+
     #[test]
     fn handle_root_returns_html() {
         let ctx = ctx(Method::GET, "/", Body::empty(), &[]);
@@ -939,6 +2834,162 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn handle_cookie_sync_returns_bidder_status() {
+        let body = Body::json(&serde_json::json!({"bidders": ["appnexus", "rubicon"]})).unwrap();
+        let ctx = ctx(Method::POST, "/cookie_sync", body, &[]);
+        let response = response_from(block_on(handle_cookie_sync(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        assert_eq!(json["status"], "ok");
+        let statuses = json["bidder_status"].as_array().unwrap();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[1]["bidder"], "rubicon");
+        assert_eq!(statuses[1]["usersync"]["type"], "redirect");
+        assert!(statuses[1]["usersync"]["url"]
+            .as_str()
+            .unwrap()
+            .ends_with("/setuid?bidder=rubicon"));
+    }
+
+    #[test]
+    fn handle_setuid_sets_uids_cookie() {
+        let ctx = ctx(
+            Method::GET,
+            "/setuid?bidder=appnexus&uid=an-1",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_setuid(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/gif"
+        );
+        let cookie = response
+            .headers()
+            .get("Set-Cookie")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(cookie.contains("SameSite=None; Secure; HttpOnly"));
+        let value = parse_cookie(cookie, UIDS_COOKIE).unwrap();
+        let uids = crate::usersync::decode_uids(value);
+        assert_eq!(uids.get("appnexus").map(String::as_str), Some("an-1"));
+    }
+
+    #[test]
+    fn setuid_merges_existing_uids_cookie() {
+        let clock = crate::clock::FixedClock::from_unix_ms(1_700_000_000_000);
+        let mut headers = HeaderMap::new();
+        let existing = format!("{}={}", UIDS_COOKIE, set_uid(None, "rubicon", "rp-1", 1));
+        headers.insert(header::COOKIE, HeaderValue::from_str(&existing).unwrap());
+        let params = SetUidParams {
+            bidder: "appnexus".to_string(),
+            uid: None,
+        };
+        let response = setuid_response(&headers, params, &clock);
+        let cookie = response
+            .headers()
+            .get("Set-Cookie")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let uids = crate::usersync::decode_uids(parse_cookie(cookie, UIDS_COOKIE).unwrap());
+        assert_eq!(uids.get("rubicon").map(String::as_str), Some("rp-1"));
+        // Without a uid a fresh one is generated
+        assert_eq!(uids["appnexus"].len(), 32);
+    }
+
+    #[test]
+    fn auction_echoes_uid_synced_through_setuid() {
+        let ctx = ctx(
+            Method::GET,
+            "/setuid?bidder=mocktioneer&uid=mt-42",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_setuid(ctx)));
+        let set_cookie = response
+            .headers()
+            .get("Set-Cookie")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let uids = parse_cookie(set_cookie, UIDS_COOKIE).unwrap();
+
+        let body = serde_json::json!({
+            "id": "r-sync",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}]
+        });
+        let request = request_builder()
+            .method(Method::POST)
+            .uri("/openrtb2/auction")
+            .header(header::COOKIE, format!("{}={}", UIDS_COOKIE, uids))
+            .body(Body::json(&body).expect("json body"))
+            .unwrap();
+        let ctx = RequestContext::new(request, PathParams::default());
+        let response = response_from(block_on(handle_openrtb_auction(ctx)));
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        assert_eq!(
+            json["seatbid"][0]["bid"][0]["ext"]["mocktioneer"]["buyeruid"],
+            "mt-42"
+        );
+    }
+
+    #[test]
+    fn handle_setuid_requires_valid_bidder() {
+        let cases = [
+            ("/setuid?uid=x", StatusCode::BAD_REQUEST),
+            (
+                "/setuid?bidder=a%3Bb&uid=x",
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+        ];
+        for (uri, status) in cases {
+            let ctx = ctx(Method::GET, uri, Body::empty(), &[]);
+            let response = response_from(block_on(handle_setuid(ctx)));
+            assert_eq!(response.status(), status, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn handle_winnotice_returns_gif() {
+        let ctx = ctx(
+            Method::GET,
+            "/winnotice?imp=1&price=2.50&event=billing",
+            Body::empty(),
+            &[],
+        );
+        let response = response_from(block_on(handle_winnotice(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "image/gif"
+        );
+        assert!(response.headers().get("Set-Cookie").is_none());
+        assert_eq!(response.into_body().into_bytes().as_ref(), PIXEL_GIF);
+    }
+
+    #[test]
+    fn handle_winnotice_requires_imp() {
+        let ctx = ctx(Method::GET, "/winnotice?price=2.50", Body::empty(), &[]);
+        let response = response_from(block_on(handle_winnotice(ctx)));
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn handle_options_sets_allow_and_zero_content_length() {
+        let ctx = ctx(Method::OPTIONS, "/pixel", Body::empty(), &[]);
+        let response = response_from(block_on(handle_options(ctx)));
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers.get(header::ALLOW).unwrap(), "GET, POST, OPTIONS");
+        assert_eq!(headers.get(header::CONTENT_LENGTH).unwrap(), "0");
+    }
+
     #[test]
     fn handle_aps_win_valid() {
         let ctx = ctx(
@@ -997,11 +3048,324 @@ mod tests {
         let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
         let json: serde_json::Value = serde_json::from_str(&body).unwrap();
         let sizes = json["sizes"].as_array().unwrap();
-        assert_eq!(sizes.len(), standard_sizes().count());
+        assert_eq!(
+            sizes.len(),
+            standard_sizes(&AuctionConfig::default()).count()
+        );
         // Check one size has all expected fields
         let first = &sizes[0];
         assert!(first["width"].is_i64());
         assert!(first["height"].is_i64());
         assert!(first["cpm"].is_f64());
     }
+
+    #[test]
+    fn handle_health_returns_ok_json() {
+        // No Host header: the probe must not depend on it
+        let ctx = ctx(Method::GET, "/health", Body::empty(), &[]);
+        let response = response_from(block_on(handle_health(ctx)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+        let json: serde_json::Value =
+            serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn with_base_path_prefixes_generated_urls() {
+        let server = ServerConfig {
+            base_path: "/mock".to_string(),
+            ..Default::default()
+        };
+        let host = with_base_path("host.test".to_string(), &server);
+        assert_eq!(host, "host.test/mock");
+        assert_eq!(
+            with_base_path("host.test".to_string(), &ServerConfig::default()),
+            "host.test"
+        );
+
+        let req: OpenRTBRequest = serde_json::from_value(serde_json::json!({
+            "id": "r1",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}]
+        }))
+        .unwrap();
+        let signature = SignatureStatus::NotPresent {
+            reason: "test".to_string(),
+        };
+        let resp = crate::auction::build_openrtb_response(&req, &host, signature);
+        let adm = resp.seatbid[0].bid[0].adm.as_deref().unwrap();
+        assert!(adm.contains("//host.test/mock/static/creatives/300x250.html"));
+    }
+
+    #[test]
+    fn strip_base_path_requires_segment_boundary() {
+        let strip = |uri: &str| {
+            strip_base_path(&uri.parse::<Uri>().unwrap(), "/mock").map(|u| u.to_string())
+        };
+        assert_eq!(strip("/mock/_/sizes?x=1").as_deref(), Some("/_/sizes?x=1"));
+        assert_eq!(strip("/mock").as_deref(), Some("/"));
+        assert!(strip("/mockery/_/sizes").is_none());
+        assert!(strip("/_/sizes").is_none());
+    }
+
+    #[test]
+    fn routes_respond_under_base_path() {
+        let app = crate::build_app();
+        let serve = |uri: &str, method: Method| {
+            let mut request = request_builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            strip_request_base_path(&mut request, "/mock");
+            block_on(app.router().oneshot(request)).status()
+        };
+        assert_eq!(serve("/mock/_/sizes", Method::GET), StatusCode::OK);
+        assert_eq!(serve("/mock/health", Method::GET), StatusCode::OK);
+        assert_eq!(serve("/_/sizes", Method::GET), StatusCode::OK);
+        // Unknown paths are 404; preflights are answered before routing
+        assert_eq!(serve("/other/_/sizes", Method::GET), StatusCode::NOT_FOUND);
+        assert_eq!(serve("/mock/nope", Method::POST), StatusCode::NOT_FOUND);
+        assert_eq!(
+            serve("/mock/_/sizes", Method::OPTIONS),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(serve("/nope", Method::OPTIONS), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn forwarded_ip_prefers_platform_header_then_first_forwarded_hop() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_ip(&headers), None);
+        headers.insert("x-real-ip", HeaderValue::from_static("10.0.0.9"));
+        assert_eq!(forwarded_ip(&headers), Some("10.0.0.9"));
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("203.0.113.7, 10.0.0.9"),
+        );
+        assert_eq!(forwarded_ip(&headers), Some("203.0.113.7"));
+        headers.insert("fastly-client-ip", HeaderValue::from_static("198.51.100.2"));
+        assert_eq!(forwarded_ip(&headers), Some("198.51.100.2"));
+        headers.insert("cf-connecting-ip", HeaderValue::from_static("192.0.2.1"));
+        assert_eq!(forwarded_ip(&headers), Some("192.0.2.1"));
+    }
+
+    #[test]
+    fn client_key_trusts_forwarded_headers_only_when_configured() {
+        let mut request = Request::new(Body::empty());
+        request
+            .headers_mut()
+            .insert("x-forwarded-for", HeaderValue::from_static("203.0.113.7"));
+        // Neither a peer address nor trust: everyone shares one bucket
+        assert_eq!(client_key(&request, false), SHARED_CLIENT);
+        assert_eq!(client_key(&request, true), "203.0.113.7");
+
+        request
+            .extensions_mut()
+            .insert(SocketAddr::from(([198, 51, 100, 4], 52000)));
+        assert_eq!(client_key(&request, false), "198.51.100.4");
+        assert_eq!(client_key(&request, true), "203.0.113.7");
+        request.headers_mut().clear();
+        assert_eq!(client_key(&request, true), "198.51.100.4");
+    }
+
+    #[test]
+    fn rate_limited_response_rounds_retry_after_up() {
+        let response = rate_limited_response(Duration::from_millis(1500));
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+        let response = rate_limited_response(Duration::ZERO);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    fn cors_request(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn cors_wildcard_without_allowed_origins() {
+        let request = cors_request(&[(header::ORIGIN, "https://pub.example")]);
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &request, &[]);
+        assert_eq!(headers.get("access-control-allow-origin").unwrap(), "*");
+        assert!(headers.get("access-control-allow-credentials").is_none());
+        assert_eq!(headers.get("access-control-max-age").unwrap(), "7200");
+    }
+
+    #[test]
+    fn cors_echoes_allowed_origin_only() {
+        let allowed = vec!["https://pub.example".to_string()];
+        let request = cors_request(&[(header::ORIGIN, "https://pub.example")]);
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &request, &allowed);
+        assert_eq!(
+            headers.get("access-control-allow-origin").unwrap(),
+            "https://pub.example"
+        );
+        assert_eq!(
+            headers.get("access-control-allow-credentials").unwrap(),
+            "true"
+        );
+        assert_eq!(headers.get(header::VARY).unwrap(), "Origin");
+
+        // A disallowed origin's preflight gets nothing echoed back
+        let request = cors_request(&[
+            (header::ORIGIN, "https://evil.example"),
+            (header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"),
+            (header::ACCESS_CONTROL_REQUEST_HEADERS, "x-custom"),
+        ]);
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &request, &allowed);
+        assert!(headers.get("access-control-allow-origin").is_none());
+        assert!(headers.get("access-control-allow-credentials").is_none());
+        assert!(headers.get("access-control-allow-methods").is_none());
+        assert!(headers.get("access-control-allow-headers").is_none());
+        assert_eq!(headers.get(header::VARY).unwrap(), "Origin");
+
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &HeaderMap::new(), &allowed);
+        assert!(headers.get("access-control-allow-origin").is_none());
+    }
+
+    #[test]
+    fn cors_preflight_reflects_requested_method_and_headers() {
+        let request = cors_request(&[
+            (header::ACCESS_CONTROL_REQUEST_METHOD, "PUT"),
+            (header::ACCESS_CONTROL_REQUEST_HEADERS, "x-custom"),
+        ]);
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &request, &[]);
+        assert_eq!(headers.get("access-control-allow-methods").unwrap(), "PUT");
+        assert_eq!(
+            headers.get("access-control-allow-headers").unwrap(),
+            "x-custom"
+        );
+
+        // Without them the static defaults apply
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &HeaderMap::new(), &[]);
+        assert_eq!(
+            headers.get("access-control-allow-methods").unwrap(),
+            "GET, POST, OPTIONS"
+        );
+        assert_eq!(
+            headers.get("access-control-allow-headers").unwrap(),
+            "content-type"
+        );
+
+        // Credentialed origins get their own request headers, never `*`
+        let allowed = vec!["https://pub.example".to_string()];
+        let request = cors_request(&[
+            (header::ORIGIN, "https://pub.example"),
+            (header::ACCESS_CONTROL_REQUEST_HEADERS, "x-custom"),
+        ]);
+        let mut headers = HeaderMap::new();
+        apply_cors(&mut headers, &request, &allowed);
+        assert_eq!(
+            headers.get("access-control-allow-headers").unwrap(),
+            "x-custom"
+        );
+    }
+
+    #[test]
+    fn access_log_passes_response_through_and_emits_record() {
+        let handler = |_ctx: RequestContext| async {
+            let mut response = build_response(StatusCode::CREATED, Body::text("made"));
+            response
+                .headers_mut()
+                .insert("x-handler", HeaderValue::from_static("yes"));
+            Ok(response)
+        };
+        let request = || ctx(Method::GET, "/pixel?pid=1", Body::empty(), &[]);
+
+        let (result, record) = block_on(run_logged(request(), handler));
+        let response = result.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["x-handler"], "yes");
+        assert_eq!(response.into_body().into_bytes().as_ref(), b"made");
+        assert_eq!(record["method"], "GET");
+        assert_eq!(record["path"], "/pixel");
+        assert_eq!(record["status"], 201);
+    }
+
+    #[test]
+    fn access_record_is_one_json_object() {
+        let id = HeaderValue::from_static("trace-1");
+        let record = access_record(
+            &Method::POST,
+            "/openrtb2/auction",
+            StatusCode::OK,
+            Duration::from_micros(12_345),
+            Some(&id),
+        );
+        assert_eq!(
+            record,
+            serde_json::json!({
+                "method": "POST",
+                "path": "/openrtb2/auction",
+                "status": 200,
+                "duration_ms": 12.3,
+                "request_id": "trace-1",
+            })
+        );
+        assert!(!record.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn server_timing_reports_app_duration_in_ms() {
+        assert_eq!(server_timing(Duration::from_micros(12_345)), "app;dur=12.3");
+        assert_eq!(server_timing(Duration::ZERO), "app;dur=0.0");
+    }
+
+    fn signed_request(ext: serde_json::Value) -> OpenRTBRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": "r-signed",
+            "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+            "site": {"domain": "publisher.test"},
+            "ext": ext,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn enforced_verification_rejects_missing_signature() {
+        let cfg = VerificationConfig {
+            enforce: true,
+            ..Default::default()
+        };
+        let req = signed_request(serde_json::json!({"trusted_server": {"kid": "k1"}}));
+        let ctx = ctx(Method::POST, "/openrtb2/auction", Body::empty(), &[]);
+        let status = block_on(request_signature_status(&ctx, &req));
+        let response = signature_rejection(&req, &status, &cfg).expect("rejected");
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = serde_json::from_slice(response.body().as_bytes()).unwrap();
+        assert_eq!(body["error"]["code"], "SIGNATURE_INVALID");
+        assert!(body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("signature"));
+
+        // Not enforced: the same request goes through
+        assert!(signature_rejection(&req, &status, &VerificationConfig::default()).is_none());
+    }
+
+    #[test]
+    fn enforced_verification_passes_unsigned_requests() {
+        let cfg = VerificationConfig {
+            enforce: true,
+            ..Default::default()
+        };
+        let req = signed_request(serde_json::json!({}));
+        let status = SignatureStatus::NotPresent {
+            reason: "unsigned".to_string(),
+        };
+        assert!(signature_rejection(&req, &status, &cfg).is_none());
+    }
 }
@@ -0,0 +1,302 @@
+//! Prebid Server style user sync: `/cookie_sync` types and the `uids` cookie.
+//!
+//! `POST /cookie_sync` answers every requested bidder with a redirect sync to
+//! our own `GET /setuid`, which records the bidder's user id in the `uids`
+//! cookie. The cookie value is URL-safe base64 of
+//! `{"uids":{"<bidder>":"<uid>"},"synced":{"<bidder>":<unix ms>}}`; once it
+//! holds [`MAX_UIDS`] bidders or [`MAX_UIDS_COOKIE_BYTES`], the bidders synced
+//! longest ago are dropped.
+//! Auctions without `user.buyeruid` pick up the uid synced for [`SYNC_BIDDER`].
+
+use std::collections::{BTreeMap, HashSet};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::openrtb::{OpenRTBRequest, User};
+
+/// Name of the cookie `/setuid` writes.
+pub const UIDS_COOKIE: &str = "uids";
+
+/// Bidder code whose synced uid the auction uses as `user.buyeruid`.
+pub const SYNC_BIDDER: &str = "mocktioneer";
+
+/// Most bidders accepted in one `/cookie_sync` request.
+pub const MAX_SYNC_BIDDERS: u64 = 50;
+
+/// `POST /cookie_sync` body.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CookieSyncRequest {
+    #[validate(length(max = MAX_SYNC_BIDDERS), custom(function = "validate_bidders"))]
+    pub bidders: Vec<String>,
+}
+
+/// Most bidders kept in the `uids` cookie.
+pub const MAX_UIDS: usize = 32;
+
+/// Largest encoded `uids` cookie value, leaving room for the attributes under
+/// the usual 4096-byte browser limit.
+pub const MAX_UIDS_COOKIE_BYTES: usize = 3800;
+
+fn is_bidder_code(bidder: &str) -> bool {
+    (1..=64).contains(&bidder.len())
+        && bidder
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn bidder_error() -> ValidationError {
+    let mut error = ValidationError::new("bidder");
+    error.message = Some("bidders must be 1-64 chars of [A-Za-z0-9_-]".into());
+    error
+}
+
+/// Bidder codes must be 1-64 characters of `[A-Za-z0-9_-]`, as they end up in
+/// the sync URL and the cookie.
+fn validate_bidders(bidders: &[String]) -> Result<(), ValidationError> {
+    if bidders.iter().all(|bidder| is_bidder_code(bidder)) {
+        return Ok(());
+    }
+    Err(bidder_error())
+}
+
+/// Single-bidder form of [`CookieSyncRequest`]'s bidder check, for `/setuid`.
+pub fn validate_bidder(bidder: &str) -> Result<(), ValidationError> {
+    if is_bidder_code(bidder) {
+        return Ok(());
+    }
+    Err(bidder_error())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CookieSyncResponse {
+    pub status: String,
+    pub bidder_status: Vec<BidderStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BidderStatus {
+    pub bidder: String,
+    pub usersync: UserSync,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserSync {
+    pub url: String,
+    /// Always `redirect`: `/setuid` answers with a pixel.
+    #[serde(rename = "type")]
+    pub sync_type: String,
+}
+
+/// Sync every requested bidder through `//{base_host}/setuid`, once per bidder.
+pub fn build_cookie_sync_response(req: &CookieSyncRequest, base_host: &str) -> CookieSyncResponse {
+    let mut seen = HashSet::new();
+    let bidder_status = req
+        .bidders
+        .iter()
+        .filter(|bidder| seen.insert(bidder.as_str()))
+        .map(|bidder| BidderStatus {
+            bidder: bidder.clone(),
+            usersync: UserSync {
+                url: format!("//{}/setuid?bidder={}", base_host, bidder),
+                sync_type: "redirect".to_string(),
+            },
+        })
+        .collect();
+    CookieSyncResponse {
+        status: "ok".to_string(),
+        bidder_status,
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UidsCookie {
+    #[serde(default)]
+    uids: BTreeMap<String, String>,
+    /// Unix ms each bidder was last synced; missing entries count as oldest.
+    #[serde(default)]
+    synced: BTreeMap<String, u64>,
+}
+
+impl UidsCookie {
+    fn decode(value: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(value).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("uids serialize"))
+    }
+
+    /// Drop the bidder synced longest ago, other than `keep`.
+    fn drop_oldest(&mut self, keep: &str) -> bool {
+        let oldest = self
+            .uids
+            .keys()
+            .filter(|bidder| bidder.as_str() != keep)
+            .min_by_key(|bidder| self.synced.get(*bidder).copied().unwrap_or(0))
+            .cloned();
+        let Some(oldest) = oldest else {
+            return false;
+        };
+        self.uids.remove(&oldest);
+        self.synced.remove(&oldest);
+        true
+    }
+}
+
+/// Bidder → uid map stored in a `uids` cookie value. Unreadable values are
+/// treated as empty, so a stale or foreign cookie is simply replaced.
+pub fn decode_uids(value: &str) -> BTreeMap<String, String> {
+    UidsCookie::decode(value)
+        .map(|cookie| cookie.uids)
+        .unwrap_or_default()
+}
+
+/// `uids` cookie value with `bidder` set to `uid` as of `now_ms`, keeping the
+/// other bidders from `existing` until the cookie goes over [`MAX_UIDS`] or
+/// [`MAX_UIDS_COOKIE_BYTES`], when the ones synced longest ago are dropped.
+pub fn set_uid(existing: Option<&str>, bidder: &str, uid: &str, now_ms: u64) -> String {
+    let mut cookie = existing.and_then(UidsCookie::decode).unwrap_or_default();
+    cookie.synced.retain(|b, _| cookie.uids.contains_key(b));
+    cookie.uids.insert(bidder.to_string(), uid.to_string());
+    cookie.synced.insert(bidder.to_string(), now_ms);
+    while cookie.uids.len() > MAX_UIDS && cookie.drop_oldest(bidder) {}
+    let mut value = cookie.encode();
+    while value.len() > MAX_UIDS_COOKIE_BYTES && cookie.drop_oldest(bidder) {
+        value = cookie.encode();
+    }
+    value
+}
+
+/// Fill a missing `user.buyeruid` with the [`SYNC_BIDDER`] uid from a `uids`
+/// cookie value. A buyeruid sent in the request wins.
+pub fn apply_synced_uid(req: &mut OpenRTBRequest, uids_cookie: Option<&str>) {
+    if req.user.as_ref().is_some_and(|u| u.buyeruid.is_some()) {
+        return;
+    }
+    let Some(uid) = uids_cookie.and_then(|value| decode_uids(value).remove(SYNC_BIDDER)) else {
+        return;
+    };
+    req.user.get_or_insert_with(User::default).buyeruid = Some(uid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cookie_sync_response_points_each_bidder_at_setuid() {
+        let req = CookieSyncRequest {
+            bidders: vec![
+                "appnexus".to_string(),
+                "rubicon".to_string(),
+                "appnexus".to_string(),
+            ],
+        };
+        let resp = build_cookie_sync_response(&req, "host.test");
+        let json = serde_json::to_value(&resp).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "status": "ok",
+                "bidder_status": [
+                    {
+                        "bidder": "appnexus",
+                        "usersync": {"url": "//host.test/setuid?bidder=appnexus", "type": "redirect"}
+                    },
+                    {
+                        "bidder": "rubicon",
+                        "usersync": {"url": "//host.test/setuid?bidder=rubicon", "type": "redirect"}
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn cookie_sync_request_rejects_bad_bidders() {
+        let req = |bidders: &[&str]| CookieSyncRequest {
+            bidders: bidders.iter().map(|b| b.to_string()).collect(),
+        };
+        assert!(req(&["appnexus", "the_trade-desk"]).validate().is_ok());
+        assert!(req(&[""]).validate().is_err());
+        assert!(req(&["a&b=c"]).validate().is_err());
+        let many: Vec<String> = (0..=MAX_SYNC_BIDDERS).map(|i| format!("b{}", i)).collect();
+        assert!(CookieSyncRequest { bidders: many }.validate().is_err());
+
+        assert!(validate_bidder("app_nexus-2").is_ok());
+        assert!(validate_bidder("a;b").is_err());
+        assert!(validate_bidder("").is_err());
+    }
+
+    #[test]
+    fn set_uid_keeps_other_bidders() {
+        let first = set_uid(None, "appnexus", "an-1", 1);
+        let second = set_uid(Some(&first), "rubicon", "rp-1", 2);
+        let uids = decode_uids(&second);
+        assert_eq!(uids.get("appnexus").map(String::as_str), Some("an-1"));
+        assert_eq!(uids.get("rubicon").map(String::as_str), Some("rp-1"));
+
+        // A garbage cookie is replaced rather than rejected
+        let uids = decode_uids(&set_uid(Some("not base64!"), "appnexus", "an-2", 3));
+        assert_eq!(uids.len(), 1);
+    }
+
+    #[test]
+    fn set_uid_drops_oldest_bidders_over_the_caps() {
+        let mut cookie = None;
+        for i in 0..=MAX_UIDS as u64 {
+            cookie = Some(set_uid(cookie.as_deref(), &format!("b{}", i), "u", i));
+        }
+        let uids = decode_uids(cookie.as_deref().unwrap());
+        assert_eq!(uids.len(), MAX_UIDS);
+        assert!(!uids.contains_key("b0"));
+        assert!(uids.contains_key("b1"));
+
+        // Re-syncing a bidder makes it the newest
+        let cookie = set_uid(cookie.as_deref(), "b1", "u2", 100);
+        let cookie = set_uid(Some(&cookie), "late", "u", 101);
+        let uids = decode_uids(&cookie);
+        assert_eq!(uids.get("b1").map(String::as_str), Some("u2"));
+        assert!(!uids.contains_key("b2"));
+
+        // Long uids hit the byte cap first; the bidder just set always stays
+        let long = "x".repeat(128);
+        let mut cookie = None;
+        for i in 0..MAX_UIDS as u64 {
+            cookie = Some(set_uid(cookie.as_deref(), &format!("b{}", i), &long, i));
+        }
+        let cookie = cookie.unwrap();
+        assert!(cookie.len() <= MAX_UIDS_COOKIE_BYTES);
+        let uids = decode_uids(&cookie);
+        assert!(uids.len() < MAX_UIDS);
+        assert!(uids.contains_key(&format!("b{}", MAX_UIDS - 1)));
+        assert!(!uids.contains_key("b0"));
+    }
+
+    #[test]
+    fn synced_uid_fills_missing_buyeruid_only() {
+        let cookie = set_uid(None, SYNC_BIDDER, "mt-1", 1);
+        let mut req = OpenRTBRequest::default();
+        apply_synced_uid(&mut req, Some(&cookie));
+        assert_eq!(req.user.unwrap().buyeruid.as_deref(), Some("mt-1"));
+
+        let mut req = OpenRTBRequest {
+            user: Some(User {
+                buyeruid: Some("from-request".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        apply_synced_uid(&mut req, Some(&cookie));
+        assert_eq!(req.user.unwrap().buyeruid.as_deref(), Some("from-request"));
+
+        // Other bidders' uids aren't ours
+        let mut req = OpenRTBRequest::default();
+        apply_synced_uid(&mut req, Some(&set_uid(None, "appnexus", "an-1", 1)));
+        assert!(req.user.is_none());
+    }
+}
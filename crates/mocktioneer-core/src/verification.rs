@@ -1,17 +1,18 @@
+use crate::config::{AppConfig, VerificationConfig};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use edgezero_core::body::Body;
 use edgezero_core::context::RequestContext;
 use edgezero_core::http::{Method, StatusCode, Uri};
 use edgezero_core::proxy::ProxyRequest;
+use futures_util::lock::Mutex as AsyncMutex;
 use futures_util::StreamExt;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::sync::{LazyLock, Mutex};
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
-const JWKS_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
-
 #[derive(Debug, Clone, Deserialize)]
 struct TrustedServerResponse {
     jwks: JwksResponse,
@@ -22,19 +23,164 @@ struct JwksResponse {
     keys: Vec<JwkKey>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 struct JwkKey {
     kid: String,
-    x: String, // Base64url-encoded Ed25519 public key
+    /// `OKP` (Ed25519) or `EC` (P-256); absent keys are treated as Ed25519.
+    #[serde(default)]
+    kty: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    alg: Option<String>,
+    x: String, // Base64url-encoded Ed25519 public key, or P-256 x coordinate
+    /// Base64url-encoded P-256 y coordinate (EC keys only).
+    #[serde(default)]
+    y: Option<String>,
 }
 
-struct JwksCache {
+/// Signature algorithms accepted for `ext.trusted_server.signature`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignatureAlg {
+    EdDsa,
+    Es256,
+}
+
+impl SignatureAlg {
+    fn name(self) -> &'static str {
+        match self {
+            Self::EdDsa => "EdDSA",
+            Self::Es256 => "ES256",
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Self, VerificationError> {
+        match name {
+            "EdDSA" => Ok(Self::EdDsa),
+            "ES256" => Ok(Self::Es256),
+            other => Err(VerificationError::InvalidSignature(format!(
+                "Unsupported signature algorithm: {}",
+                other
+            ))),
+        }
+    }
+
+    /// Algorithm for `key`, decided by the key alone: `kty`/`crv` (`OKP`/`Ed25519`
+    /// or `EC`/`P-256`), which the JWK `alg` must agree with. Keys without `kty`
+    /// predate it and are Ed25519 unless their `alg` says otherwise. A `requested`
+    /// algorithm (`ext.trusted_server.alg`) that differs is rejected rather than
+    /// used, so a signer can't pick how its key is interpreted.
+    fn for_key(key: &JwkKey, requested: Option<&str>) -> Result<Self, VerificationError> {
+        let invalid = |msg: String| Err(VerificationError::InvalidSignature(msg));
+        let from_type = match (key.kty.as_deref(), key.crv.as_deref()) {
+            (Some("OKP"), Some("Ed25519")) => Some(Self::EdDsa),
+            (Some("EC"), Some("P-256")) => Some(Self::Es256),
+            (Some(kty @ ("OKP" | "EC")), None) => {
+                return invalid(format!("{} key {} is missing crv", kty, key.kid))
+            }
+            (Some(kty @ ("OKP" | "EC")), Some(crv)) => {
+                return invalid(format!("Unsupported {} curve: {}", kty, crv))
+            }
+            (Some(kty), _) => return invalid(format!("Unsupported key type: {}", kty)),
+            (None, _) => None,
+        };
+        let from_alg = key.alg.as_deref().map(Self::from_name).transpose()?;
+        let alg = match (from_type, from_alg) {
+            (Some(t), Some(a)) if t != a => {
+                return invalid(format!(
+                    "Key {} is {} but declares alg {}",
+                    key.kid,
+                    t.name(),
+                    a.name()
+                ))
+            }
+            (Some(alg), _) | (None, Some(alg)) => alg,
+            (None, None) => Self::EdDsa,
+        };
+        if let Some(requested) = requested {
+            if Self::from_name(requested)? != alg {
+                return invalid(format!(
+                    "Signature alg {} does not match key {} ({})",
+                    requested,
+                    key.kid,
+                    alg.name()
+                ));
+            }
+        }
+        Ok(alg)
+    }
+}
+
+struct JwksCacheEntry {
     jwks: JwksResponse,
     fetched_at: Instant,
+    /// Value of [`JwksCache::tick`] when this entry was last read or written.
+    last_used: u64,
 }
 
-static JWKS_CACHE: LazyLock<Mutex<HashMap<String, JwksCache>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Per-domain JWKS cache holding at most `[verification] jwks_cache_size`
+/// domains; the least recently used one is evicted to make room.
+#[derive(Default)]
+struct JwksCache {
+    entries: HashMap<String, JwksCacheEntry>,
+    tick: u64,
+    /// One lock per domain being fetched, so concurrent misses share a fetch.
+    fetching: HashMap<String, Arc<AsyncMutex<()>>>,
+}
+
+impl JwksCache {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Cached keys for `domain`, if present and younger than `ttl`.
+    fn get(&mut self, domain: &str, ttl: Duration) -> Option<JwksResponse> {
+        let tick = self.next_tick();
+        let Some(entry) = self.entries.get_mut(domain) else {
+            log::debug!("JWKS cache empty for {} (first fetch)", domain);
+            return None;
+        };
+        let age = entry.fetched_at.elapsed();
+        if age >= ttl {
+            log::debug!("JWKS cache expired for {} (age: {:?})", domain, age);
+            return None;
+        }
+        log::debug!("JWKS cache hit for {} (age: {:?})", domain, age);
+        entry.last_used = tick;
+        Some(entry.jwks.clone())
+    }
+
+    /// Store `jwks` for `domain`, evicting least recently used domains so at
+    /// most `capacity` (at least one) remain.
+    fn insert(&mut self, domain: String, jwks: JwksResponse, capacity: usize) {
+        let tick = self.next_tick();
+        if !self.entries.contains_key(&domain) {
+            while self.entries.len() >= capacity.max(1) {
+                let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone())
+                else {
+                    break;
+                };
+                log::debug!("JWKS cache full, evicting {}", oldest);
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            domain,
+            JwksCacheEntry {
+                jwks,
+                fetched_at: Instant::now(),
+                last_used: tick,
+            },
+        );
+    }
+}
+
+static JWKS_CACHE: LazyLock<Mutex<JwksCache>> = LazyLock::new(Mutex::default);
 
 #[derive(Debug, thiserror::Error)]
 pub enum VerificationError {
@@ -50,8 +196,15 @@ pub enum VerificationError {
     NoJwksDomain,
 }
 
-async fn fetch_jwks(ctx: &RequestContext, domain: &str) -> Result<JwksResponse, VerificationError> {
-    let jwks_url = format!("https://{}/.well-known/trusted-server.json", domain);
+/// Where `domain` publishes its signing keys. Uses `https` unless
+/// `[verification] allow_insecure_jwks` is set.
+fn jwks_url(domain: &str, allow_insecure: bool) -> String {
+    let scheme = if allow_insecure { "http" } else { "https" };
+    format!("{}://{}/.well-known/trusted-server.json", scheme, domain)
+}
+
+async fn fetch_jwks(ctx: &RequestContext, domain: &str) -> Result<Vec<u8>, VerificationError> {
+    let jwks_url = jwks_url(domain, AppConfig::global().verification.allow_insecure_jwks);
 
     log::debug!("Fetching JWKS from {}", jwks_url);
 
@@ -93,8 +246,21 @@ async fn fetch_jwks(ctx: &RequestContext, domain: &str) -> Result<JwksResponse,
             collected
         }
     };
-    let response: TrustedServerResponse = serde_json::from_slice(&body_bytes)
+    Ok(body_bytes)
+}
+
+/// Parse a trusted-server JWKS document, rejecting one with more than
+/// `max_keys` keys.
+fn parse_jwks(body: &[u8], max_keys: usize) -> Result<JwksResponse, VerificationError> {
+    let response: TrustedServerResponse = serde_json::from_slice(body)
         .map_err(|e| VerificationError::HttpError(format!("JWKS parse failed: {}", e)))?;
+    let count = response.jwks.keys.len();
+    if count > max_keys {
+        return Err(VerificationError::HttpError(format!(
+            "JWKS has {} keys, more than jwks_max_keys ({})",
+            count, max_keys
+        )));
+    }
     Ok(response.jwks)
 }
 
@@ -102,56 +268,90 @@ async fn get_cached_jwks(
     ctx: &RequestContext,
     domain: &str,
 ) -> Result<JwksResponse, VerificationError> {
-    let cache_key = domain.to_string();
-
-    {
-        let cache = JWKS_CACHE
-            .lock()
-            .map_err(|_| VerificationError::HttpError("Cache lock poisoned".to_string()))?;
-
-        if let Some(cached) = cache.get(&cache_key) {
-            if cached.fetched_at.elapsed() < JWKS_CACHE_TTL {
-                log::debug!(
-                    "JWKS cache hit for {} (age: {:?})",
-                    cache_key,
-                    cached.fetched_at.elapsed()
-                );
-                return Ok(cached.jwks.clone());
-            } else {
-                log::debug!(
-                    "JWKS cache expired for {} (age: {:?})",
-                    cache_key,
-                    cached.fetched_at.elapsed()
-                );
-            }
-        } else {
-            log::debug!("JWKS cache empty for {} (first fetch)", cache_key);
+    let cfg = &AppConfig::global().verification;
+    load_jwks(&JWKS_CACHE, domain, cfg, || fetch_jwks(ctx, domain)).await
+}
+
+/// Keys for `domain` from `cache`, or fetched with `fetch` and cached when
+/// missing or older than `cfg.jwks_cache_ttl_secs`.
+async fn load_jwks<F, Fut>(
+    cache: &Mutex<JwksCache>,
+    domain: &str,
+    cfg: &VerificationConfig,
+    fetch: F,
+) -> Result<JwksResponse, VerificationError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, VerificationError>>,
+{
+    let ttl = Duration::from_secs(cfg.jwks_cache_ttl_secs);
+    let flight = {
+        let mut guard = lock_cache(cache)?;
+        if let Some(jwks) = guard.get(domain, ttl) {
+            return Ok(jwks);
+        }
+        let lock = guard
+            .fetching
+            .entry(domain.to_string())
+            .or_default()
+            .clone();
+        FetchFlight {
+            cache,
+            domain,
+            lock: Some(lock),
         }
+    };
+
+    // Single flight: one caller fetches while the others wait, then find its
+    // keys in the cache. After a failed fetch the next waiter tries again.
+    let fetch_lock = flight.lock.as_ref().expect("set until drop");
+    let _fetching = fetch_lock.lock().await;
+    if let Some(jwks) = lock_cache(cache)?.get(domain, ttl) {
+        return Ok(jwks);
     }
+    log::debug!("Fetching fresh JWKS for {}", domain);
+    let jwks = parse_jwks(&fetch().await?, cfg.jwks_max_keys)?;
+    lock_cache(cache)?.insert(domain.to_string(), jwks.clone(), cfg.jwks_cache_size);
+    Ok(jwks)
+}
 
-    log::debug!("Fetching fresh JWKS for {}", cache_key);
-    let jwks = fetch_jwks(ctx, domain).await?;
+/// A caller's share of a domain's in-flight fetch lock. Dropping it, on return
+/// or when the caller is cancelled, removes the lock from the cache once no
+/// other caller holds it.
+struct FetchFlight<'a> {
+    cache: &'a Mutex<JwksCache>,
+    domain: &'a str,
+    lock: Option<Arc<AsyncMutex<()>>>,
+}
 
-    let mut cache = JWKS_CACHE
-        .lock()
-        .map_err(|_| VerificationError::HttpError("Cache lock poisoned".to_string()))?;
-
-    cache.insert(
-        cache_key,
-        JwksCache {
-            jwks: jwks.clone(),
-            fetched_at: Instant::now(),
-        },
-    );
+impl Drop for FetchFlight<'_> {
+    fn drop(&mut self) {
+        // Release our reference first: callers only clone the lock while
+        // holding the cache, so a count of 1 there means only the map has it
+        drop(self.lock.take());
+        let Ok(mut cache) = self.cache.lock() else {
+            return;
+        };
+        if cache
+            .fetching
+            .get(self.domain)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            cache.fetching.remove(self.domain);
+        }
+    }
+}
 
-    Ok(jwks)
+fn lock_cache(cache: &Mutex<JwksCache>) -> Result<MutexGuard<'_, JwksCache>, VerificationError> {
+    cache
+        .lock()
+        .map_err(|_| VerificationError::HttpError("Cache lock poisoned".to_string()))
 }
 
-fn find_public_key<'a>(jwks: &'a JwksResponse, kid: &str) -> Result<&'a str, VerificationError> {
+fn find_public_key<'a>(jwks: &'a JwksResponse, kid: &str) -> Result<&'a JwkKey, VerificationError> {
     jwks.keys
         .iter()
         .find(|k| k.kid == kid)
-        .map(|k| k.x.as_str())
         .ok_or_else(|| VerificationError::KeyNotFound(format!("Key {} not found in JWKS", kid)))
 }
 
@@ -200,6 +400,73 @@ fn verify_ed25519_signature(
     Ok(())
 }
 
+/// Verify a JWS-style ES256 signature: `signature_b64` is the raw 64-byte `r || s`
+/// pair and the key is given by its JWK `x`/`y` coordinates.
+fn verify_es256_signature(
+    x_b64: &str,
+    y_b64: &str,
+    signature_b64: &str,
+    message: &str,
+) -> Result<(), VerificationError> {
+    let coordinate = |b64: &str, name: &str| {
+        let bytes = URL_SAFE_NO_PAD.decode(b64).map_err(|e| {
+            VerificationError::InvalidSignature(format!("Invalid {} encoding: {}", name, e))
+        })?;
+        if bytes.len() != 32 {
+            return Err(VerificationError::InvalidSignature(format!(
+                "Invalid {} length: expected 32, got {}",
+                name,
+                bytes.len()
+            )));
+        }
+        Ok(bytes)
+    };
+
+    // SEC1 uncompressed point: 0x04 || x || y
+    let mut point = vec![0x04];
+    point.extend(coordinate(x_b64, "x coordinate")?);
+    point.extend(coordinate(y_b64, "y coordinate")?);
+    let verifying_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+        .map_err(|e| VerificationError::InvalidSignature(format!("Invalid public key: {}", e)))?;
+
+    let signature_bytes = URL_SAFE_NO_PAD.decode(signature_b64).map_err(|e| {
+        VerificationError::InvalidSignature(format!("Invalid signature encoding: {}", e))
+    })?;
+
+    if signature_bytes.len() != 64 {
+        return Err(VerificationError::InvalidSignature(format!(
+            "Invalid signature length: expected 64, got {}",
+            signature_bytes.len()
+        )));
+    }
+
+    let signature = p256::ecdsa::Signature::from_slice(&signature_bytes)
+        .map_err(|e| VerificationError::InvalidSignature(format!("Invalid signature: {}", e)))?;
+
+    verifying_key
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| VerificationError::SignatureVerificationFailed)
+}
+
+/// Verify `signature_b64` over `message` with `key`, picking the algorithm via
+/// [`SignatureAlg::for_key`].
+fn verify_signature(
+    key: &JwkKey,
+    alg: Option<&str>,
+    signature_b64: &str,
+    message: &str,
+) -> Result<(), VerificationError> {
+    match SignatureAlg::for_key(key, alg)? {
+        SignatureAlg::EdDsa => verify_ed25519_signature(&key.x, signature_b64, message),
+        SignatureAlg::Es256 => {
+            let y = key.y.as_deref().ok_or_else(|| {
+                VerificationError::InvalidSignature(format!("EC key {} is missing y", key.kid))
+            })?;
+            verify_es256_signature(&key.x, y, signature_b64, message)
+        }
+    }
+}
+
 pub async fn verify_request_id_signature(
     ctx: &RequestContext,
     request_id: &str,
@@ -221,6 +488,8 @@ pub async fn verify_request_id_signature(
         VerificationError::KeyNotFound("Missing ext.trusted_server.kid".to_string())
     })?;
 
+    let alg = ext_obj.get("alg").and_then(|v| v.as_str());
+
     log::info!(
         "Signature verification requested: id={}, kid={}, domain={:?}",
         request_id,
@@ -230,7 +499,7 @@ pub async fn verify_request_id_signature(
 
     let jwks = get_cached_jwks(ctx, domain).await?;
     let public_key = find_public_key(&jwks, key_id)?;
-    verify_ed25519_signature(public_key, signature, request_id)?;
+    verify_signature(public_key, alg, signature, request_id)?;
 
     Ok(key_id.to_string())
 }
@@ -240,10 +509,15 @@ mod tests {
     use edgezero_core::http::request_builder;
     use edgezero_core::params::PathParams;
     use futures::executor::block_on;
+    use std::cell::Cell;
     use std::collections::HashMap;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
 
     use super::*;
 
+    const TTL: Duration = Duration::from_secs(600);
+
     fn create_test_context() -> RequestContext {
         let request = request_builder()
             .method(Method::POST)
@@ -338,17 +612,194 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn jwks_url_uses_https_by_default() {
+        let cfg = AppConfig::from_toml_str("").unwrap();
+        let url = jwks_url("example.com", cfg.verification.allow_insecure_jwks);
+        assert_eq!(url, "https://example.com/.well-known/trusted-server.json");
+        let uri = url.parse::<Uri>().unwrap();
+        assert_eq!(uri.scheme_str(), Some("https"));
+
+        let url = jwks_url("localhost:8080", true);
+        assert_eq!(url, "http://localhost:8080/.well-known/trusted-server.json");
+    }
+
+    fn test_jwks(kid: &str) -> JwksResponse {
+        JwksResponse {
+            keys: vec![JwkKey {
+                kid: kid.to_string(),
+                x: "x".to_string(),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn jwks_cache_never_exceeds_capacity() {
+        let mut cache = JwksCache::default();
+        for i in 0..10 {
+            cache.insert(format!("d{}.example", i), test_jwks("k"), 4);
+            assert!(cache.entries.len() <= 4);
+        }
+        assert_eq!(cache.entries.len(), 4);
+        // The most recent domains survive
+        assert!(cache.get("d9.example", TTL).is_some());
+        assert!(cache.get("d0.example", TTL).is_none());
+
+        // Re-inserting a cached domain doesn't evict anything
+        cache.insert("d9.example".to_string(), test_jwks("k2"), 4);
+        assert_eq!(cache.entries.len(), 4);
+    }
+
+    #[test]
+    fn jwks_cache_evicts_least_recently_used() {
+        let mut cache = JwksCache::default();
+        cache.insert("a".to_string(), test_jwks("a"), 2);
+        cache.insert("b".to_string(), test_jwks("b"), 2);
+        // Reading "a" makes "b" the eviction candidate
+        assert!(cache.get("a", TTL).is_some());
+        cache.insert("c".to_string(), test_jwks("c"), 2);
+        assert!(cache.get("a", TTL).is_some());
+        assert!(cache.get("b", TTL).is_none());
+        assert!(cache.get("c", TTL).is_some());
+    }
+
+    #[test]
+    fn jwks_cache_keeps_ttl() {
+        let mut cache = JwksCache::default();
+        cache.insert("a".to_string(), test_jwks("a"), 2);
+        assert!(cache.get("a", Duration::ZERO).is_none());
+        assert!(cache.get("a", TTL).is_some());
+    }
+
+    /// Trusted-server JWKS document with `count` keys.
+    fn jwks_body(count: usize) -> Vec<u8> {
+        let keys: Vec<_> = (0..count)
+            .map(|i| serde_json::json!({"kid": format!("k{}", i), "x": "AA"}))
+            .collect();
+        serde_json::to_vec(&serde_json::json!({"jwks": {"keys": keys}})).unwrap()
+    }
+
+    #[test]
+    fn load_jwks_rejects_key_sets_over_max_keys() {
+        let cache = Mutex::new(JwksCache::default());
+        let cfg = VerificationConfig {
+            jwks_max_keys: 3,
+            ..Default::default()
+        };
+        let fake_proxy = |count| move || async move { Ok(jwks_body(count)) };
+
+        let err = block_on(load_jwks(&cache, "big.example", &cfg, fake_proxy(4))).unwrap_err();
+        assert!(matches!(err, VerificationError::HttpError(_)));
+        assert!(cache.lock().unwrap().entries.is_empty());
+
+        let jwks = block_on(load_jwks(&cache, "ok.example", &cfg, fake_proxy(3))).unwrap();
+        assert_eq!(jwks.keys.len(), 3);
+        // Cached: a second load doesn't fetch again
+        let jwks = block_on(load_jwks(&cache, "ok.example", &cfg, || async {
+            Err(VerificationError::HttpError("fetched twice".to_string()))
+        }))
+        .unwrap();
+        assert_eq!(jwks.keys[0].kid, "k0");
+    }
+
+    #[test]
+    fn load_jwks_refetches_after_cache_ttl() {
+        let cache = Mutex::new(JwksCache::default());
+        let fetches = Cell::new(0);
+        let counting_proxy = || async {
+            fetches.set(fetches.get() + 1);
+            Ok(jwks_body(1))
+        };
+
+        let cfg = VerificationConfig {
+            jwks_cache_ttl_secs: 0,
+            ..Default::default()
+        };
+        block_on(load_jwks(&cache, "ttl.example", &cfg, counting_proxy)).unwrap();
+        block_on(load_jwks(&cache, "ttl.example", &cfg, counting_proxy)).unwrap();
+        assert_eq!(fetches.get(), 2);
+
+        // Under the default TTL the keys just fetched are still fresh
+        let cfg = VerificationConfig::default();
+        block_on(load_jwks(&cache, "ttl.example", &cfg, counting_proxy)).unwrap();
+        assert_eq!(fetches.get(), 2);
+    }
+
+    /// Pending once before completing, so concurrent callers interleave.
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn load_jwks_shares_one_fetch_between_concurrent_misses() {
+        let cache = Mutex::new(JwksCache::default());
+        let cfg = VerificationConfig::default();
+        let fetches = Cell::new(0);
+        let counting_proxy = || async {
+            fetches.set(fetches.get() + 1);
+            YieldOnce(false).await;
+            Ok(jwks_body(2))
+        };
+
+        let (a, b, c) = block_on(futures::future::join3(
+            load_jwks(&cache, "shared.example", &cfg, counting_proxy),
+            load_jwks(&cache, "shared.example", &cfg, counting_proxy),
+            load_jwks(&cache, "shared.example", &cfg, counting_proxy),
+        ));
+        assert_eq!(fetches.get(), 1);
+        for jwks in [a, b, c] {
+            assert_eq!(jwks.unwrap().keys.len(), 2);
+        }
+        // The in-flight lock is gone once everyone is done
+        assert!(cache.lock().unwrap().fetching.is_empty());
+
+        // Other domains still get their own fetch
+        block_on(load_jwks(&cache, "other.example", &cfg, counting_proxy)).unwrap();
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[test]
+    fn load_jwks_cancelled_mid_fetch_releases_the_lock() {
+        use futures::FutureExt;
+
+        let cache = Mutex::new(JwksCache::default());
+        let cfg = VerificationConfig::default();
+        let stalled = || futures::future::pending::<Result<Vec<u8>, VerificationError>>();
+        // Polled once, the load is stuck in its fetch and then dropped
+        let load = load_jwks(&cache, "slow.example", &cfg, stalled);
+        assert!(load.now_or_never().is_none());
+        assert!(cache.lock().unwrap().fetching.is_empty());
+
+        // A failed fetch releases it too
+        let failing = || async { Err(VerificationError::HttpError("down".to_string())) };
+        assert!(block_on(load_jwks(&cache, "slow.example", &cfg, failing)).is_err());
+        assert!(cache.lock().unwrap().fetching.is_empty());
+    }
+
     #[test]
     fn find_public_key_found() {
         let jwks = JwksResponse {
             keys: vec![JwkKey {
                 kid: "key-001".to_string(),
                 x: "test-key-base64url".to_string(),
+                ..Default::default()
             }],
         };
 
         let result = find_public_key(&jwks, "key-001");
-        assert_eq!(result.unwrap(), "test-key-base64url");
+        assert_eq!(result.unwrap().x, "test-key-base64url");
     }
 
     #[test]
@@ -380,4 +831,117 @@ mod tests {
             VerificationError::InvalidSignature(_)
         ));
     }
+
+    // ES256 example from RFC 7515, appendix A.3: the public key, signing input
+    // and signature
+    const ES256_X: &str = "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU";
+    const ES256_Y: &str = "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0";
+    const ES256_MESSAGE: &str = "eyJhbGciOiJFUzI1NiJ9.eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+    const ES256_SIGNATURE: &str =
+        "DtEhU3ljbEg8L38VWAfUAqOyKAM6-Xx-F4GawxaepmXFCgfTjDxw5djxLa8ISlSApmWQxfKTUJqPP3-Kg6NU1Q";
+
+    fn es256_test_key() -> JwkKey {
+        JwkKey {
+            kid: "es-key".to_string(),
+            kty: Some("EC".to_string()),
+            crv: Some("P-256".to_string()),
+            x: ES256_X.to_string(),
+            y: Some(ES256_Y.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verify_es256_known_good_signature() {
+        let jwk = es256_test_key();
+        assert!(verify_signature(&jwk, None, ES256_SIGNATURE, ES256_MESSAGE).is_ok());
+        assert!(verify_signature(&jwk, Some("ES256"), ES256_SIGNATURE, ES256_MESSAGE).is_ok());
+    }
+
+    #[test]
+    fn verify_es256_tampered_message_fails() {
+        let jwk = es256_test_key();
+        let tampered = ES256_MESSAGE.replacen("eyJ", "eyK", 1);
+        assert!(matches!(
+            verify_signature(&jwk, None, ES256_SIGNATURE, &tampered).unwrap_err(),
+            VerificationError::SignatureVerificationFailed
+        ));
+    }
+
+    #[test]
+    fn signature_alg_follows_jwk_type() {
+        let ec = es256_test_key();
+        let okp = JwkKey {
+            kty: Some("OKP".to_string()),
+            crv: Some("Ed25519".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            SignatureAlg::for_key(&ec, None).unwrap(),
+            SignatureAlg::Es256
+        );
+        assert_eq!(
+            SignatureAlg::for_key(&okp, None).unwrap(),
+            SignatureAlg::EdDsa
+        );
+        assert_eq!(
+            SignatureAlg::for_key(&okp, Some("EdDSA")).unwrap(),
+            SignatureAlg::EdDsa
+        );
+        // Keys without kty keep the original Ed25519 behavior
+        assert_eq!(
+            SignatureAlg::for_key(&JwkKey::default(), None).unwrap(),
+            SignatureAlg::EdDsa
+        );
+        let es_alg_only = JwkKey {
+            alg: Some("ES256".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            SignatureAlg::for_key(&es_alg_only, None).unwrap(),
+            SignatureAlg::Es256
+        );
+    }
+
+    #[test]
+    fn signature_alg_rejects_mismatches_and_unknown_keys() {
+        let ec = es256_test_key();
+        let okp = JwkKey {
+            kty: Some("OKP".to_string()),
+            crv: Some("Ed25519".to_string()),
+            ..Default::default()
+        };
+        // The request can't switch the algorithm the key implies
+        assert!(SignatureAlg::for_key(&okp, Some("ES256")).is_err());
+        assert!(SignatureAlg::for_key(&ec, Some("EdDSA")).is_err());
+        assert!(SignatureAlg::for_key(&okp, Some("RS256")).is_err());
+        // Nor can the JWK's alg contradict its kty/crv
+        let contradicting = JwkKey {
+            alg: Some("EdDSA".to_string()),
+            ..ec.clone()
+        };
+        assert!(SignatureAlg::for_key(&contradicting, None).is_err());
+        for (kty, crv) in [
+            ("RSA", None),
+            ("oct", None),
+            ("EC", None),
+            ("OKP", None),
+            ("EC", Some("P-384")),
+            ("OKP", Some("X25519")),
+        ] {
+            let key = JwkKey {
+                kty: Some(kty.to_string()),
+                crv: crv.map(str::to_string),
+                ..Default::default()
+            };
+            assert!(
+                SignatureAlg::for_key(&key, None).is_err(),
+                "{} {:?}",
+                kty,
+                crv
+            );
+        }
+        // An ES256 signature against the Ed25519 reading of the same x fails
+        assert!(verify_signature(&ec, Some("EdDSA"), ES256_SIGNATURE, ES256_MESSAGE).is_err());
+    }
 }
@@ -41,6 +41,26 @@ fn root_returns_html_with_cors() {
     );
 }
 
+#[test]
+fn openrtb_auction_get_returns_form() {
+    let app = app();
+    let response = block_on(app.router().oneshot(make_request(
+        Method::GET,
+        "/openrtb2/auction",
+        Body::empty(),
+    )));
+    assert_eq!(response.status(), StatusCode::OK);
+    let ct = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(ct.starts_with("text/html"));
+    let body = String::from_utf8(response.into_body().into_bytes().to_vec()).unwrap();
+    assert!(body.contains("<form"));
+}
+
 #[test]
 fn pixel_sets_cookie_and_is_gif() {
     let app = app();
@@ -78,6 +98,21 @@ fn pixel_sets_cookie_and_is_gif() {
     assert!(response.headers().get("set-cookie").is_none());
 }
 
+#[test]
+fn winnotice_is_gif() {
+    let app = app();
+    let response = block_on(app.router().oneshot(make_request(
+        Method::GET,
+        "/winnotice?imp=1&price=2.50",
+        Body::empty(),
+    )));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "image/gif"
+    );
+}
+
 #[test]
 fn openrtb_auction_returns_json() {
     let app = app();
@@ -102,6 +137,132 @@ fn openrtb_auction_returns_json() {
     let payload: serde_json::Value = serde_json::from_slice(response.body().as_bytes()).unwrap();
     assert_eq!(payload["id"], "r1");
     assert!(payload["seatbid"].is_array());
+    let timing = response
+        .headers()
+        .get("server-timing")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(timing.starts_with("app;dur="), "{}", timing);
+    assert_eq!(response.headers().get("timing-allow-origin").unwrap(), "*");
+}
+
+#[test]
+fn request_id_is_echoed_or_generated() {
+    let app = app();
+    let mut request = make_request(Method::GET, "/", Body::empty());
+    request
+        .headers_mut()
+        .insert("x-request-id", HeaderValue::from_static("trace-123"));
+    let response = block_on(app.router().oneshot(request));
+    assert_eq!(response.headers().get("x-request-id").unwrap(), "trace-123");
+
+    let response = block_on(
+        app.router()
+            .oneshot(make_request(Method::GET, "/", Body::empty())),
+    );
+    let generated = response
+        .headers()
+        .get("x-request-id")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let uuid = uuid::Uuid::parse_str(generated).unwrap();
+    assert_eq!(uuid.get_version_num(), 7);
+
+    // Error responses carry it too
+    let response = block_on(app.router().oneshot(make_request(
+        Method::GET,
+        "/static/img/301x251.svg",
+        Body::empty(),
+    )));
+    assert!(response.status().is_client_error());
+    assert!(response.headers().get("x-request-id").is_some());
+}
+
+#[test]
+fn openrtb_auction_unsigned_request_passes_through() {
+    let app = app();
+    let body = Body::json(&serde_json::json!({
+        "id": "r-unsigned",
+        "imp": [{"id":"1","banner":{"w":300,"h":250}}],
+        "site": {"domain": "publisher.test"}
+    }))
+    .unwrap();
+    let response = block_on(app.router().oneshot(make_request(
+        Method::POST,
+        "/openrtb2/auction",
+        body,
+    )));
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload: serde_json::Value = serde_json::from_slice(response.body().as_bytes()).unwrap();
+    assert_eq!(payload["id"], "r-unsigned");
+}
+
+#[test]
+fn adserver_mediate_picks_highest_bidder() {
+    let app = app();
+    let body = Body::json(&serde_json::json!({
+        "id": "m1",
+        "imp": [{"id": "1", "banner": {"w": 300, "h": 250}}],
+        "ext": {
+            "bidder_responses": [
+                {"bidder": "low", "bids": [{"imp_id": "1", "price": 1.5, "w": 300, "h": 250}]},
+                {"bidder": "high", "bids": [{"imp_id": "1", "price": 3.25, "w": 300, "h": 250}]}
+            ]
+        }
+    }))
+    .unwrap();
+    let response = block_on(app.router().oneshot(make_request(
+        Method::POST,
+        "/adserver/mediate",
+        body,
+    )));
+    assert_eq!(response.status(), StatusCode::OK);
+    let payload: serde_json::Value = serde_json::from_slice(response.body().as_bytes()).unwrap();
+    assert_eq!(payload["id"], "m1");
+    let seatbids = payload["seatbid"].as_array().unwrap();
+    assert_eq!(seatbids.len(), 1);
+    assert_eq!(seatbids[0]["seat"], "high");
+    assert_eq!(seatbids[0]["bid"][0]["impid"], "1");
+    assert_eq!(seatbids[0]["bid"][0]["price"], 3.25);
+}
+
+#[test]
+fn aps_bid_returns_amznbid_for_slot() {
+    let app = app();
+    let body = Body::json(&serde_json::json!({
+        "pubId": "5555",
+        "slots": [{"slotID": "header", "sizes": [[300, 250]]}]
+    }))
+    .unwrap();
+    let response = block_on(
+        app.router()
+            .oneshot(make_request(Method::POST, "/e/dtb/bid", body)),
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    let resp: serde_json::Value =
+        serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+    assert!(resp["contextual"]["slots"][0]["amznbid"].is_string());
+}
+
+#[test]
+fn aps_bid_accepts_apstag_query_string() {
+    let app = app();
+    let uri = "/e/dtb/bid?src=5555&slots=%5B%7B%22sd%22%3A%22header%22%2C%22s%22%3A%5B%22300x250%22%5D%7D%5D";
+    let response = block_on(
+        app.router()
+            .oneshot(make_request(Method::GET, uri, Body::empty())),
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+    let resp: serde_json::Value =
+        serde_json::from_slice(&response.into_body().into_bytes()).unwrap();
+    assert_eq!(resp["contextual"]["slots"][0]["slotID"], "header");
+    assert!(resp["contextual"]["slots"][0]["amznbid"].is_string());
 }
 
 #[test]
@@ -131,6 +292,43 @@ fn static_img_svg_and_nonstandard_404() {
     assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
 
+#[test]
+fn unknown_path_is_not_found_except_for_preflight() {
+    let app = app();
+    for method in [Method::GET, Method::POST] {
+        let response = block_on(app.router().oneshot(make_request(
+            method,
+            "/no/such/route",
+            Body::empty(),
+        )));
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    // The Cors middleware answers preflights before routing
+    let response = block_on(app.router().oneshot(make_request(
+        Method::OPTIONS,
+        "/no/such/route",
+        Body::empty(),
+    )));
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(response.headers().get(header::ALLOW).is_some());
+    assert_eq!(response.headers().get(header::CONTENT_LENGTH).unwrap(), "0");
+}
+
+#[test]
+fn static_video_mp4_served() {
+    let app = app();
+    let response = block_on(app.router().oneshot(make_request(
+        Method::GET,
+        "/static/video/640x360.mp4",
+        Body::empty(),
+    )));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "video/mp4"
+    );
+}
+
 #[test]
 fn static_creatives_html_ok() {
     let app = app();
@@ -227,6 +425,7 @@ fn options_includes_allow_and_cors_headers() {
         .unwrap();
     assert!(allow.contains("POST"));
     assert!(allow.contains("OPTIONS"));
+    assert_eq!(response.headers().get(header::CONTENT_LENGTH).unwrap(), "0");
     assert_eq!(
         response
             .headers()
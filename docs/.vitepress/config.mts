@@ -48,6 +48,7 @@ export default defineConfig({
           { text: 'Tracking', link: '/api/tracking' },
           { text: 'Mediation', link: '/api/mediation' },
           { text: 'APS Win Notification', link: '/api/aps-win' },
+          { text: 'Debug', link: '/api/debug' },
         ],
       },
       {